@@ -0,0 +1,30 @@
+extern crate testanything;
+
+use testanything::tap_parser::TapParser;
+use testanything::tap_pretty::{ColorChoice, TapPrettyReporter};
+
+use std::io::{self, BufRead};
+use std::process;
+
+// Feed this a TAP stream on STDIN, for example:
+//
+//     cargo run --example stream | cargo run --example pretty
+fn main() {
+    let mut parser = TapParser::new();
+    let mut reporter = TapPrettyReporter::stdout(ColorChoice::Auto).name("STDIN");
+
+    for line in io::stdin().lock().lines() {
+        let line = line.expect("Couldn't read from STDIN");
+        if let Some(event) = parser.parse_line(&line) {
+            if let Err(reason) = reporter.event(&event) {
+                eprintln!("{}", reason);
+            }
+        }
+    }
+
+    match reporter.finish() {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(reason) => eprintln!("{}", reason),
+    }
+}
@@ -15,7 +15,7 @@ fn main() {
     let failing_test = TapTestBuilder::new()
         .name("Curry Noodle")
         .passed(false)
-        .diagnostics(&vec!["Tree", "Flower"])
+        .diagnostics(&["Tree", "Flower"])
         .finalize();
 
     // Build the suite
@@ -0,0 +1,31 @@
+//! `tap diff` -- Compare two runs of the same suite
+
+use testanything::tap_diff::TapDiff;
+
+use crate::read_suite;
+
+const USAGE: &str = "Usage: tap diff [--new-failures-only] OLD NEW";
+
+/// Report how the second run differs from the first. Exits non-zero if it regressed: if anything that used to pass now fails or is gone, or the second run bailed out. With --new-failures-only, only tests which now fail count.
+pub fn run(args: &[String]) -> Result<i32, String> {
+    let (new_failures_only, args) = match args {
+        [flag, args @ ..] if flag == "--new-failures-only" => (true, args),
+        _ => (false, args),
+    };
+    let (old_path, new_path) = match args {
+        [old_path, new_path] => (old_path, new_path),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let diff = TapDiff::new(&read_suite(old_path)?, &read_suite(new_path)?);
+    for line in diff.lines() {
+        println!("{}", line);
+    }
+
+    let regressed = if new_failures_only {
+        diff.has_new_failures()
+    } else {
+        diff.has_regressions()
+    };
+    Ok(if regressed { 1 } else { 0 })
+}
@@ -0,0 +1,18 @@
+//! `tap github` -- Pass a TAP stream through, annotating failures for GitHub Actions
+
+use testanything::tap_github::TapGithubReporter;
+
+use crate::pass_through;
+
+/// Copy a TAP stream (from the named file, or STDIN by default) to STDOUT, interleaving workflow commands as failures complete. Exits non-zero if anything failed or bailed out.
+pub fn run(args: &[String]) -> Result<i32, String> {
+    let mut reporter = TapGithubReporter::new();
+    let failed = pass_through(args, "Usage: tap github [FILE]", |event| {
+        reporter.event(event)
+    })?;
+    for command in reporter.finish() {
+        println!("{}", command);
+    }
+
+    Ok(if failed { 1 } else { 0 })
+}
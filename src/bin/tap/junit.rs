@@ -0,0 +1,35 @@
+//! `tap junit` -- Convert a JUnit XML report into a TAP stream
+
+use testanything::tap_junit::from_junit;
+
+use std::fs;
+use std::io::{self, Read};
+
+use crate::print_suite;
+
+/// Print the named JUnit report (or STDIN, given "-") as TAP. Exits non-zero if the report has failures.
+pub fn run(args: &[String]) -> Result<i32, String> {
+    let path = match args {
+        [path] => path,
+        _ => return Err("Usage: tap junit FILE".to_string()),
+    };
+
+    let mut xml = String::new();
+    if path == "-" {
+        io::stdin()
+            .read_to_string(&mut xml)
+            .map_err(|reason| format!("Couldn't read STDIN: {}", reason))?;
+    } else {
+        xml = fs::read_to_string(path)
+            .map_err(|reason| format!("Couldn't read {}: {}", path, reason))?;
+    }
+
+    let suite = from_junit(&xml).map_err(|reason| format!("{}: {}", path, reason))?;
+    print_suite(&suite)?;
+
+    Ok(if suite.tests.iter().any(|test| test.is_failure()) {
+        1
+    } else {
+        0
+    })
+}
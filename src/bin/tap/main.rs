@@ -0,0 +1,126 @@
+//! `tap` -- Command-line tools for working with TAP streams
+
+extern crate testanything;
+
+mod diff;
+mod github;
+mod junit;
+mod merge;
+mod report;
+mod run;
+mod subunit;
+mod teamcity;
+
+use testanything::tap_parser::{TapEvent, TapParser};
+use testanything::tap_suite::TapSuite;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::process;
+
+const USAGE: &str = "Usage: tap <command> [options]
+
+Commands:
+  diff [--new-failures-only] OLD NEW       Show which tests changed between two runs
+  github [FILE]                            Pass TAP through, annotating failures for GitHub Actions
+  junit FILE                               Convert a JUnit XML report into TAP
+  merge [--nested] [--name NAME] FILE...   Combine several TAP streams into one
+  report [--markdown | --html] FILE        Render a readable report of a TAP stream
+  run [--jobs N] [--filter PATTERN]... [--shard K/N]
+      [--skip-filtered] MANIFEST           Run the commands in a manifest as tests
+  subunit [--decode] FILE                  Convert TAP to SubUnit v2, or back with --decode
+  teamcity [FILE]                          Pass TAP through, reporting tests to TeamCity";
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+
+    let result = match args.first().map(String::as_str) {
+        Some("diff") => diff::run(&args[1..]),
+        Some("github") => github::run(&args[1..]),
+        Some("junit") => junit::run(&args[1..]),
+        Some("merge") => merge::run(&args[1..]),
+        Some("report") => report::run(&args[1..]),
+        Some("run") => run::run(&args[1..]),
+        Some("subunit") => subunit::run(&args[1..]),
+        Some("teamcity") => teamcity::run(&args[1..]),
+        Some("help") | Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            Ok(0)
+        }
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(code) => process::exit(code),
+        Err(reason) => {
+            eprintln!("{}", reason);
+            process::exit(2);
+        }
+    }
+}
+
+/// Read a TAP stream from a file, or from STDIN if the path is "-". The suite is named after the path.
+fn read_suite(path: &str) -> Result<TapSuite, String> {
+    let mut input = String::new();
+    if path == "-" {
+        io::stdin()
+            .read_to_string(&mut input)
+            .map_err(|reason| format!("Couldn't read STDIN: {}", reason))?;
+    } else {
+        input = fs::read_to_string(path)
+            .map_err(|reason| format!("Couldn't read {}: {}", path, reason))?;
+    }
+    Ok(TapParser::new().parse_suite(path, &input))
+}
+
+/// Write a suite to STDOUT as a TAP stream, ending with a newline.
+fn print_suite(suite: &TapSuite) -> Result<(), String> {
+    suite.print(io::stdout().lock())?;
+    println!();
+    Ok(())
+}
+
+/// Copy a TAP stream (from the file named in `args`, or STDIN by default) to STDOUT line by line, following each line with whatever `translate` makes of it. Yields whether anything failed or bailed out.
+fn pass_through(
+    args: &[String],
+    usage: &str,
+    mut translate: impl FnMut(&TapEvent) -> Vec<String>,
+) -> Result<bool, String> {
+    let input: Box<dyn BufRead> = match args {
+        [] => Box::new(io::stdin().lock()),
+        [path] if path == "-" => Box::new(io::stdin().lock()),
+        [path] => {
+            Box::new(BufReader::new(File::open(path).map_err(|reason| {
+                format!("Couldn't read {}: {}", path, reason)
+            })?))
+        }
+        _ => return Err(usage.to_string()),
+    };
+
+    let mut parser = TapParser::new();
+    let mut failed = false;
+
+    let mut events = |event: TapEvent| {
+        failed |= match &event {
+            TapEvent::Test { test, .. } => test.is_failure(),
+            TapEvent::BailOut(_) => true,
+            _ => false,
+        };
+        for command in translate(&event) {
+            println!("{}", command);
+        }
+    };
+    for line in input.lines() {
+        let line = line.map_err(|reason| format!("Couldn't read TAP: {}", reason))?;
+        println!("{}", line);
+        if let Some(event) = parser.parse_line(&line) {
+            events(event);
+        }
+    }
+    if let Some(event) = parser.finish() {
+        events(event);
+    }
+
+    Ok(failed)
+}
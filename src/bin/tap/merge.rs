@@ -0,0 +1,38 @@
+//! `tap merge` -- Combine several TAP streams into one
+
+use testanything::tap_merge::{merge, MergeMode};
+
+use crate::{print_suite, read_suite};
+
+/// Merge the named files, flat by default or nested with `--nested`, and print the result. Exits non-zero if the merged suite has failures.
+pub fn run(args: &[String]) -> Result<i32, String> {
+    let mut mode = MergeMode::Flat;
+    let mut name = "Merged TAP stream".to_string();
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--nested" => mode = MergeMode::Nested,
+            "--flat" => mode = MergeMode::Flat,
+            "--name" => {
+                name = args.next().ok_or("--name needs a value")?.to_string();
+            }
+            _ => paths.push(arg.as_str()),
+        }
+    }
+
+    if paths.is_empty() {
+        return Err("Usage: tap merge [--nested] [--name NAME] FILE...".to_string());
+    }
+
+    let suites = paths
+        .iter()
+        .map(|path| read_suite(path))
+        .collect::<Result<Vec<_>, String>>()?;
+    let merged = merge(&name, &suites, mode);
+    print_suite(&merged)?;
+
+    let failed = merged.bail_out.is_some() || merged.tests.iter().any(|test| test.is_failure());
+    Ok(if failed { 1 } else { 0 })
+}
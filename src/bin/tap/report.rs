@@ -0,0 +1,40 @@
+//! `tap report` -- Render a TAP stream as a Markdown or HTML report
+
+use testanything::tap_report::{html_report, markdown_report};
+use testanything::tap_selection::TapSelection;
+use testanything::tap_summary::TapSummary;
+
+use crate::read_suite;
+
+const USAGE: &str = "Usage: tap report [--markdown | --html] [--tag TAG]... FILE";
+
+/// Print a report of the named TAP stream (or STDIN, given "-"), in Markdown unless --html is given. With --tag, only tests with one of the tags are reported on. The report is the product, so failing tests don't make this fail.
+pub fn run(args: &[String]) -> Result<i32, String> {
+    let mut html = false;
+    let mut selection = TapSelection::new();
+    let mut path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--html" => html = true,
+            "--markdown" => html = false,
+            "--tag" => selection = selection.with_tag(args.next().ok_or("--tag needs a tag")?),
+            _ if path.is_none() && (arg == "-" || !arg.starts_with('-')) => {
+                path = Some(arg.as_str())
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+
+    let summary = TapSummary::new(&selection.apply(&read_suite(path)?));
+    let lines = if html {
+        html_report(&summary)
+    } else {
+        markdown_report(&summary)
+    };
+    println!("{}", lines.join("\n"));
+
+    Ok(0)
+}
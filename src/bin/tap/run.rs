@@ -0,0 +1,45 @@
+//! `tap run` -- Run the commands in a manifest as TAP tests
+
+use testanything::tap_command::{command_harness, load_manifest};
+use testanything::tap_selection::{parse_shard, TapSelection};
+use testanything::tap_version::TapVersion;
+use testanything::tap_writer::TapWriter;
+
+const USAGE: &str =
+    "Usage: tap run [--jobs N] [--filter PATTERN]... [--shard K/N] [--skip-filtered] MANIFEST";
+
+/// Run every command in the manifest, several at a time with `--jobs`, or only those `--filter` and `--shard` select, printing TAP 14 as results come in. One at a time, the TAP a command prints comes out as it prints it. Exits non-zero if any command failed.
+pub fn run(args: &[String]) -> Result<i32, String> {
+    let mut jobs = 1;
+    let mut selection = TapSelection::new();
+    let mut manifest = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--jobs" | "-j" => {
+                jobs = args
+                    .next()
+                    .and_then(|jobs| jobs.parse::<usize>().ok())
+                    .ok_or("--jobs needs a number")?;
+            }
+            "--filter" => {
+                selection = selection.with_name(args.next().ok_or("--filter needs a pattern")?);
+            }
+            "--shard" => {
+                let (index, count) = parse_shard(args.next().ok_or("--shard needs a shard")?)?;
+                selection = selection.with_shard(index, count);
+            }
+            "--skip-filtered" => selection = selection.with_skip_deselected(),
+            _ if manifest.is_none() && !arg.starts_with('-') => manifest = Some(arg.as_str()),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let manifest = manifest.ok_or(USAGE)?;
+
+    let mut harness = command_harness(manifest, load_manifest(manifest)?);
+    harness.jobs(jobs).live_subtests().select(selection);
+    let suite = harness.run_with(&TapWriter::with_version(manifest, TapVersion::Tap14));
+    let failed = suite.bail_out.is_some() || suite.tests.iter().any(|test| test.is_failure());
+    Ok(if failed { 1 } else { 0 })
+}
@@ -0,0 +1,56 @@
+//! `tap subunit` -- Convert a TAP stream to SubUnit v2, or back again
+
+use testanything::tap_subunit::{from_subunit, to_subunit};
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{print_suite, read_suite};
+
+const USAGE: &str = "Usage: tap subunit [--decode] FILE";
+
+/// Write the named TAP stream (or STDIN, given "-") to STDOUT as SubUnit v2. With --decode, read SubUnit v2 and write TAP instead, exiting non-zero if anything failed.
+pub fn run(args: &[String]) -> Result<i32, String> {
+    match args {
+        [flag, path] if flag == "--decode" => decode(path),
+        [path] if !path.starts_with("--") => encode(path),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn encode(path: &str) -> Result<i32, String> {
+    let suite = read_suite(path)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|reason| reason.to_string())?;
+
+    let bytes = to_subunit(&suite, now)?;
+    io::stdout()
+        .lock()
+        .write_all(&bytes)
+        .map_err(|reason| format!("Couldn't write SubUnit: {}", reason))?;
+    Ok(0)
+}
+
+fn decode(path: &str) -> Result<i32, String> {
+    let mut bytes = Vec::new();
+    if path == "-" {
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(|reason| format!("Couldn't read STDIN: {}", reason))?;
+    } else {
+        bytes = fs::read(path).map_err(|reason| format!("Couldn't read {}: {}", path, reason))?;
+    }
+
+    let suite = from_subunit(path, &bytes).map_err(|reason| format!("{}: {}", path, reason))?;
+    print_suite(&suite)?;
+
+    Ok(
+        if suite.bail_out.is_some() || suite.tests.iter().any(|test| test.is_failure()) {
+            1
+        } else {
+            0
+        },
+    )
+}
@@ -0,0 +1,22 @@
+//! `tap teamcity` -- Pass a TAP stream through, reporting its tests to TeamCity
+
+use testanything::tap_teamcity::TapTeamcityReporter;
+
+use crate::pass_through;
+
+/// Copy a TAP stream (from the named file, or STDIN by default) to STDOUT, interleaving service messages as tests complete. A file's tests are reported as a suite named after it. Exits non-zero if anything failed or bailed out.
+pub fn run(args: &[String]) -> Result<i32, String> {
+    let name = match args {
+        [path] if path != "-" => path.as_str(),
+        _ => "",
+    };
+    let mut reporter = TapTeamcityReporter::new(name);
+    let failed = pass_through(args, "Usage: tap teamcity [FILE]", |event| {
+        reporter.event(event)
+    })?;
+    for message in reporter.finish() {
+        println!("{}", message);
+    }
+
+    Ok(if failed { 1 } else { 0 })
+}
@@ -79,9 +79,57 @@ const OK_SYMBOL: &str = "ok";
 /// Global constant for the "not ok"
 const NOT_OK_SYMBOL: &str = "not ok";
 
+#[cfg(feature = "std")]
+pub mod tap_bench;
+#[cfg(feature = "std")]
+pub mod tap_capture;
+#[cfg(feature = "std")]
+pub mod tap_command;
+pub mod tap_comparison;
+pub mod tap_diff;
+pub mod tap_directive;
+#[cfg(feature = "std")]
+pub mod tap_fixture;
+pub mod tap_github;
+#[cfg(feature = "std")]
+pub mod tap_harness;
+pub mod tap_junit;
+pub mod tap_location;
+pub mod tap_merge;
+#[cfg(feature = "std")]
+pub mod tap_panic;
+pub mod tap_parser;
+#[cfg(feature = "std")]
+pub mod tap_pretty;
+pub mod tap_property;
+pub mod tap_regex;
+pub mod tap_report;
+pub mod tap_selection;
+#[cfg(feature = "std")]
+pub mod tap_snapshot;
+pub mod tap_subunit;
 pub mod tap_suite;
 pub mod tap_suite_builder;
+pub mod tap_summary;
+pub mod tap_teamcity;
 pub mod tap_test;
 pub mod tap_test_builder;
+pub mod tap_version;
 #[cfg(feature = "std")]
 pub mod tap_writer;
+pub mod tap_yaml;
+
+/// Is the environment variable `var` switched on? Anything but unset, "" or "0" counts.
+#[cfg(feature = "std")]
+pub(crate) fn env_flag(var: &str) -> bool {
+    std::env::var(var).is_ok_and(|value| !value.is_empty() && value != "0")
+}
+
+/// A path in the temporary directory for the test called `test` to keep files at, with whatever an earlier run left there cleared away
+#[cfg(test)]
+pub(crate) fn temp_path(test: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("tap-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    let _ = std::fs::remove_file(&path);
+    path
+}
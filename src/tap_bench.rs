@@ -0,0 +1,495 @@
+//! `TapBenchmarks` -- Micro-benchmarks checked against a stored baseline, and reported as TAP
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::hint::black_box;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::env_flag;
+use crate::tap_location::TapLocation;
+use crate::tap_test::TapTest;
+use crate::tap_test_builder::TapTestBuilder;
+use crate::tap_writer::TapWriter;
+use crate::tap_yaml::YamlValue;
+
+/// Switch this environment variable on, with `TAP_UPDATE_BASELINE=1`, to store each benchmark's median as its new baseline. Benchmarks always pass while doing so.
+pub const UPDATE_BASELINE_VAR: &str = "TAP_UPDATE_BASELINE";
+
+/// Statistics over the samples of one benchmark. Times are per iteration, in nanoseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchStats {
+    /// How many samples were taken
+    pub samples: usize,
+    /// How many times the benchmark ran in each sample
+    pub iterations: u64,
+    /// The mean time
+    pub mean_ns: f64,
+    /// The median time, which is what gets compared with the baseline
+    pub median_ns: f64,
+    /// The sample standard deviation
+    pub stddev_ns: f64,
+    /// The fastest sample
+    pub min_ns: f64,
+    /// The slowest sample
+    pub max_ns: f64,
+    /// How many samples lay more than 1.5 interquartile ranges outside the middle half
+    pub outliers: usize,
+}
+
+impl BenchStats {
+    /// Work out the statistics of some per-iteration times. There must be at least one.
+    pub fn from_samples(iterations: u64, samples: &[f64]) -> BenchStats {
+        assert!(
+            !samples.is_empty(),
+            "No samples to work out statistics from"
+        );
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+
+        let count = sorted.len() as f64;
+        let mean_ns = sorted.iter().sum::<f64>() / count;
+        let variance = if sorted.len() > 1 {
+            sorted.iter().map(|x| (x - mean_ns).powi(2)).sum::<f64>() / (count - 1.0)
+        } else {
+            0.0
+        };
+
+        let q1 = percentile(&sorted, 0.25);
+        let q3 = percentile(&sorted, 0.75);
+        let fence = 1.5 * (q3 - q1);
+        let outliers = sorted
+            .iter()
+            .filter(|x| **x < q1 - fence || **x > q3 + fence)
+            .count();
+
+        BenchStats {
+            samples: sorted.len(),
+            iterations,
+            mean_ns,
+            median_ns: percentile(&sorted, 0.5),
+            stddev_ns: variance.sqrt(),
+            min_ns: sorted[0],
+            max_ns: sorted[sorted.len() - 1],
+            outliers,
+        }
+    }
+
+    /// The statistics as they go into a YAML block
+    pub fn to_yaml(&self) -> YamlValue {
+        let mut fields = vec![
+            (
+                "samples".to_string(),
+                YamlValue::scalar(self.samples.to_string()),
+            ),
+            (
+                "iterations".to_string(),
+                YamlValue::scalar(self.iterations.to_string()),
+            ),
+        ];
+        for (key, value) in [
+            ("mean_ns", self.mean_ns),
+            ("median_ns", self.median_ns),
+            ("stddev_ns", self.stddev_ns),
+            ("min_ns", self.min_ns),
+            ("max_ns", self.max_ns),
+        ] {
+            fields.push((key.to_string(), YamlValue::scalar(format!("{:.1}", value))));
+        }
+        fields.push((
+            "outliers".to_string(),
+            YamlValue::scalar(self.outliers.to_string()),
+        ));
+        YamlValue::Map(fields)
+    }
+}
+
+/// A set of benchmarks and the baseline they're held to, stored as a file of names and median times.
+///
+/// Each benchmark becomes a test, with its statistics in the YAML block under `benchmark`. The test fails when the median time is slower than the baseline by more than the threshold. Benchmarks with no baseline pass, with a note. In update mode, the baseline is rewritten with each result instead.
+///
+/// A baseline file which can't be read, or has a line which isn't a name, a tab and a positive median, fails every benchmark, unless it's being rewritten.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use testanything::tap_bench::TapBenchmarks;
+/// use testanything::tap_writer::TapWriter;
+///
+/// # let baseline = std::env::temp_dir().join(format!("tap-bench-doc-{}", std::process::id()));
+/// let mut benchmarks = TapBenchmarks::new(&baseline)
+///     .with_update(false)
+///     .with_samples(5)
+///     .with_sample_time(Duration::from_micros(100));
+/// let writer = TapWriter::new("Benchmarks");
+///
+/// writer.plan(1, 1);
+/// let tap_test = benchmarks.assert(&writer, 1, "Sum to 1000", || (0..1000u64).sum::<u64>());
+///
+/// assert!(tap_test.passed);
+/// assert!(tap_test.yaml_value("benchmark").is_some());
+/// ```
+#[derive(Debug, Clone)]
+pub struct TapBenchmarks {
+    path: PathBuf,
+    baseline: BTreeMap<String, f64>,
+    /// What's wrong with the stored baseline, if anything
+    problem: Option<String>,
+    update: bool,
+    threshold: f64,
+    samples: usize,
+    sample_time: Duration,
+}
+
+impl TapBenchmarks {
+    /// Use the baseline stored at `path`, if there is one, in update mode if the `TAP_UPDATE_BASELINE` environment variable says so. Benchmarks take 30 samples of at least a millisecond each, and may be up to 10% slower than the baseline.
+    pub fn new<P: AsRef<Path>>(path: P) -> TapBenchmarks {
+        let path = path.as_ref();
+        let (baseline, problem) = match fs::read_to_string(path) {
+            Ok(text) => {
+                let (baseline, problem) = parse_baseline(&text);
+                let problem = problem.map(|problem| {
+                    format!("The baseline in {} is corrupt: {}", path.display(), problem)
+                });
+                (baseline, problem)
+            }
+            Err(reason) if reason.kind() == io::ErrorKind::NotFound => (BTreeMap::new(), None),
+            Err(reason) => {
+                let problem = format!(
+                    "Couldn't read the baseline in {}: {}",
+                    path.display(),
+                    reason
+                );
+                (BTreeMap::new(), Some(problem))
+            }
+        };
+        TapBenchmarks {
+            path: path.to_path_buf(),
+            baseline,
+            problem,
+            update: env_flag(UPDATE_BASELINE_VAR),
+            threshold: 10.0,
+            samples: 30,
+            sample_time: Duration::from_millis(1),
+        }
+    }
+
+    /// Record new baselines rather than check against the stored ones, or the other way round, overriding `TAP_UPDATE_BASELINE`
+    pub fn with_update(mut self, update: bool) -> TapBenchmarks {
+        self.update = update;
+        self
+    }
+
+    /// How much slower than the baseline, in percent, a benchmark may get before it fails
+    pub fn with_threshold(mut self, percent: f64) -> TapBenchmarks {
+        self.threshold = percent;
+        self
+    }
+
+    /// How many samples to take of each benchmark
+    pub fn with_samples(mut self, samples: usize) -> TapBenchmarks {
+        self.samples = samples.max(1);
+        self
+    }
+
+    /// How long each sample should take at least. The benchmark runs as many times per sample as that needs.
+    pub fn with_sample_time(mut self, sample_time: Duration) -> TapBenchmarks {
+        self.sample_time = sample_time;
+        self
+    }
+
+    /// The baseline median time of a benchmark, in nanoseconds, if there is one
+    pub fn baseline(&self, name: &str) -> Option<f64> {
+        self.baseline.get(&baseline_name(name)).copied()
+    }
+
+    /// Time `routine` and produce a test called `name` located where this was called from.
+    #[track_caller]
+    pub fn run<F, R>(&mut self, name: &str, mut routine: F) -> TapTest
+    where
+        F: FnMut() -> R,
+    {
+        let mut builder = TapTestBuilder::new();
+        builder.name(name).location(TapLocation::caller());
+
+        let started = Instant::now();
+        let stats = self.measure(&mut routine);
+        builder
+            .duration(started.elapsed())
+            .yaml_field("benchmark", stats.to_yaml());
+
+        let median = format_ns(stats.median_ns);
+        if self.update && stats.median_ns <= 0.0 {
+            let diagnostic = format!("Can't record a median of {} as a baseline", median);
+            return builder.passed(false).diagnostics(&[&diagnostic]).finalize();
+        }
+        if self.update {
+            self.baseline.insert(baseline_name(name), stats.median_ns);
+            let (passed, diagnostic) = match self.store() {
+                Ok(()) => (
+                    true,
+                    format!("Recorded a median of {} in {}", median, self.path.display()),
+                ),
+                Err(reason) => (
+                    false,
+                    format!("Couldn't write {}: {}", self.path.display(), reason),
+                ),
+            };
+            return builder
+                .passed(passed)
+                .diagnostics(&[&diagnostic])
+                .finalize();
+        }
+
+        if let Some(problem) = &self.problem {
+            return builder.passed(false).diagnostics(&[problem]).finalize();
+        }
+        let Some(baseline_ns) = self.baseline(name) else {
+            let diagnostic = format!(
+                "Median {}; no baseline to compare with, set {}=1 to record one",
+                median, UPDATE_BASELINE_VAR
+            );
+            return builder.passed(true).diagnostics(&[&diagnostic]).finalize();
+        };
+
+        let change = (stats.median_ns - baseline_ns) / baseline_ns * 100.0;
+        builder.yaml_field(
+            "baseline",
+            YamlValue::Map(vec![
+                (
+                    "median_ns".to_string(),
+                    YamlValue::scalar(format!("{:.1}", baseline_ns)),
+                ),
+                (
+                    "change_percent".to_string(),
+                    YamlValue::scalar(format!("{:.1}", change)),
+                ),
+                (
+                    "threshold_percent".to_string(),
+                    YamlValue::scalar(format!("{:.1}", self.threshold)),
+                ),
+            ]),
+        );
+
+        if change > self.threshold {
+            let diagnostic = format!(
+                "Median {} is {:.1}% slower than the baseline {}, past the {:.1}% threshold",
+                median,
+                change,
+                format_ns(baseline_ns),
+                self.threshold
+            );
+            builder.passed(false).diagnostics(&[&diagnostic]).finalize()
+        } else {
+            builder.passed(true).finalize()
+        }
+    }
+
+    /// Time `routine` as `run` does, and emit the test through `writer` as test number `test_number` before returning it.
+    #[track_caller]
+    pub fn assert<F, R>(
+        &mut self,
+        writer: &TapWriter,
+        test_number: i32,
+        name: &str,
+        routine: F,
+    ) -> TapTest
+    where
+        F: FnMut() -> R,
+    {
+        let tap_test = self.run(name, routine);
+        writer.test(test_number, &tap_test);
+        tap_test
+    }
+
+    fn measure<F, R>(&self, routine: &mut F) -> BenchStats
+    where
+        F: FnMut() -> R,
+    {
+        let time = |routine: &mut F, iterations: u64| {
+            let started = Instant::now();
+            for _ in 0..iterations {
+                black_box(routine());
+            }
+            started.elapsed()
+        };
+
+        // Warm up, doubling the iterations until a sample takes long enough to time
+        let mut iterations = 1;
+        while time(routine, iterations) < self.sample_time && iterations < 1 << 30 {
+            iterations *= 2;
+        }
+
+        let samples = (0..self.samples)
+            .map(|_| time(routine, iterations).as_nanos() as f64 / iterations as f64)
+            .collect::<Vec<f64>>();
+        BenchStats::from_samples(iterations, &samples)
+    }
+
+    fn store(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = self
+            .baseline
+            .iter()
+            .map(|(name, median_ns)| format!("{}\t{}\n", name, median_ns))
+            .collect::<String>();
+        fs::write(&self.path, text)
+    }
+}
+
+/// The value a fraction of the way through sorted samples, interpolating between neighbors
+fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+    let position = fraction * (sorted.len() - 1) as f64;
+    let below = position.floor() as usize;
+    let above = position.ceil() as usize;
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f64)
+}
+
+/// Names are stored one per line, before a tab
+fn baseline_name(name: &str) -> String {
+    name.replace(['\t', '\n', '\r'], " ")
+}
+
+/// The baselines in a stored file, and what's wrong with the first line which isn't one, if any is
+fn parse_baseline(text: &str) -> (BTreeMap<String, f64>, Option<String>) {
+    let mut baseline = BTreeMap::new();
+    let mut problem = None;
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let parsed = line
+            .rsplit_once('\t')
+            .and_then(|(name, median_ns)| Some((name, median_ns.trim().parse::<f64>().ok()?)));
+        match parsed {
+            Some((name, median_ns)) if median_ns.is_finite() && median_ns > 0.0 => {
+                baseline.insert(name.to_string(), median_ns);
+            }
+            _ => {
+                problem.get_or_insert_with(|| {
+                    format!("line {} isn't a name, a tab and a positive median", i + 1)
+                });
+            }
+        }
+    }
+    (baseline, problem)
+}
+
+/// Like "812 ns", "12.34 µs" or "1.50 ms"
+fn format_ns(ns: f64) -> String {
+    if ns < 1_000.0 {
+        format!("{:.0} ns", ns)
+    } else if ns < 1_000_000.0 {
+        format!("{:.2} µs", ns / 1_000.0)
+    } else if ns < 1_000_000_000.0 {
+        format!("{:.2} ms", ns / 1_000_000.0)
+    } else {
+        format!("{:.2} s", ns / 1_000_000_000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_ns, BenchStats, TapBenchmarks};
+
+    use crate::temp_path;
+
+    use std::fs;
+    use std::time::Duration;
+
+    fn quick(benchmarks: TapBenchmarks) -> TapBenchmarks {
+        benchmarks
+            .with_samples(5)
+            .with_sample_time(Duration::from_micros(50))
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = BenchStats::from_samples(10, &[4.0, 2.0, 3.0, 5.0, 1.0, 100.0]);
+
+        assert_eq!(stats.samples, 6);
+        assert_eq!(stats.median_ns, 3.5);
+        assert_eq!(stats.mean_ns, 115.0 / 6.0);
+        assert_eq!(stats.min_ns, 1.0);
+        assert_eq!(stats.max_ns, 100.0);
+        assert_eq!(stats.outliers, 1);
+        assert!((stats.stddev_ns - 39.63).abs() < 0.01);
+
+        assert_eq!(format_ns(812.4), "812 ns");
+        assert_eq!(format_ns(1_500_000.0), "1.50 ms");
+    }
+
+    #[test]
+    fn test_baseline_recorded_and_checked() {
+        let path = temp_path("bench-recorded");
+        let mut recording = quick(TapBenchmarks::new(&path).with_update(true));
+
+        let recorded = recording.run("Sum", || (0..100u64).sum::<u64>());
+        assert!(recorded.passed);
+        assert!(recorded.diagnostics[0].starts_with("Recorded a median of"));
+        assert_eq!(recorded.location.unwrap().file, file!());
+
+        let checking = quick(TapBenchmarks::new(&path).with_update(false));
+        assert_eq!(checking.baseline("Sum"), recording.baseline("Sum"));
+        assert!(checking.baseline("Sum").unwrap() > 0.0);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_regression_past_threshold() {
+        let path = temp_path("bench-regression");
+        fs::write(&path, "Slow\t0.001\nFast\t1000000000.0\n").unwrap();
+        let mut benchmarks = quick(TapBenchmarks::new(&path).with_update(false));
+
+        let slow = benchmarks.run("Slow", || (0..100u64).sum::<u64>());
+        assert!(!slow.passed);
+        assert!(slow.diagnostics[0].contains("past the 10.0% threshold"));
+        assert!(slow.yaml_value("baseline").is_some());
+
+        let fast = benchmarks.run("Fast", || (0..100u64).sum::<u64>());
+        assert!(fast.passed);
+        assert!(fast.diagnostics.is_empty());
+
+        let unknown = benchmarks.run("Unknown", || ());
+        assert!(unknown.passed);
+        assert!(unknown.diagnostics[0].contains("no baseline"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_baseline() {
+        let path = temp_path("bench-corrupt");
+        fs::write(&path, "Sum\t1000000000.0\nZero\t0\n").unwrap();
+        let mut benchmarks = quick(TapBenchmarks::new(&path).with_update(false));
+
+        let sum = benchmarks.run("Sum", || (0..100u64).sum::<u64>());
+        assert!(!sum.passed);
+        assert_eq!(
+            sum.diagnostics,
+            vec![format!(
+                "The baseline in {} is corrupt: line 2 isn't a name, a tab and a positive median",
+                path.display()
+            )]
+        );
+
+        // Rewriting it keeps the lines which were fine
+        let mut updating = quick(TapBenchmarks::new(&path).with_update(true));
+        assert!(updating.run("Zero", || (0..100u64).sum::<u64>()).passed);
+        let rewritten = quick(TapBenchmarks::new(&path).with_update(false));
+        assert_eq!(rewritten.baseline("Sum"), Some(1_000_000_000.0));
+        assert!(rewritten.baseline("Zero").unwrap() > 0.0);
+
+        fs::remove_file(&path).unwrap();
+        fs::create_dir_all(&path).unwrap();
+        let mut unreadable = quick(TapBenchmarks::new(&path).with_update(false));
+        let sum = unreadable.run("Sum", || (0..100u64).sum::<u64>());
+        assert!(sum.diagnostics[0].starts_with("Couldn't read the baseline in"));
+        fs::remove_dir(&path).unwrap();
+    }
+}
@@ -0,0 +1,264 @@
+//! `CapturedOutput` -- Keep what a test prints out of the TAP stream by running the test in a process of its own
+
+use std::env;
+use std::io::{self, Read, Write};
+use std::process::{self, Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::tap_parser::TapParser;
+use crate::tap_test::TapTest;
+use crate::tap_test_builder::TapTestBuilder;
+use crate::tap_version::TapVersion;
+use crate::tap_yaml::YamlValue;
+
+/// Set in the environment of the process a captured test runs in, to "INDEX:HARNESS NAME". The harness of that name runs only the test at that index, then exits.
+pub const CAPTURE_TEST_VAR: &str = "TAP_CAPTURE_TEST";
+
+/// Marks where the test starts, so whatever the program printed before getting to it is left out
+const START_MARKER: &str = "\u{1}TAP CAPTURE START\u{1}\n";
+/// Marks where the test ends, and its result begins
+const END_MARKER: &str = "\u{1}TAP CAPTURE END\u{1}\n";
+/// The YAML key the child reports diagnostics under, since a diagnostic line can't hold a diagnostic with newlines in it
+const DIAGNOSTICS_KEY: &str = "captured_diagnostics";
+
+/// What a test printed while it ran
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CapturedOutput {
+    /// Everything written to standard output
+    pub stdout: String,
+    /// Everything written to standard error
+    pub stderr: String,
+}
+
+impl CapturedOutput {
+    /// Did the test print nothing at all?
+    pub fn is_empty(&self) -> bool {
+        self.stdout.is_empty() && self.stderr.is_empty()
+    }
+
+    /// The output as it goes into a YAML block under `output`, leaving out streams with nothing in them
+    pub fn to_yaml(&self) -> YamlValue {
+        let mut fields = Vec::new();
+        if !self.stdout.is_empty() {
+            fields.push((
+                "stdout".to_string(),
+                YamlValue::scalar(self.stdout.as_str()),
+            ));
+        }
+        if !self.stderr.is_empty() {
+            fields.push((
+                "stderr".to_string(),
+                YamlValue::scalar(self.stderr.as_str()),
+            ));
+        }
+        YamlValue::Map(fields)
+    }
+}
+
+/// If this process was started to run one test of the harness called `harness_name`, the index of that test
+pub(crate) fn child_test(harness_name: &str) -> Option<usize> {
+    let value = env::var(CAPTURE_TEST_VAR).ok()?;
+    let (index, name) = value.split_once(':')?;
+    if name == harness_name {
+        index.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Run a test as the child process, reporting its result to the parent, and exit
+pub(crate) fn run_in_child<F: FnOnce() -> TapTest>(test: F) -> ! {
+    print!("{}", START_MARKER);
+    eprint!("{}", START_MARKER);
+    let _ = io::stdout().flush();
+
+    let mut tap_test = test();
+
+    // Reported in full, as TAP, so nothing about the test is lost on the way to the parent
+    let diagnostics = tap_test.diagnostics.drain(..).map(YamlValue::Scalar);
+    let diagnostics = YamlValue::List(diagnostics.collect());
+    tap_test
+        .yaml
+        .push((DIAGNOSTICS_KEY.to_string(), diagnostics));
+    let mut report = String::from(END_MARKER);
+    for line in tap_test.tap_with_version(1, TapVersion::Tap14) {
+        report.push_str(&line);
+        report.push('\n');
+    }
+    // A newline first, in case the test left a line unfinished
+    print!("\n{}", report);
+    eprint!("\n{}", END_MARKER);
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+    process::exit(0)
+}
+
+/// Run the test at `index` of the harness called `harness_name` in a process of its own, started from this program with `args`. Gives back the test as the child reported it, or a failing test saying why it couldn't, and what it printed. The process is killed if it's still going at `deadline`, and then there's nothing to give back.
+pub(crate) fn run_in_parent(
+    harness_name: &str,
+    index: usize,
+    args: &[String],
+    deadline: Option<Instant>,
+) -> Option<(TapTest, CapturedOutput)> {
+    let child = env::current_exe().and_then(|program| {
+        Command::new(program)
+            .args(args)
+            .env(CAPTURE_TEST_VAR, format!("{}:{}", index, harness_name))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+    });
+    let mut child = match child {
+        Ok(child) => child,
+        Err(reason) => {
+            let diagnostic = format!("Couldn't start a process for the test: {}", reason);
+            return Some((failure(&diagnostic), CapturedOutput::default()));
+        }
+    };
+    // Drain both pipes as we go, or a chatty test would fill one and stall
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let status = match wait_until(&mut child, deadline) {
+        Ok(Some(status)) => status,
+        Ok(None) => return None,
+        Err(reason) => {
+            let diagnostic = format!("Couldn't wait for the test's process: {}", reason);
+            return Some((failure(&diagnostic), CapturedOutput::default()));
+        }
+    };
+
+    let stdout = String::from_utf8_lossy(&receive(&stdout, deadline)).into_owned();
+    let stderr = String::from_utf8_lossy(&receive(&stderr, deadline)).into_owned();
+    let (stdout, report) = split_output(&stdout);
+    let (stderr, _) = split_output(&stderr);
+    let captured = CapturedOutput {
+        stdout: stdout.to_string(),
+        stderr: stderr.to_string(),
+    };
+
+    let reported = report.and_then(|report| TapParser::new().parse_suite("", report).tests.pop());
+    let Some(mut tap_test) = reported else {
+        let diagnostic = format!(
+            "The test's process exited ({}) before the test finished",
+            status
+        );
+        return Some((failure(&diagnostic), captured));
+    };
+    if let Some(at) = tap_test
+        .yaml
+        .iter()
+        .position(|(key, _)| key == DIAGNOSTICS_KEY)
+    {
+        if let (_, YamlValue::List(diagnostics)) = tap_test.yaml.remove(at) {
+            tap_test.diagnostics = diagnostics
+                .iter()
+                .filter_map(|diagnostic| diagnostic.as_str().map(ToString::to_string))
+                .collect();
+        }
+    }
+    Some((tap_test, captured))
+}
+
+/// Wait for `child` to exit, killing it if it's still going at `deadline`, when there's no status to give back
+pub(crate) fn wait_until(
+    child: &mut Child,
+    deadline: Option<Instant>,
+) -> io::Result<Option<ExitStatus>> {
+    loop {
+        match deadline {
+            None => return child.wait().map(Some),
+            Some(deadline) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(None);
+            }
+            Some(_) => match child.try_wait()? {
+                Some(status) => return Ok(Some(status)),
+                None => thread::sleep(Duration::from_millis(10)),
+            },
+        }
+    }
+}
+
+/// Read all of a pipe on a thread of its own, handing the bytes over once it's closed
+pub(crate) fn read_pipe<P: Read + Send + 'static>(pipe: Option<P>) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        let _ = sender.send(bytes);
+    });
+    receiver
+}
+
+/// What was read from a pipe, giving up at `deadline`, since anything the process started may still hold it open
+pub(crate) fn receive(pipe: &Receiver<Vec<u8>>, deadline: Option<Instant>) -> Vec<u8> {
+    let bytes = match deadline {
+        None => pipe.recv().ok(),
+        Some(deadline) => pipe
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .ok(),
+    };
+    bytes.unwrap_or_default()
+}
+
+fn failure(diagnostic: &str) -> TapTest {
+    TapTestBuilder::new()
+        .passed(false)
+        .diagnostics(&[diagnostic])
+        .finalize()
+}
+
+/// Split what a child process printed into what the test printed, and the report after it, if the test got as far as one
+fn split_output(text: &str) -> (&str, Option<&str>) {
+    let text = match text.find(START_MARKER) {
+        Some(start) => &text[start + START_MARKER.len()..],
+        None => text,
+    };
+    match text.rfind(END_MARKER) {
+        // Drop the newline put before the marker
+        Some(end) => (
+            text[..end].strip_suffix('\n').unwrap_or(&text[..end]),
+            Some(&text[end + END_MARKER.len()..]),
+        ),
+        None => (text, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_output, CapturedOutput, END_MARKER, START_MARKER};
+    use crate::tap_yaml::YamlValue;
+
+    #[test]
+    fn test_split_output() {
+        let text = format!(
+            "running 1 test\n{}Hello\n\n{}not ok\n# Nope\n",
+            START_MARKER, END_MARKER
+        );
+        assert_eq!(split_output(&text), ("Hello\n", Some("not ok\n# Nope\n")));
+
+        let unfinished = format!("{}Hello", START_MARKER);
+        assert_eq!(split_output(&unfinished), ("Hello", None));
+    }
+
+    #[test]
+    fn test_to_yaml() {
+        let output = CapturedOutput {
+            stdout: String::new(),
+            stderr: "Warning\n".to_string(),
+        };
+
+        assert!(!output.is_empty());
+        assert_eq!(
+            output.to_yaml(),
+            YamlValue::Map(vec![("stderr".to_string(), YamlValue::scalar("Warning\n"))])
+        );
+    }
+}
@@ -0,0 +1,667 @@
+//! `TapCommand` -- Run a command and check how it went, as a TAP test
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::tap_capture::{self, CapturedOutput};
+use crate::tap_harness::TapHarness;
+use crate::tap_location::TapLocation;
+use crate::tap_parser::TapSuiteReader;
+use crate::tap_regex::Regex;
+use crate::tap_suite::TapSuite;
+use crate::tap_test::TapTest;
+use crate::tap_test_builder::TapTestBuilder;
+use crate::tap_writer::TapWriter;
+use crate::tap_yaml::{self, YamlValue};
+
+/// A command to run, and what it should do. It passes if it exits with the expected status and its output matches every pattern given for it.
+///
+/// A failing command's output goes into the test's YAML block under `output`, along with the command line under `command`.
+///
+/// A command which prints TAP itself can have its stream embedded as a subtest instead, with the test passing only if every test in the stream did too.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_command::TapCommand;
+/// use testanything::tap_regex::Regex;
+///
+/// let mut command = TapCommand::new("Rust is installed", "rustc --version").unwrap();
+/// command.stdout.push(Regex::new(r"^rustc \d+\.\d+").unwrap());
+///
+/// assert!(command.run().passed);
+/// ```
+#[derive(Debug, Clone)]
+pub struct TapCommand {
+    /// The name of the test
+    pub name: String,
+    /// The program to run, found on the `PATH` if it's not a path itself
+    pub program: String,
+    /// The arguments to give it
+    pub args: Vec<String>,
+    /// The directory to run it in, if not the current one
+    pub dir: Option<PathBuf>,
+    /// Environment variables to set for it
+    pub env: Vec<(String, String)>,
+    /// The exit status it should have
+    pub status: i32,
+    /// Patterns its standard output should match. `^` and `$` match at the start and end of any of its lines.
+    pub stdout: Vec<Regex>,
+    /// Patterns its standard error should match
+    pub stderr: Vec<Regex>,
+    /// How long it may run before it's killed
+    pub timeout: Option<Duration>,
+    /// Where the command was defined
+    pub location: Option<TapLocation>,
+    /// Whether the command prints TAP, to be embedded as a subtest
+    pub tap: bool,
+}
+
+impl TapCommand {
+    /// Make a command which should exit with status 0, located where this was called from. The command line is split into words as a shell would, minus everything but quoting and backslashes.
+    #[track_caller]
+    pub fn new(name: &str, command_line: &str) -> Result<TapCommand, String> {
+        let mut words = split_command_line(command_line)?;
+        if words.is_empty() {
+            return Err(format!("{} has an empty command line", name));
+        }
+        let program = words.remove(0);
+        Ok(TapCommand {
+            name: name.to_string(),
+            program,
+            args: words,
+            dir: None,
+            env: Vec::new(),
+            status: 0,
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            timeout: None,
+            location: Some(TapLocation::caller()),
+            tap: false,
+        })
+    }
+
+    /// The program and its arguments, quoted where they need to be
+    pub fn command_line(&self) -> String {
+        let quote = |word: &String| {
+            if !word.is_empty()
+                && !word.contains(|c: char| c.is_whitespace() || "'\"\\".contains(c))
+            {
+                word.clone()
+            } else {
+                format!("'{}'", word.replace('\'', "'\\''"))
+            }
+        };
+        std::iter::once(&self.program)
+            .chain(&self.args)
+            .map(quote)
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// Run the command, wait for it to finish, and check how it went. If it prints TAP, that comes back as the test's subtests.
+    pub fn run(&self) -> TapTest {
+        self.run_checked(self.tap, |_| {})
+    }
+
+    /// Run a command which prints TAP, handing each line of its stream to `emit` as it comes, indented as a subtest and headed by a "# Subtest" comment. The test sums up the stream: it passes if every test in it did, its plan was kept, and the command exited as expected.
+    ///
+    /// Treats the command as printing TAP, whether or not `tap` is set.
+    pub fn run_nested<F: FnMut(&str)>(&self, emit: F) -> TapTest {
+        self.run_checked(true, emit)
+    }
+
+    /// Like `run_nested`, printing the stream as it comes, then the line of test `test_number` summing it up through `writer`.
+    pub fn run_nested_to(&self, writer: &TapWriter, test_number: i32) -> TapTest {
+        let tap_test = self.run_nested(|line| println!("{}", line));
+        writer.test_after_subtests(test_number, &tap_test);
+        tap_test
+    }
+
+    fn run_checked<F: FnMut(&str)>(&self, tap: bool, mut emit: F) -> TapTest {
+        let mut builder = TapTestBuilder::new();
+        builder.name(self.name.as_str()).start_timer();
+        if let Some(location) = &self.location {
+            builder.location(location.clone());
+        }
+
+        let mut reader = tap.then(TapSuiteReader::new);
+        let mut headed = false;
+        let ran = self.execute(|line| {
+            if let Some(reader) = reader.as_mut() {
+                if !headed {
+                    emit(&format!("    # Subtest: {}", self.name));
+                    headed = true;
+                }
+                reader.read_line(line);
+                emit(&format!("    {}", line));
+            }
+        });
+        let (status, mut output) = match ran {
+            Ok(ran) => ran,
+            Err(reason) => {
+                let diagnostic = format!("Couldn't run {}: {}", self.program, reason);
+                return builder.passed(false).diagnostics(&[&diagnostic]).finalize();
+            }
+        };
+
+        let mut diagnostics = Vec::new();
+        match status {
+            None => diagnostics.push(format!(
+                "Timed out after {}s",
+                self.timeout.unwrap_or_default().as_secs_f64()
+            )),
+            Some(status) if status.code() == Some(self.status) => {}
+            Some(status) => diagnostics.push(match status.code() {
+                Some(code) => format!("Exited with status {}, expecting {}", code, self.status),
+                None => format!("Exited with {}, expecting status {}", status, self.status),
+            }),
+        }
+        for (stream, text, patterns) in [
+            ("stdout", &output.stdout, &self.stdout),
+            ("stderr", &output.stderr, &self.stderr),
+        ] {
+            for pattern in patterns.iter().filter(|pattern| !pattern.is_match(text)) {
+                diagnostics.push(format!("{} doesn't match {}", stream, pattern));
+            }
+        }
+
+        if let Some(reader) = reader {
+            let plan = reader.plan();
+            let suite = reader.finish(&self.name);
+            diagnostics.extend(summarize(plan, &suite));
+            builder.subtests(suite.tests);
+            // The stream is in the subtests already
+            output.stdout.clear();
+        }
+
+        if diagnostics.is_empty() {
+            return builder.passed(true).finalize();
+        }
+        builder
+            .passed(false)
+            .diagnostics(
+                &diagnostics
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<&str>>(),
+            )
+            .yaml_field("command", YamlValue::scalar(self.command_line()));
+        if !output.is_empty() {
+            builder.yaml_field("output", output.to_yaml());
+        }
+        builder.finalize()
+    }
+
+    /// Run the command to the end, or until it times out, which gives no status. Each line of its standard output is handed to `on_line` as it comes.
+    fn execute<F: FnMut(&str)>(
+        &self,
+        mut on_line: F,
+    ) -> Result<(Option<ExitStatus>, CapturedOutput), String> {
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        let mut child = command.spawn().map_err(|reason| reason.to_string())?;
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+
+        // Standard output is read a line at a time, to be handed on as it comes; standard error all at once, on the side
+        let stderr = tap_capture::read_pipe(child.stderr.take());
+        let (sender, lines) = mpsc::channel();
+        let stdout = child.stdout.take();
+        thread::spawn(move || {
+            let Some(stdout) = stdout else {
+                return;
+            };
+            let mut stdout = BufReader::new(stdout);
+            let mut line = Vec::new();
+            while stdout
+                .read_until(b'\n', &mut line)
+                .is_ok_and(|read| read > 0)
+            {
+                if sender
+                    .send(String::from_utf8_lossy(&line).into_owned())
+                    .is_err()
+                {
+                    break;
+                }
+                line.clear();
+            }
+        });
+
+        let mut output = CapturedOutput::default();
+        let next_line = || match deadline {
+            None => lines.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            Some(deadline) => {
+                lines.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+        };
+        while let Ok(line) = next_line() {
+            on_line(line.trim_end_matches(['\n', '\r']));
+            output.stdout.push_str(&line);
+        }
+
+        // If the output stopped at the deadline, the command is killed now
+        let status =
+            tap_capture::wait_until(&mut child, deadline).map_err(|reason| reason.to_string())?;
+        let stderr = tap_capture::receive(&stderr, deadline);
+        output.stderr = String::from_utf8_lossy(&stderr).into_owned();
+        Ok((status, output))
+    }
+}
+
+/// What's wrong with a stream a command printed, if anything
+fn summarize(plan: Option<(i64, i64)>, suite: &TapSuite) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Some(reason) = &suite.bail_out {
+        problems.push(format!("Bailed out: {}", reason));
+    }
+    match plan {
+        None => problems.push("The TAP stream had no plan".to_string()),
+        Some((start, end)) => {
+            let planned = (end - start + 1).max(0) as usize;
+            if planned != suite.tests.len() && suite.bail_out.is_none() {
+                problems.push(format!(
+                    "Planned {} tests but ran {}",
+                    planned,
+                    suite.tests.len()
+                ));
+            }
+        }
+    }
+    let failures = suite.tests.iter().filter(|test| test.is_failure()).count();
+    if failures > 0 {
+        problems.push(format!(
+            "Failed {} of {} tests",
+            failures,
+            suite.tests.len()
+        ));
+    }
+    problems
+}
+
+/// Read the commands in a manifest file. See `parse_manifest` for the format.
+pub fn load_manifest(path: &str) -> Result<Vec<TapCommand>, String> {
+    let text =
+        fs::read_to_string(path).map_err(|reason| format!("Couldn't read {}: {}", path, reason))?;
+    parse_manifest(path, &text)
+}
+
+/// Read the commands in a manifest, which is YAML with a list of commands under `commands`. Each is located in `source`, the name of the manifest.
+///
+/// Each command has a `run` command line, or a list of the program and its arguments, and optionally:
+///
+/// * `name`: the name of the test, the command line if not given
+/// * `status`: the exit status expected, 0 if not given
+/// * `stdout`, `stderr`: a pattern, or list of patterns, the output should match
+/// * `dir`: the directory to run in
+/// * `env`: a mapping of environment variables to set
+/// * `timeout`: how many seconds it may run for
+/// * `tap`: true if the command prints TAP, to be embedded as a subtest
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_command::parse_manifest;
+///
+/// let manifest = "
+/// commands:
+///   - name: Rust is installed
+///     run: rustc --version
+///     stdout: ^rustc
+///   - run: cargo --definitely-not-a-flag
+///     status: 1
+///     timeout: 30
+/// ";
+/// let commands = parse_manifest("checks.yml", manifest).unwrap();
+///
+/// assert_eq!(commands[1].name, "cargo --definitely-not-a-flag");
+/// assert_eq!(commands[1].status, 1);
+/// ```
+pub fn parse_manifest(source: &str, text: &str) -> Result<Vec<TapCommand>, String> {
+    let lines = text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .collect::<Vec<&str>>();
+    let entries = tap_yaml::parse_block(&lines);
+    let Some(YamlValue::List(items)) = tap_yaml::lookup(&entries, "commands") else {
+        return Err(format!("{}: no list of commands", source));
+    };
+
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            parse_command(source, item)
+                .map_err(|reason| format!("{}: command {}: {}", source, i + 1, reason))
+        })
+        .collect()
+}
+
+fn parse_command(source: &str, item: &YamlValue) -> Result<TapCommand, String> {
+    let YamlValue::Map(fields) = item else {
+        return Err("not a mapping".to_string());
+    };
+    let scalar = |key: &str, value: &YamlValue| {
+        value
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("{} should be a single value", key))
+    };
+    let patterns = |key: &str, value: &YamlValue| {
+        let patterns = match value {
+            YamlValue::List(items) => items
+                .iter()
+                .map(|item| scalar(key, item))
+                .collect::<Result<Vec<String>, String>>()?,
+            value => vec![scalar(key, value)?],
+        };
+        patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect::<Result<Vec<Regex>, String>>()
+    };
+
+    let words = match tap_yaml::lookup(fields, "run") {
+        Some(YamlValue::List(items)) => items
+            .iter()
+            .map(|item| scalar("run", item))
+            .collect::<Result<Vec<String>, String>>()?,
+        Some(value) => split_command_line(&scalar("run", value)?)?,
+        None => return Err("nothing to run".to_string()),
+    };
+    let Some((program, args)) = words.split_first() else {
+        return Err("an empty command line".to_string());
+    };
+    let mut command = TapCommand {
+        name: String::new(),
+        program: program.clone(),
+        args: args.to_vec(),
+        dir: None,
+        env: Vec::new(),
+        status: 0,
+        stdout: Vec::new(),
+        stderr: Vec::new(),
+        timeout: None,
+        location: Some(TapLocation::new(source, 0, 0)),
+        tap: false,
+    };
+    command.name = command.command_line();
+
+    for (key, value) in fields {
+        match key.as_str() {
+            "run" => {}
+            "name" => command.name = scalar(key, value)?,
+            "status" => {
+                command.status = scalar(key, value)?
+                    .parse()
+                    .map_err(|_| "status should be a whole number".to_string())?
+            }
+            "stdout" => command.stdout = patterns(key, value)?,
+            "stderr" => command.stderr = patterns(key, value)?,
+            "dir" => command.dir = Some(PathBuf::from(scalar(key, value)?)),
+            "tap" => {
+                command.tap = match scalar(key, value)?.as_str() {
+                    "true" | "yes" => true,
+                    "false" | "no" => false,
+                    _ => return Err("tap should be true or false".to_string()),
+                }
+            }
+            "env" => {
+                let YamlValue::Map(variables) = value else {
+                    return Err("env should be a mapping".to_string());
+                };
+                for (name, value) in variables {
+                    command.env.push((name.clone(), scalar(name, value)?));
+                }
+            }
+            "timeout" => {
+                let timeout = scalar(key, value)?
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                    .ok_or("timeout should be a number of seconds")?;
+                command.timeout = Some(timeout);
+            }
+            key => return Err(format!("unknown key {}", key)),
+        }
+    }
+    Ok(command)
+}
+
+/// A harness which runs each command as a test. The TAP a command prints comes out as its subtests once it has finished, or as it's printed if the harness is set to `live_subtests`.
+pub fn command_harness(name: &str, commands: Vec<TapCommand>) -> TapHarness {
+    let mut harness = TapHarness::new(name);
+    for command in commands {
+        let test_name = command.name.clone();
+        if command.tap {
+            harness.nested_check(test_name, move |emit| command.run_nested(emit));
+        } else {
+            harness.check(test_name, move || command.run());
+        }
+    }
+    harness
+}
+
+/// Split a command line into words at whitespace. Single quotes keep everything in them as is; double quotes keep everything but backslash escapes; a backslash outside quotes escapes the next character.
+pub fn split_command_line(command_line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command_line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(format!("Unclosed ' in {}", command_line)),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(format!("Unclosed \" in {}", command_line)),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(format!("Unclosed \" in {}", command_line)),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_manifest, split_command_line, TapCommand};
+    use crate::tap_regex::Regex;
+    use crate::tap_version::TapVersion;
+
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_split_command_line() {
+        assert_eq!(
+            split_command_line(r#"grep -e 'a b' "c \"d\"" e\ f ''"#).unwrap(),
+            vec!["grep", "-e", "a b", "c \"d\"", "e f", ""]
+        );
+        assert!(split_command_line("echo 'oops").is_err());
+
+        let command = TapCommand::new("Echo", r#"echo "a b" c"#).unwrap();
+        assert_eq!(command.command_line(), "echo 'a b' c");
+    }
+
+    #[test]
+    fn test_anchored_patterns() {
+        let mut command = TapCommand::new("Greetings", "sh -c 'echo hello; echo hi'").unwrap();
+        command.stdout.push(Regex::new("^hi$").unwrap());
+        command.stdout.push(Regex::new("^hello$").unwrap());
+        assert!(command.run().passed);
+
+        command.stdout.push(Regex::new("^hello hi$").unwrap());
+        assert_eq!(
+            command.run().diagnostics,
+            vec!["stdout doesn't match /^hello hi$/"]
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let mut command = TapCommand::new("Rust version", "rustc --version").unwrap();
+        command.stdout.push(Regex::new(r"^rustc \d").unwrap());
+        let passing = command.run();
+        assert!(passing.passed);
+        assert_eq!(passing.location.unwrap().file, file!());
+
+        command.status = 3;
+        command.stderr.push(Regex::new("panda").unwrap());
+        let failing = command.run();
+        assert!(!failing.passed);
+        assert_eq!(
+            failing.diagnostics,
+            vec![
+                "Exited with status 0, expecting 3",
+                "stderr doesn't match /panda/"
+            ]
+        );
+        assert!(failing
+            .yaml_value("output")
+            .unwrap()
+            .get("stdout")
+            .is_some());
+        assert_eq!(
+            failing
+                .yaml_value("command")
+                .and_then(|command| command.as_str()),
+            Some("rustc --version")
+        );
+
+        let missing = TapCommand::new("Missing", "definitely-not-a-program-anywhere").unwrap();
+        assert!(missing.run().diagnostics[0].starts_with("Couldn't run"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_nested() {
+        let child = TapCommand::new(
+            "Child",
+            "sh -c 'echo 1..2; echo ok 1 Panda; echo not ok 2 Bamboo; echo \"# Ran out\"'",
+        )
+        .unwrap();
+        let mut lines = Vec::new();
+        let tap_test = child.run_nested(|line| lines.push(line.to_string()));
+
+        assert_eq!(
+            lines,
+            vec![
+                "    # Subtest: Child",
+                "    1..2",
+                "    ok 1 Panda",
+                "    not ok 2 Bamboo",
+                "    # Ran out"
+            ]
+        );
+        assert!(!tap_test.passed);
+        assert_eq!(tap_test.diagnostics, vec!["Failed 1 of 2 tests"]);
+        assert_eq!(tap_test.subtests[1].diagnostics, vec!["Ran out"]);
+        assert_eq!(tap_test.subtest_lines(TapVersion::Tap14)[..3], lines[..3]);
+
+        let mut short = TapCommand::new("Short", "sh -c 'echo 1..3; echo ok; exit 4'").unwrap();
+        short.tap = true;
+        let tap_test = short.run();
+        assert_eq!(
+            tap_test.diagnostics,
+            vec![
+                "Exited with status 4, expecting 0",
+                "Planned 3 tests but ran 1"
+            ]
+        );
+        assert!(tap_test.yaml_value("output").is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_timeout() {
+        // The background sleep keeps the pipes open after the shell is killed
+        let mut command = TapCommand::new("Sleepy", "sh -c 'sleep 5 & sleep 5'").unwrap();
+        command.timeout = Some(Duration::from_millis(200));
+        let started = Instant::now();
+        let tap_test = command.run();
+
+        assert!(started.elapsed() < Duration::from_secs(4));
+        assert_eq!(tap_test.diagnostics, vec!["Timed out after 0.2s"]);
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = "
+# Checks to run
+commands:
+  - name: Version
+    run:
+      - rustc
+      - --version
+    stdout:
+      - ^rustc
+      - \\d+\\.\\d+
+    env:
+      RUST_LOG: debug
+    timeout: 1.5
+  - run: cargo --nope
+    status: 1
+";
+        let commands = parse_manifest("checks.yml", manifest).unwrap();
+
+        assert_eq!(commands[0].name, "Version");
+        assert_eq!(commands[0].args, vec!["--version"]);
+        assert_eq!(commands[0].stdout.len(), 2);
+        assert_eq!(
+            commands[0].env,
+            vec![("RUST_LOG".to_string(), "debug".to_string())]
+        );
+        assert_eq!(commands[0].timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(commands[1].name, "cargo --nope");
+        assert_eq!(commands[1].location.clone().unwrap().file, "checks.yml");
+
+        assert_eq!(
+            parse_manifest("checks.yml", "commands:\n  - run: ls\n    colour: blue\n").unwrap_err(),
+            "checks.yml: command 1: unknown key colour"
+        );
+        assert_eq!(
+            parse_manifest("checks.yml", "commands:\n  - name: Nothing\n").unwrap_err(),
+            "checks.yml: command 1: nothing to run"
+        );
+        assert_eq!(
+            parse_manifest("checks.yml", "commands:\n  - run: ls\n    timeout: 1e30\n")
+                .unwrap_err(),
+            "checks.yml: command 1: timeout should be a number of seconds"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! `TapComparison` -- The value a failing test got, the value it expected, and how they were compared
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::tap_yaml::YamlValue;
+
+/// What a failing assertion compared. Values are kept as text, however the caller chose to format them.
+///
+/// Rendered as `got`, `expected` and `operator` in a YAML block, plus a line-level `diff` when either value spans several lines. TAP 12 consumers get the same as aligned diagnostics instead.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_comparison::TapComparison;
+///
+/// let comparison = TapComparison::equal("Panda\nTiger", "Panda\nLion");
+///
+/// assert_eq!(comparison.diff(), vec!["  Panda", "- Lion", "+ Tiger"]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TapComparison {
+    /// The value the test got
+    pub got: String,
+    /// The value the test expected
+    pub expected: String,
+    /// How the two were compared, like "==" or "contains". May be blank.
+    pub operator: String,
+}
+
+impl TapComparison {
+    /// Make a comparison from its parts
+    pub fn new<G, E, O>(got: G, expected: E, operator: O) -> TapComparison
+    where
+        G: Into<String>,
+        E: Into<String>,
+        O: Into<String>,
+    {
+        TapComparison {
+            got: got.into(),
+            expected: expected.into(),
+            operator: operator.into(),
+        }
+    }
+
+    /// Make a comparison of two values which should have been equal
+    pub fn equal<G: Into<String>, E: Into<String>>(got: G, expected: E) -> TapComparison {
+        TapComparison::new(got, expected, "==")
+    }
+
+    /// Does either value span more than one line?
+    pub fn is_multiline(&self) -> bool {
+        self.got.contains('\n') || self.expected.contains('\n')
+    }
+
+    /// Compare the values line by line. Lines only in the expected value are marked "- ", lines only in the value got "+ ", and shared lines "  ".
+    ///
+    /// The diff is as short as can be, found with Myers' algorithm, unless the values differ in more than 1000 lines. Then it's just every expected line followed by every line got, since the time and memory a shortest diff takes grow with the number of differences.
+    pub fn diff(&self) -> Vec<String> {
+        let expected = self.expected.lines().collect::<Vec<&str>>();
+        let got = self.got.lines().collect::<Vec<&str>>();
+
+        shortest_diff(&expected, &got).unwrap_or_else(|| {
+            let removed = expected.iter().map(|line| format!("- {}", line));
+            let added = got.iter().map(|line| format!("+ {}", line));
+            removed.chain(added).collect()
+        })
+    }
+
+    /// The comparison as it goes into a YAML block
+    pub fn yaml_fields(&self) -> Vec<(String, YamlValue)> {
+        let mut fields = vec![
+            ("got".to_string(), YamlValue::scalar(self.got.as_str())),
+            (
+                "expected".to_string(),
+                YamlValue::scalar(self.expected.as_str()),
+            ),
+        ];
+        if !self.operator.is_empty() {
+            fields.push((
+                "operator".to_string(),
+                YamlValue::scalar(self.operator.as_str()),
+            ));
+        }
+        if self.is_multiline() {
+            fields.push((
+                "diff".to_string(),
+                YamlValue::scalar(self.diff().join("\n")),
+            ));
+        }
+        fields
+    }
+
+    /// The comparison as diagnostics for consumers which predate YAML blocks, with the labels right-aligned so the values line up.
+    pub fn classic_lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut push = |label: &str, value: &str| {
+            let mut value_lines = value.split('\n');
+            lines.push(format!(
+                "  {:>8}: {}",
+                label,
+                value_lines.next().unwrap_or_default()
+            ));
+            lines.extend(value_lines.map(|line| format!("            {}", line)));
+        };
+
+        push("got", &self.got);
+        push("expected", &self.expected);
+        if !self.operator.is_empty() {
+            push("operator", &self.operator);
+        }
+        if self.is_multiline() {
+            push("diff", "");
+            lines.extend(self.diff().iter().map(|line| format!("    {}", line)));
+        }
+        lines
+            .into_iter()
+            .map(|line| line.trim_end().to_string())
+            .collect()
+    }
+}
+
+/// The most lines two values may differ in for `diff` to work out the shortest diff
+const MAX_EDITS: usize = 1000;
+
+/// The shortest diff turning `expected` into `got`, if it takes no more than `MAX_EDITS` edits. Myers' algorithm: for each number of edits in turn, follow the diagonals of the edit graph as far as lines match, keeping the furthest point reached on each, until one gets to the end.
+fn shortest_diff(expected: &[&str], got: &[&str]) -> Option<Vec<String>> {
+    let (n, m) = (expected.len() as isize, got.len() as isize);
+    let max = n + m;
+    // furthest[k + max] is how far along the expected lines the best path on diagonal k, where x - y = k, got
+    let mut furthest = vec![0isize; 2 * max as usize + 2];
+    // The furthest points before each number of edits, just the diagonals which could be reached by then
+    let mut trace = Vec::new();
+
+    let at = |k: isize| (k + max) as usize;
+    let mut edits = None;
+    for d in 0..=max.min(MAX_EDITS as isize) {
+        trace.push(furthest[at(-d)..=at(d)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && furthest[at(k - 1)] < furthest[at(k + 1)]) {
+                furthest[at(k + 1)]
+            } else {
+                furthest[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && expected[x as usize] == got[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            furthest[at(k)] = x;
+            if x >= n && y >= m {
+                edits = Some(d);
+                break;
+            }
+        }
+        if edits.is_some() {
+            break;
+        }
+    }
+
+    // Walk back from the end, one edit at a time
+    let mut lines = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (0..=edits?).rev() {
+        let before = &trace[d as usize];
+        let reached = |k: isize| before[(k + d) as usize];
+        let k = x - y;
+        let (previous_x, previous_y) = if d == 0 {
+            (0, 0)
+        } else {
+            let previous_k = if k == -d || (k != d && reached(k - 1) < reached(k + 1)) {
+                k + 1
+            } else {
+                k - 1
+            };
+            (reached(previous_k), reached(previous_k) - previous_k)
+        };
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            lines.push(format!("  {}", expected[x as usize]));
+        }
+        if d > 0 {
+            if x == previous_x {
+                lines.push(format!("+ {}", got[previous_y as usize]));
+            } else {
+                lines.push(format!("- {}", expected[previous_x as usize]));
+            }
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    lines.reverse();
+    Some(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TapComparison;
+
+    #[test]
+    fn test_diff() {
+        let comparison = TapComparison::equal("a\nb\nx\nd", "a\nb\nc\nd\ne");
+
+        assert_eq!(
+            comparison.diff(),
+            vec!["  a", "  b", "- c", "+ x", "  d", "- e"]
+        );
+        assert_eq!(TapComparison::equal("same", "same").diff(), vec!["  same"]);
+        assert_eq!(TapComparison::equal("", "").diff(), Vec::<String>::new());
+        assert_eq!(
+            TapComparison::equal("b\na\nc", "a\nb\nc").diff(),
+            vec!["- a", "  b", "+ a", "  c"]
+        );
+    }
+
+    #[test]
+    fn test_long_diffs() {
+        let numbers =
+            |range: core::ops::Range<usize>| range.map(|i| i.to_string()).collect::<Vec<String>>();
+
+        // Few differences between long values take little
+        let mut got = numbers(0..100_000);
+        got[50_000] = "changed".to_string();
+        let comparison = TapComparison::equal(got.join("\n"), numbers(0..100_000).join("\n"));
+        let diff = comparison.diff();
+        assert_eq!(diff.len(), 100_001);
+        assert_eq!(diff[50_000..50_002], ["- 50000", "+ changed"]);
+
+        // Too many, and the values are listed in full
+        let comparison = TapComparison::equal(
+            numbers(0..2_000).join("\n"),
+            numbers(2_000..4_000).join("\n"),
+        );
+        let diff = comparison.diff();
+        assert_eq!(diff.len(), 4_000);
+        assert_eq!(diff[0], "- 2000");
+        assert_eq!(diff[2_000], "+ 0");
+    }
+
+    #[test]
+    fn test_classic_lines() {
+        let comparison = TapComparison::new("1", "2", "==");
+        assert_eq!(
+            comparison.classic_lines(),
+            vec!["       got: 1", "  expected: 2", "  operator: =="]
+        );
+
+        let multiline = TapComparison::new("Panda\nTiger", "Panda\nLion", "");
+        assert_eq!(
+            multiline.classic_lines(),
+            vec![
+                "       got: Panda",
+                "            Tiger",
+                "  expected: Panda",
+                "            Lion",
+                "      diff:",
+                "      Panda",
+                "    - Lion",
+                "    + Tiger",
+            ]
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! `TapDiff` -- For comparing two runs of the same suite to find out what changed
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+#[cfg(feature = "std")]
+use std::collections::BTreeMap;
+
+use crate::tap_suite::TapSuite;
+
+/// A test whose fate differs between two runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestChange {
+    /// The name of the test
+    pub name: String,
+    /// Its number in the old run, if it was there
+    pub old_number: Option<i64>,
+    /// Its number in the new run, if it's there
+    pub new_number: Option<i64>,
+    /// Whether it passed in the new run, or in the old one if it has since disappeared
+    pub passed: bool,
+}
+
+/// How two runs of a suite differ. Tests are matched up by name, except where a name is blank or shared by several tests; those are matched up by number instead.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_diff::TapDiff;
+/// use testanything::tap_parser::TapParser;
+///
+/// let old = TapParser::new().parse_suite("old", "1..2\nok 1 Panda\nnot ok 2 Bamboo");
+/// let new = TapParser::new().parse_suite("new", "1..2\nnot ok 1 Panda\nok 2 Bamboo");
+///
+/// let diff = TapDiff::new(&old, &new);
+///
+/// assert!(diff.has_regressions());
+/// assert_eq!(diff.regressions[0].name, "Panda");
+/// assert_eq!(diff.fixes[0].name, "Bamboo");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TapDiff {
+    /// Tests which didn't fail before and do now. A failing TODO test doesn't count as failing.
+    pub regressions: Vec<TestChange>,
+    /// Tests which failed before and don't now
+    pub fixes: Vec<TestChange>,
+    /// Tests which were in the old run but not the new one
+    pub removed: Vec<TestChange>,
+    /// Tests which are in the new run but weren't in the old one
+    pub added: Vec<TestChange>,
+    /// Why the new run was cut short, if it was
+    pub bail_out: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum TestKey {
+    Name(String),
+    Number(i64),
+}
+
+impl TapDiff {
+    /// Compare an old run with a new one.
+    pub fn new(old: &TapSuite, new: &TapSuite) -> TapDiff {
+        let mut ambiguous = ambiguous_names(old);
+        ambiguous.extend(ambiguous_names(new));
+
+        let key = |number: i64, name: &str| {
+            if name.is_empty() || ambiguous.iter().any(|ambiguous| ambiguous == name) {
+                TestKey::Number(number)
+            } else {
+                TestKey::Name(name.to_string())
+            }
+        };
+
+        let mut old_tests = BTreeMap::new();
+        for (i, test) in old.tests.iter().enumerate() {
+            let number = i as i64 + 1;
+            old_tests.insert(key(number, &test.name), (number, test));
+        }
+
+        let mut diff = TapDiff {
+            bail_out: new.bail_out.clone(),
+            ..Default::default()
+        };
+        for (i, test) in new.tests.iter().enumerate() {
+            let number = i as i64 + 1;
+            let change = |old_number| TestChange {
+                name: test.name.clone(),
+                old_number,
+                new_number: Some(number),
+                passed: test.passed,
+            };
+            match old_tests.remove(&key(number, &test.name)) {
+                None => diff.added.push(change(None)),
+                Some((old_number, old_test)) if !old_test.is_failure() && test.is_failure() => {
+                    diff.regressions.push(change(Some(old_number)))
+                }
+                Some((old_number, old_test)) if old_test.is_failure() && !test.is_failure() => {
+                    diff.fixes.push(change(Some(old_number)))
+                }
+                Some(_) => {}
+            }
+        }
+
+        let mut removed = old_tests
+            .into_values()
+            .map(|(number, test)| TestChange {
+                name: test.name.clone(),
+                old_number: Some(number),
+                new_number: None,
+                passed: test.passed,
+            })
+            .collect::<Vec<TestChange>>();
+        removed.sort_by_key(|change| change.old_number);
+        diff.removed = removed;
+
+        diff
+    }
+
+    /// Did the new run get worse? That's when something that used to pass fails, something that used to pass disappeared, or the new run bailed out, which usually explains the disappearances.
+    pub fn has_regressions(&self) -> bool {
+        self.has_new_failures()
+            || self.bail_out.is_some()
+            || self.removed.iter().any(|change| change.passed)
+    }
+
+    /// Did anything that used to pass start failing? Unlike `has_regressions`, this doesn't mind tests which are gone.
+    pub fn has_new_failures(&self) -> bool {
+        !self.regressions.is_empty()
+    }
+
+    /// Are the two runs the same, as far as passing and failing goes?
+    pub fn is_empty(&self) -> bool {
+        self.regressions.is_empty()
+            && self.fixes.is_empty()
+            && self.removed.is_empty()
+            && self.added.is_empty()
+            && self.bail_out.is_none()
+    }
+
+    /// Produce a human-readable report, one section per kind of change, ending with a tally.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let sections = [
+            ("Newly failing", &self.regressions),
+            ("Newly passing", &self.fixes),
+            ("Disappeared", &self.removed),
+            ("Appeared", &self.added),
+        ];
+
+        for (heading, changes) in sections.iter() {
+            if changes.is_empty() {
+                continue;
+            }
+            lines.push(format!("{} ({}):", heading, changes.len()));
+            lines.extend(changes.iter().map(describe));
+        }
+        if let Some(reason) = &self.bail_out {
+            lines.push(
+                format!("New run bailed out: {}", reason)
+                    .trim_end()
+                    .to_string(),
+            );
+        }
+
+        lines.push(format!(
+            "{} newly failing, {} newly passing, {} disappeared, {} appeared",
+            self.regressions.len(),
+            self.fixes.len(),
+            self.removed.len(),
+            self.added.len()
+        ));
+
+        lines
+    }
+}
+
+fn describe(change: &TestChange) -> String {
+    let status = if change.passed { "ok" } else { "not ok" };
+    let numbers = match (change.old_number, change.new_number) {
+        (Some(old), Some(new)) if old == new => format!("#{}", new),
+        (Some(old), Some(new)) => format!("#{} -> #{}", old, new),
+        (Some(old), None) => format!("#{}", old),
+        (None, Some(new)) => format!("#{}", new),
+        (None, None) => String::new(),
+    };
+    format!("  {} ({}, {})", change.name, numbers, status)
+}
+
+/// Names which can't tell tests apart, because more than one test in the suite has them.
+fn ambiguous_names(suite: &TapSuite) -> Vec<String> {
+    let mut seen = BTreeMap::new();
+    for test in &suite.tests {
+        *seen.entry(test.name.as_str()).or_insert(0) += 1;
+    }
+    seen.into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TapDiff;
+    use crate::tap_parser::TapParser;
+
+    #[test]
+    fn test_diff_by_name() {
+        let old = TapParser::new().parse_suite(
+            "old",
+            "1..4\nok 1 Panda\nnot ok 2 Bamboo\nok 3 Curry\nok 4 Tree",
+        );
+        let new = TapParser::new().parse_suite(
+            "new",
+            "1..4\nok 1 Bamboo\nnot ok 2 Curry\nok 3 Panda\nnot ok 4 Noodle",
+        );
+
+        let diff = TapDiff::new(&old, &new);
+
+        let expected = vec![
+            "Newly failing (1):",
+            "  Curry (#3 -> #2, not ok)",
+            "Newly passing (1):",
+            "  Bamboo (#2 -> #1, ok)",
+            "Disappeared (1):",
+            "  Tree (#4, ok)",
+            "Appeared (1):",
+            "  Noodle (#4, not ok)",
+            "1 newly failing, 1 newly passing, 1 disappeared, 1 appeared",
+        ];
+
+        assert!(diff.has_regressions());
+        assert_eq!(expected, diff.lines());
+    }
+
+    #[test]
+    fn test_diff_falls_back_to_number() {
+        let old = TapParser::new().parse_suite("old", "1..3\nok 1 Same\nok 2 Same\nok 3");
+        let new = TapParser::new().parse_suite("new", "1..3\nok 1 Same\nnot ok 2 Same\nok 3");
+
+        let diff = TapDiff::new(&old, &new);
+
+        assert_eq!(diff.regressions.len(), 1);
+        assert_eq!(diff.regressions[0].old_number, Some(2));
+        assert!(diff.fixes.is_empty() && diff.removed.is_empty() && diff.added.is_empty());
+    }
+
+    #[test]
+    fn test_diff_with_todo() {
+        let old = TapParser::new().parse_suite("old", "1..2\nok 1 Panda\nnot ok 2 Bamboo");
+        let new = TapParser::new().parse_suite(
+            "new",
+            "1..2\nnot ok 1 Panda # TODO Eats too much\nnot ok 2 Bamboo # TODO Grows back",
+        );
+
+        let diff = TapDiff::new(&old, &new);
+
+        assert!(!diff.has_regressions());
+        assert_eq!(diff.fixes.len(), 1);
+        assert_eq!(diff.fixes[0].name, "Bamboo");
+    }
+
+    #[test]
+    fn test_diff_with_bail_out() {
+        let old = TapParser::new().parse_suite("old", "1..3\nok 1 Panda\nok 2 Bamboo\nok 3 Curry");
+        let new = TapParser::new().parse_suite("new", "1..3\nok 1 Panda\nBail out! db down");
+
+        let diff = TapDiff::new(&old, &new);
+
+        assert!(diff.has_regressions());
+        assert!(!diff.has_new_failures());
+        assert_eq!(diff.removed.len(), 2);
+        assert_eq!(
+            diff.lines()[3..],
+            [
+                "New run bailed out: db down",
+                "0 newly failing, 0 newly passing, 2 disappeared, 0 appeared"
+            ]
+        );
+
+        let lost = TapParser::new().parse_suite("new", "1..1\nok 1 Panda");
+        assert!(TapDiff::new(&old, &lost).has_regressions());
+        let gone = TapParser::new().parse_suite("old", "1..2\nok 1 Panda\nnot ok 2 Bamboo");
+        assert!(!TapDiff::new(&gone, &lost).has_regressions());
+    }
+
+    #[test]
+    fn test_no_difference() {
+        let old = TapParser::new().parse_suite("old", "1..1\nok 1 Panda");
+
+        let diff = TapDiff::new(&old, &old);
+
+        assert!(diff.is_empty());
+        assert_eq!(
+            diff.lines(),
+            vec!["0 newly failing, 0 newly passing, 0 disappeared, 0 appeared"]
+        );
+    }
+}
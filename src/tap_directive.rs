@@ -0,0 +1,104 @@
+//! `TapDirective` -- The SKIP and TODO markers which may trail a test line
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+
+/// A directive changes what a test's result means. A skipped test wasn't really run; a TODO test is expected to fail and doesn't count against the suite when it does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TapDirective {
+    /// The test was skipped, for the given reason
+    Skip(String),
+    /// The test covers something not done yet, for the given reason
+    Todo(String),
+}
+
+impl TapDirective {
+    /// Produce the directive as it trails a test line, like "# SKIP no database".
+    pub fn directive_string(&self) -> String {
+        let (keyword, reason) = match self {
+            TapDirective::Skip(reason) => ("SKIP", reason),
+            TapDirective::Todo(reason) => ("TODO", reason),
+        };
+        format!("# {} {}", keyword, reason).trim_end().to_string()
+    }
+
+    /// The reason given for the directive, which may be blank
+    pub fn reason(&self) -> &str {
+        match self {
+            TapDirective::Skip(reason) | TapDirective::Todo(reason) => reason,
+        }
+    }
+
+    /// Split a test description into the name and the directive trailing it, if there is one. Keywords are matched without regard to case, and longer forms like "skipped" are accepted.
+    pub fn split(description: &str) -> (&str, Option<TapDirective>) {
+        for (i, _) in description.match_indices('#').rev() {
+            // A directive's mark must stand apart from the name, and a \# is a literal mark
+            if i > 0 && !description[..i].ends_with(char::is_whitespace) {
+                continue;
+            }
+            if let Some(directive) = TapDirective::parse(&description[i + 1..]) {
+                return (description[..i].trim_end(), Some(directive));
+            }
+        }
+        (description, None)
+    }
+
+    fn parse(s: &str) -> Option<TapDirective> {
+        let s = s.trim_start();
+        let keyword_length = s
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(s.len());
+        let keyword = s[..keyword_length].to_ascii_lowercase();
+        let rest = s[keyword_length..].trim_start();
+        let reason = rest.strip_prefix(':').unwrap_or(rest).trim().to_string();
+
+        if keyword.starts_with("skip") {
+            Some(TapDirective::Skip(reason))
+        } else if keyword.starts_with("todo") {
+            Some(TapDirective::Todo(reason))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TapDirective;
+
+    #[test]
+    fn test_directive_string() {
+        assert_eq!(
+            TapDirective::Skip("no database".to_string()).directive_string(),
+            "# SKIP no database"
+        );
+        assert_eq!(
+            TapDirective::Todo(String::new()).directive_string(),
+            "# TODO"
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(
+            TapDirective::split("Panda # SKIP no bamboo"),
+            ("Panda", Some(TapDirective::Skip("no bamboo".to_string())))
+        );
+        assert_eq!(
+            TapDirective::split("Tiger #skipped: asleep"),
+            ("Tiger", Some(TapDirective::Skip("asleep".to_string())))
+        );
+        assert_eq!(
+            TapDirective::split("Curry # todo"),
+            ("Curry", Some(TapDirective::Todo(String::new())))
+        );
+        assert_eq!(TapDirective::split("Issue #12"), ("Issue #12", None));
+        assert_eq!(
+            TapDirective::split("Not\\# SKIP this"),
+            ("Not\\# SKIP this", None)
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! `TapFixture` -- Something harness tests need set up first, and cleaned up after
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::tap_harness::TestResult;
+use crate::tap_panic::catch_panic;
+use crate::tap_test::TapTest;
+use crate::tap_test_builder::TapTestBuilder;
+
+type HookFn = Arc<dyn Fn() -> TestResult + Send + Sync>;
+
+/// Something tests need, like a temporary directory or a local server: how to set it up, and how to tear it down again. A hook which panics fails, with the panic's message as its reason.
+///
+/// A fixture is only torn down if it was set up, and fixtures are torn down in the reverse of the order they were set up in.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_fixture::TapFixture;
+///
+/// let fixture = TapFixture::new(
+///     "Bamboo store",
+///     || Ok(()),
+///     || Err("Store still locked".to_string()),
+/// );
+///
+/// assert_eq!(fixture.set_up(), Ok(()));
+/// let failure = fixture.tear_down(None).unwrap();
+///
+/// assert_eq!(failure.name, "Teardown of Bamboo store");
+/// assert_eq!(failure.diagnostics, vec!["Store still locked"]);
+/// ```
+#[derive(Clone)]
+pub struct TapFixture {
+    /// What the fixture provides
+    pub name: String,
+    setup: HookFn,
+    teardown: HookFn,
+}
+
+impl TapFixture {
+    /// Make a fixture out of its setup and teardown
+    pub fn new<S, F, G>(name: S, setup: F, teardown: G) -> TapFixture
+    where
+        S: Into<String>,
+        F: Fn() -> TestResult + Send + Sync + 'static,
+        G: Fn() -> TestResult + Send + Sync + 'static,
+    {
+        TapFixture {
+            name: name.into(),
+            setup: Arc::new(setup),
+            teardown: Arc::new(teardown),
+        }
+    }
+
+    /// Set the fixture up, or explain why it couldn't be, like "Setup of Bamboo store failed: Disk full"
+    pub fn set_up(&self) -> Result<(), String> {
+        run_hook(&self.setup).map_err(|reason| format!("Setup of {} failed: {}", self.name, reason))
+    }
+
+    /// Tear the fixture down, after the test called `after` if it was set up for one. If that fails, gives back a failing test to report it with.
+    pub fn tear_down(&self, after: Option<&str>) -> Option<TapTest> {
+        run_hook(&self.teardown).err().map(|reason| {
+            let name = match after {
+                Some(test) => format!("Teardown of {} after {}", self.name, test),
+                None => format!("Teardown of {}", self.name),
+            };
+            TapTestBuilder::new()
+                .name(name)
+                .passed(false)
+                .diagnostics(&[&reason])
+                .finalize()
+        })
+    }
+}
+
+impl fmt::Debug for TapFixture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TapFixture")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+/// Set up each fixture in turn, stopping at the first which can't be. Gives back how many were set up, and why the next one couldn't be, if it couldn't.
+pub(crate) fn set_up_all(fixtures: &[TapFixture]) -> (usize, Option<String>) {
+    for (i, fixture) in fixtures.iter().enumerate() {
+        if let Err(reason) = fixture.set_up() {
+            return (i, Some(reason));
+        }
+    }
+    (fixtures.len(), None)
+}
+
+/// Tear down fixtures which were set up, last first, giving back a failing test for each which couldn't be
+pub(crate) fn tear_down_all(fixtures: &[TapFixture], after: Option<&str>) -> Vec<TapTest> {
+    fixtures
+        .iter()
+        .rev()
+        .filter_map(|fixture| fixture.tear_down(after))
+        .collect()
+}
+
+fn run_hook(hook: &HookFn) -> TestResult {
+    catch_panic(|| hook()).unwrap_or_else(|details| Err(details.message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{set_up_all, tear_down_all, TapFixture};
+
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_set_up_and_tear_down_all() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let fixture = |name: &'static str, works: bool| {
+            let (setup_log, teardown_log) = (log.clone(), log.clone());
+            TapFixture::new(
+                name,
+                move || {
+                    setup_log.lock().unwrap().push(format!("Set up {}", name));
+                    if works {
+                        Ok(())
+                    } else {
+                        Err("Nope".to_string())
+                    }
+                },
+                move || {
+                    teardown_log
+                        .lock()
+                        .unwrap()
+                        .push(format!("Tore down {}", name));
+                    Ok(())
+                },
+            )
+        };
+        let fixtures = vec![
+            fixture("Panda", true),
+            fixture("Bamboo", true),
+            fixture("Tree", false),
+            fixture("Curry", true),
+        ];
+
+        let (set_up, failure) = set_up_all(&fixtures);
+        assert_eq!(set_up, 2);
+        assert_eq!(failure.as_deref(), Some("Setup of Tree failed: Nope"));
+        assert!(tear_down_all(&fixtures[..set_up], None).is_empty());
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                "Set up Panda",
+                "Set up Bamboo",
+                "Set up Tree",
+                "Tore down Bamboo",
+                "Tore down Panda",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_panicking_hooks() {
+        let fixture = TapFixture::new("Panda", || panic!("Out of bamboo"), || panic!("Stuck"));
+
+        assert_eq!(
+            fixture.set_up(),
+            Err("Setup of Panda failed: Out of bamboo".to_string())
+        );
+        let failures = tear_down_all(&[fixture], Some("Eats"));
+        assert_eq!(failures[0].name, "Teardown of Panda after Eats");
+        assert_eq!(failures[0].diagnostics, vec!["Stuck"]);
+        assert!(!failures[0].passed);
+    }
+}
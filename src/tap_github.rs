@@ -0,0 +1,280 @@
+//! `TapGithubReporter` -- Turns TAP failures into GitHub Actions workflow commands, which show up as annotations on a pull request
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::tap_parser::TapEvent;
+use crate::tap_suite::TapSuite;
+use crate::tap_test::TapTest;
+
+/// Produce workflow commands for a finished suite: an `::error` for every failure, and a `::group::` around each top-level test with subtests.
+///
+/// A failure is pinned to a file and line when the test has a `location`, which parsing picks up from the `at` key of its YAML block.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_github::github_annotations;
+/// use testanything::tap_parser::TapParser;
+///
+/// let tap_suite = TapParser::new().parse_suite(
+///     "Zoo",
+///     "TAP version 13\n1..1\nnot ok 1 Tiger\n  ---\n  at: src/zoo.rs:12\n  ...\n# Escaped",
+/// );
+///
+/// assert_eq!(
+///     github_annotations(&tap_suite),
+///     vec!["::error file=src/zoo.rs,line=12,title=not ok 1 Tiger::Escaped"]
+/// );
+/// ```
+pub fn github_annotations(suite: &TapSuite) -> Vec<String> {
+    let mut commands = Vec::new();
+    annotate(&suite.tests, "", true, &mut commands);
+    if let Some(reason) = &suite.bail_out {
+        commands.push(bail_out_command(reason));
+    }
+    commands
+}
+
+fn annotate(tests: &[TapTest], path: &str, top_level: bool, commands: &mut Vec<String>) {
+    for (i, test) in tests.iter().enumerate() {
+        let name = qualify(path, &test.name);
+        if !test.subtests.is_empty() {
+            if top_level {
+                commands.push(format!("::group::{}", escape_data(&test.name)));
+            }
+            annotate(&test.subtests, &name, false, commands);
+            if top_level {
+                commands.push("::endgroup::".to_string());
+            }
+        }
+        if test.is_failure() {
+            commands.push(error_command(i as i64 + 1, &name, test));
+        }
+    }
+}
+
+/// Produces workflow commands from a live TAP stream, one `TapEvent` at a time. A failure's command is held back until its diagnostics are all in, i.e. until the next line that isn't one of them.
+///
+/// Feed the TAP stream through to STDOUT alongside the commands and it still reads as valid TAP: consumers ignore the lines they don't understand.
+#[derive(Debug, Default)]
+pub struct TapGithubReporter {
+    pending: Option<(i64, TapTest)>,
+    // Reads the subtest stream in progress, if there is one
+    child: Option<Box<TapGithubReporter>>,
+    group: Option<String>,
+    nested: bool,
+}
+
+impl TapGithubReporter {
+    /// Make a reporter positioned at the start of a stream
+    pub fn new() -> TapGithubReporter {
+        TapGithubReporter::default()
+    }
+
+    /// Take in the next piece of a TAP stream, yielding any commands it completes.
+    pub fn event(&mut self, event: &TapEvent) -> Vec<String> {
+        match event {
+            TapEvent::Yaml(fields) => {
+                if let Some((_, test)) = self.pending.as_mut() {
+                    test.apply_yaml(fields);
+                }
+                Vec::new()
+            }
+            TapEvent::Diagnostic(diagnostic) => {
+                if let Some((_, test)) = self.pending.as_mut() {
+                    test.diagnostics.push(diagnostic.clone());
+                }
+                Vec::new()
+            }
+            TapEvent::Subtest(inner) => {
+                let mut commands = self.flush();
+                if self.child.is_none() {
+                    let name = match inner.as_ref() {
+                        TapEvent::Diagnostic(comment) => comment.strip_prefix("Subtest: "),
+                        _ => None,
+                    }
+                    .unwrap_or("Subtest")
+                    .to_string();
+                    if !self.nested {
+                        commands.push(format!("::group::{}", escape_data(&name)));
+                    }
+                    self.child = Some(Box::new(TapGithubReporter {
+                        group: Some(name),
+                        nested: true,
+                        ..Default::default()
+                    }));
+                }
+                if let Some(child) = self.child.as_mut() {
+                    commands.extend(child.event(inner));
+                }
+                commands
+            }
+            TapEvent::Test { number, test } => {
+                let mut commands = self.flush();
+                self.pending = Some((*number, test.as_ref().clone()));
+                commands.extend(self.close_subtest());
+                commands
+            }
+            TapEvent::BailOut(reason) => {
+                let mut commands = self.finish();
+                commands.push(bail_out_command(reason));
+                commands
+            }
+            _ => self.flush(),
+        }
+    }
+
+    /// Yield the commands still held back, at the end of the stream.
+    pub fn finish(&mut self) -> Vec<String> {
+        let mut commands = self.close_subtest();
+        commands.extend(self.flush());
+        commands
+    }
+
+    fn flush(&mut self) -> Vec<String> {
+        match self.pending.take() {
+            Some((number, test)) if test.is_failure() => {
+                let name = qualify(self.group.as_deref().unwrap_or_default(), &test.name);
+                vec![error_command(number, &name, &test)]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn close_subtest(&mut self) -> Vec<String> {
+        match self.child.take() {
+            Some(mut child) => {
+                let mut commands = child.finish();
+                if !self.nested {
+                    commands.push("::endgroup::".to_string());
+                }
+                commands
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+fn qualify(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{} / {}", path, name)
+    }
+}
+
+fn error_command(number: i64, name: &str, test: &TapTest) -> String {
+    let mut properties = Vec::new();
+    if let Some(location) = &test.location {
+        properties.push(format!("file={}", escape_property(&location.file)));
+        if location.line > 0 {
+            properties.push(format!("line={}", location.line));
+        }
+        if location.column > 0 {
+            properties.push(format!("col={}", location.column));
+        }
+    }
+    properties.push(format!(
+        "title={}",
+        escape_property(&format!("not ok {} {}", number, name))
+    ));
+
+    let mut message_lines = test.diagnostics.clone();
+    if let Some(comparison) = &test.comparison {
+        message_lines.extend(comparison.classic_lines());
+    }
+    let message = if message_lines.is_empty() {
+        "Test failed".to_string()
+    } else {
+        message_lines.join("\n")
+    };
+
+    format!(
+        "::error {}::{}",
+        properties.join(","),
+        escape_data(&message)
+    )
+}
+
+fn bail_out_command(reason: &str) -> String {
+    let message = if reason.is_empty() {
+        "Bail out!"
+    } else {
+        reason
+    };
+    format!("::error title=Bail out!::{}", escape_data(message))
+}
+
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{github_annotations, TapGithubReporter};
+    use crate::tap_parser::TapParser;
+
+    const STREAM: &str = "TAP version 14
+1..3
+ok 1 Panda
+not ok 2 Tiger
+  ---
+  at:
+    file: src/zoo.rs
+    line: 12
+    column: 5
+  ...
+# Escaped, 100% gone
+    # Subtest: Aquarium
+    1..2
+    not ok 1 Shark
+    ok 2 Octopus
+not ok 3 Aquarium
+Bail out! Flooded";
+
+    #[test]
+    fn test_github_annotations() {
+        let tap_suite = TapParser::new().parse_suite("Zoo", STREAM);
+
+        let expected = vec![
+            "::error file=src/zoo.rs,line=12,col=5,title=not ok 2 Tiger::Escaped, 100%25 gone",
+            "::group::Aquarium",
+            "::error title=not ok 1 Aquarium / Shark::Test failed",
+            "::endgroup::",
+            "::error title=not ok 3 Aquarium::Test failed",
+            "::error title=Bail out!::Flooded",
+        ];
+
+        assert_eq!(expected, github_annotations(&tap_suite));
+    }
+
+    #[test]
+    fn test_github_reporter_events() {
+        let mut parser = TapParser::new();
+        let mut reporter = TapGithubReporter::new();
+
+        let mut commands = Vec::new();
+        for line in STREAM.lines() {
+            if let Some(event) = parser.parse_line(line) {
+                commands.extend(reporter.event(&event));
+            }
+        }
+        commands.extend(reporter.finish());
+
+        let tap_suite = TapParser::new().parse_suite("Zoo", STREAM);
+        assert_eq!(github_annotations(&tap_suite), commands);
+    }
+}
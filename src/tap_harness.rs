@@ -0,0 +1,1449 @@
+//! `TapHarness` -- For running a set of tests and reporting on them as TAP
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::tap_capture;
+use crate::tap_directive::TapDirective;
+use crate::tap_fixture::{self, TapFixture};
+use crate::tap_location::TapLocation;
+use crate::tap_panic::catch_panic;
+use crate::tap_selection::TapSelection;
+use crate::tap_suite::TapSuite;
+use crate::tap_test::{format_duration_ms, TapTest};
+use crate::tap_test_builder::TapTestBuilder;
+use crate::tap_writer::TapWriter;
+use crate::tap_yaml::YamlValue;
+
+/// What a test hands back: nothing if it passed, an explanation if it didn't.
+pub type TestResult = Result<(), String>;
+
+type TestFn = Arc<dyn Fn() -> TapTest + Send + Sync>;
+
+type NestedFn = Arc<dyn Fn(&mut dyn FnMut(&str)) -> TapTest + Send + Sync>;
+
+/// How a test registered with `TapHarness::test_with` is run. The defaults are those of a test registered with `TapHarness::test`.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use testanything::tap_harness::{TapHarness, TestOptions};
+///
+/// let options = TestOptions::new()
+///     .with_timeout(Duration::from_secs(5))
+///     .with_attempts(3)
+///     .with_tag("network");
+///
+/// let mut harness = TapHarness::new("Zoo");
+/// harness.test_with("Feed the pandas", options, || Ok(()));
+///
+/// assert!(harness.run().tests[0].tags.contains("network"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TestOptions {
+    /// How long the test may take, whatever the harness's timeout is
+    pub timeout: Option<Duration>,
+    /// How many times the test may be run to get it to pass. Meant for tests which fail now and then through no fault of the code under test, like those which go over a network. Less than 1 counts as 1.
+    pub attempts: usize,
+    /// Tags, which the test's result carries and a selection can pick it out by
+    pub tags: BTreeSet<String>,
+}
+
+impl TestOptions {
+    /// Run the test once, with the harness's timeout, and no tags
+    pub fn new() -> TestOptions {
+        TestOptions::default()
+    }
+
+    /// Give the test no longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> TestOptions {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Run the test up to `attempts` times, stopping as soon as it passes
+    pub fn with_attempts(mut self, attempts: usize) -> TestOptions {
+        self.attempts = attempts;
+        self
+    }
+
+    /// Tag the test
+    pub fn with_tag(mut self, tag: &str) -> TestOptions {
+        self.tags.insert(tag.to_string());
+        self
+    }
+}
+
+/// A test as registered
+struct HarnessTest {
+    name: String,
+    location: TapLocation,
+    timeout: Option<Duration>,
+    /// How many times the test may be run to get it to pass
+    attempts: usize,
+    tags: BTreeSet<String>,
+    test: TestFn,
+    /// The same check, handing over the lines of the TAP it produces as they come, if it produces any
+    nested: Option<NestedFn>,
+}
+
+impl HarnessTest {
+    fn selected_by(&self, selection: &TapSelection) -> bool {
+        let tags = self.tags.iter().map(String::as_str).collect::<Vec<&str>>();
+        selection.selects(&self.name, &tags)
+    }
+}
+
+/// What came of going through one scheduled test
+struct Outcome {
+    tap_test: TapTest,
+    /// Why the run stops here, if it does
+    bail_out: Option<String>,
+    /// A failing test for each fixture which couldn't be torn down after it
+    teardowns: Vec<TapTest>,
+    /// Whether its subtests were printed as they ran
+    streamed: bool,
+}
+
+/// A named collection of tests to run. Each run is timed, so every `TapTest` it produces carries its duration. A test which panics fails, with the panic's message and location as diagnostics, and the run carries on.
+///
+/// Tests given a timeout run on a thread of their own. One which runs out of time fails with a "Timed out after Ns" diagnostic, and the run carries on without it, or bails out if asked to. Its thread can't be stopped, so it's left to finish in the background. That means it may still be using what a per-test fixture set up once the fixture is torn down, and the next test's setup may run alongside it; a test which can time out is best not left relying on a per-test fixture.
+///
+/// A test registered with retries is run again after failing, until it passes or runs out of attempts. One which times out isn't retried, since the attempt that timed out may still be running. Every attempt is recorded under `attempts`, and a test which passes after failing is flaky: it's ok, with diagnostics saying which attempts failed, or TODO if asked for.
+///
+/// A selection narrows the run down to some of the tests. The rest are left out, with the tests after them renumbered, or reported as `# SKIP filtered` without being run.
+///
+/// Fixtures set up what the tests need, and tear it down again, once around the whole run or around every test. If one can't be set up, the run bails out, or the tests which needed it are skipped if asked for. Each fixture which can't be torn down is reported as a failing test of its own, after all the others, so the plan comes last when there are fixtures. Fixtures are set up in this process, even when a test runs in a process of its own to capture its output.
+///
+/// Tests may run several at a time, unless there are per-test fixtures. Results are still numbered in the order the tests were registered, and by default emitted in that order too.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_harness::TapHarness;
+/// use testanything::tap_writer::TapWriter;
+///
+/// let mut harness = TapHarness::new("Example TAP harness");
+/// harness
+///     .test("Panda", || Ok(()))
+///     .test("Bamboo", || Err("Ran out of bamboo".to_string()));
+///
+/// // Print each result as soon as it's in
+/// let tap_suite = harness.run_with(&TapWriter::new("Example TAP harness"));
+///
+/// assert!(tap_suite.tests[0].duration.is_some());
+/// ```
+pub struct TapHarness {
+    /// The name of the harness, which becomes the name of the suite it produces
+    pub name: String,
+    tests: Vec<HarnessTest>,
+    /// The arguments to start this program with to run one test, when capturing output
+    capture_args: Option<Vec<String>>,
+    /// How long each test may take, unless it has a timeout of its own
+    timeout: Option<Duration>,
+    /// How long all the tests together may take
+    overall_timeout: Option<Duration>,
+    bail_out_on_timeout: bool,
+    /// How many tests may run at once
+    jobs: usize,
+    in_completion_order: bool,
+    live_subtests: bool,
+    flaky_as_todo: bool,
+    selection: Option<TapSelection>,
+    fixtures: Vec<TapFixture>,
+    fixtures_each: Vec<TapFixture>,
+    skip_on_setup_failure: bool,
+}
+
+impl TapHarness {
+    /// Make an empty harness
+    pub fn new(name: &str) -> TapHarness {
+        TapHarness {
+            name: name.to_string(),
+            tests: Vec::new(),
+            capture_args: None,
+            timeout: None,
+            overall_timeout: None,
+            bail_out_on_timeout: false,
+            jobs: 1,
+            in_completion_order: false,
+            live_subtests: false,
+            flaky_as_todo: false,
+            selection: None,
+            fixtures: Vec::new(),
+            fixtures_each: Vec::new(),
+            skip_on_setup_failure: false,
+        }
+    }
+
+    /// Run up to this many tests at once, each on a thread of its own. 0 means as many as there are CPUs. Ignored if there are per-test fixtures.
+    pub fn jobs(&mut self, jobs: usize) -> &mut TapHarness {
+        self.jobs = if jobs == 0 {
+            thread::available_parallelism().map_or(1, |jobs| jobs.get())
+        } else {
+            jobs
+        };
+        self
+    }
+
+    /// Emit each result as soon as it's in, rather than holding it back until those registered before it are done. It keeps its number, so the numbers may come out of order.
+    pub fn in_completion_order(&mut self) -> &mut TapHarness {
+        self.in_completion_order = true;
+        self
+    }
+
+    /// When running with a writer, print the TAP of nested checks as it comes, rather than once each is done. Only for a check run one test at a time, without a timeout or retries, in this process, since otherwise its lines could come out among others or more than once.
+    pub fn live_subtests(&mut self) -> &mut TapHarness {
+        self.live_subtests = true;
+        self
+    }
+
+    /// Give each test this long to finish, unless it was registered with a timeout of its own
+    pub fn timeout(&mut self, timeout: Duration) -> &mut TapHarness {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Give the whole run this long to finish. Once it's up, the test running times out and any left fail without being run.
+    pub fn overall_timeout(&mut self, timeout: Duration) -> &mut TapHarness {
+        self.overall_timeout = Some(timeout);
+        self
+    }
+
+    /// Bail out of the run as soon as a test times out, rather than carrying on with the rest
+    pub fn bail_out_on_timeout(&mut self) -> &mut TapHarness {
+        self.bail_out_on_timeout = true;
+        self
+    }
+
+    /// Set `fixture` up before the first test runs, and tear it down after the last. Fixtures are set up in the order they were added.
+    pub fn fixture(&mut self, fixture: TapFixture) -> &mut TapHarness {
+        self.fixtures.push(fixture);
+        self
+    }
+
+    /// Set `fixture` up before every test, and tear it down after each. A fixture hands nothing to the test, which finds what it set up in shared state, like a directory or a server; so that two tests never share it at once, a harness with any per-test fixtures runs its tests one at a time, whatever `jobs` says.
+    pub fn fixture_each(&mut self, fixture: TapFixture) -> &mut TapHarness {
+        self.fixtures_each.push(fixture);
+        self
+    }
+
+    /// Skip the tests whose fixtures couldn't be set up, giving the reason, rather than bailing out
+    pub fn skip_on_setup_failure(&mut self) -> &mut TapHarness {
+        self.skip_on_setup_failure = true;
+        self
+    }
+
+    /// Run only the tests `selection` selects
+    pub fn select(&mut self, selection: TapSelection) -> &mut TapHarness {
+        self.selection = Some(selection);
+        self
+    }
+
+    /// Mark a test which only passed after being retried as TODO, so it stands out without failing the run
+    pub fn flaky_as_todo(&mut self) -> &mut TapHarness {
+        self.flaky_as_todo = true;
+        self
+    }
+
+    /// Keep what each test prints out of the TAP stream, attaching it to the test's result under `output` instead.
+    ///
+    /// Each test runs in a process of its own: this program, started again with the same arguments and `TAP_CAPTURE_TEST` in its environment. It must get back to this harness, and when it does, the harness runs just that one test and exits. Anything the program prints before then is left out. A test which brings its process down fails, rather than the whole run.
+    pub fn capture_output(&mut self) -> &mut TapHarness {
+        self.capture_output_with_args(std::env::args().skip(1))
+    }
+
+    /// Like `capture_output`, but start the program with these arguments rather than the ones it was given. Useful when those wouldn't lead back to this harness, like under `cargo test`.
+    pub fn capture_output_with_args<I, S>(&mut self, args: I) -> &mut TapHarness
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.capture_args = Some(args.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Register a test. Tests run in the order they were registered, and each is located where it was registered.
+    #[track_caller]
+    pub fn test<S, F>(&mut self, name: S, test: F) -> &mut TapHarness
+    where
+        S: Into<String>,
+        F: Fn() -> TestResult + Send + Sync + 'static,
+    {
+        self.check(name, move || match test() {
+            Ok(()) => TapTestBuilder::new().passed(true).finalize(),
+            Err(reason) => TapTestBuilder::new()
+                .passed(false)
+                .diagnostics(&[&reason])
+                .finalize(),
+        })
+    }
+
+    /// Register a check which produces a `TapTest` of its own, like a property, snapshot or benchmark. The test takes the registered name, and the registered location and its running time unless it has its own.
+    #[track_caller]
+    pub fn check<S, F>(&mut self, name: S, check: F) -> &mut TapHarness
+    where
+        S: Into<String>,
+        F: Fn() -> TapTest + Send + Sync + 'static,
+    {
+        self.tests.push(HarnessTest {
+            name: name.into(),
+            location: TapLocation::caller(),
+            timeout: None,
+            attempts: 1,
+            tags: BTreeSet::new(),
+            test: Arc::new(check),
+            nested: None,
+        });
+        self
+    }
+
+    /// Register a check which produces TAP of its own, like a command printing TAP, to come out as its subtests. It hands each line of that, as a subtest, to the function it's given, which prints them as they come with `live_subtests`, and otherwise does nothing.
+    #[track_caller]
+    pub fn nested_check<S, F>(&mut self, name: S, check: F) -> &mut TapHarness
+    where
+        S: Into<String>,
+        F: Fn(&mut dyn FnMut(&str)) -> TapTest + Send + Sync + 'static,
+    {
+        let nested: NestedFn = Arc::new(check);
+        let quiet = nested.clone();
+        self.check(name, move || quiet(&mut |_| {}));
+        if let Some(registered) = self.tests.last_mut() {
+            registered.nested = Some(nested);
+        }
+        self
+    }
+
+    /// Register a test with a timeout, retries or tags of its own
+    #[track_caller]
+    pub fn test_with<S, F>(&mut self, name: S, options: TestOptions, test: F) -> &mut TapHarness
+    where
+        S: Into<String>,
+        F: Fn() -> TestResult + Send + Sync + 'static,
+    {
+        self.test(name, test);
+        if let Some(registered) = self.tests.last_mut() {
+            registered.timeout = options.timeout;
+            registered.attempts = options.attempts.max(1);
+            registered.tags = options.tags;
+        }
+        self
+    }
+
+    /// Run every test and collect the results, printing nothing.
+    pub fn run(&self) -> TapSuite {
+        self.run_child_test();
+        let (tests, bail_out) = self.run_fixtured(false, |_, _, _| {});
+        TapSuite {
+            name: self.name.clone(),
+            tests,
+            bail_out,
+            ..Default::default()
+        }
+    }
+
+    /// Run every test, emitting the plan and then each result through `writer` as soon as it's known. The plan comes up front, unless there are fixtures whose teardowns may add to the tests, when it comes last. The results are also collected and handed back, in the order the tests were registered.
+    pub fn run_with(&self, writer: &TapWriter) -> TapSuite {
+        self.run_child_test();
+        writer.version();
+        let plan_first = self.fixtures.is_empty() && self.fixtures_each.is_empty();
+        if plan_first {
+            writer.plan(1, self.scheduled().len() as i32);
+        }
+
+        let (tests, bail_out) = self.run_fixtured(self.live_subtests, |i, tap_test, streamed| {
+            if streamed {
+                writer.test_after_subtests(i as i32 + 1, tap_test)
+            } else {
+                writer.test(i as i32 + 1, tap_test)
+            }
+        });
+        match &bail_out {
+            Some(message) => writer.bail_out_with_message(message),
+            None if !plan_first => writer.plan(1, tests.len() as i32),
+            None => {}
+        }
+
+        TapSuite {
+            name: self.name.clone(),
+            tests,
+            version: writer.version,
+            bail_out,
+            plan: None,
+        }
+    }
+
+    /// Run the tests within the harness's fixtures, handing each result to `emit` as it's due, along with its position and whether its subtests were printed as they ran, which they are if `live`. Gives back the results, followed by those of any teardowns which failed, and why the run bailed out, if it did. Fixtures are torn down even then, but their failures go unreported.
+    fn run_fixtured<F: FnMut(usize, &TapTest, bool)>(
+        &self,
+        live: bool,
+        mut emit: F,
+    ) -> (Vec<TapTest>, Option<String>) {
+        let (set_up, failure) = tap_fixture::set_up_all(&self.fixtures);
+        let (mut tests, bail_out) = match failure {
+            None => self.run_all(live, &mut emit),
+            Some(reason) if self.skip_on_setup_failure => {
+                let tests = self
+                    .scheduled()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, index)| {
+                        let tap_test = self.setup_failed(index, &reason);
+                        emit(i, &tap_test, false);
+                        tap_test
+                    })
+                    .collect();
+                (tests, None)
+            }
+            Some(reason) => (Vec::new(), Some(reason)),
+        };
+
+        let teardowns = tap_fixture::tear_down_all(&self.fixtures[..set_up], None);
+        if bail_out.is_none() {
+            for tap_test in teardowns {
+                emit(tests.len(), &tap_test, false);
+                tests.push(tap_test);
+            }
+        }
+        (tests, bail_out)
+    }
+
+    /// Run the tests, handing each result to `emit` as it's due, along with its position among those scheduled and whether its subtests were printed as they ran. Those of nested checks are if `live` and the tests run one at a time. Gives back the results in registration order, followed by those of any teardowns after them which failed, and why the run bailed out, if it did.
+    fn run_all<F: FnMut(usize, &TapTest, bool)>(
+        &self,
+        live: bool,
+        mut emit: F,
+    ) -> (Vec<TapTest>, Option<String>) {
+        let started = Instant::now();
+        let scheduled = self.scheduled();
+        // Tests running at once would set up and tear down the same per-test fixtures under each other
+        if self.jobs <= 1 || scheduled.len() <= 1 || !self.fixtures_each.is_empty() {
+            let mut tests = Vec::with_capacity(scheduled.len());
+            let mut teardowns = Vec::new();
+            for (i, &index) in scheduled.iter().enumerate() {
+                let outcome = self.run_scheduled(index, started, live);
+                emit(i, &outcome.tap_test, outcome.streamed);
+                tests.push(outcome.tap_test);
+                teardowns.extend(outcome.teardowns);
+                if outcome.bail_out.is_some() {
+                    return (tests, outcome.bail_out);
+                }
+            }
+            return (self.append_teardowns(tests, teardowns, emit), None);
+        }
+
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let mut results = (0..scheduled.len()).map(|_| None).collect::<Vec<_>>();
+        let mut emitted = vec![false; scheduled.len()];
+        let mut bail_out = None;
+
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for _ in 0..self.jobs.min(scheduled.len()) {
+                let (sender, next, stop, scheduled) = (sender.clone(), &next, &stop, &scheduled);
+                scope.spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= scheduled.len()
+                            || sender
+                                .send((i, self.run_scheduled(scheduled[i], started, false)))
+                                .is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            let mut next_in_order = 0;
+            for (i, result) in receiver {
+                if bail_out.is_some() {
+                    continue;
+                }
+                results[i] = Some(result);
+                let due = if self.in_completion_order {
+                    i..i + 1
+                } else {
+                    let first = next_in_order;
+                    while results.get(next_in_order).is_some_and(Option::is_some) {
+                        next_in_order += 1;
+                    }
+                    first..next_in_order
+                };
+                for j in due {
+                    if let Some(outcome) = &results[j] {
+                        emit(j, &outcome.tap_test, outcome.streamed);
+                        emitted[j] = true;
+                        if outcome.bail_out.is_some() {
+                            bail_out.clone_from(&outcome.bail_out);
+                            stop.store(true, Ordering::Relaxed);
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        let (tests, teardowns): (Vec<TapTest>, Vec<Vec<TapTest>>) = results
+            .into_iter()
+            .zip(emitted)
+            .filter_map(|(result, emitted)| result.filter(|_| emitted))
+            .map(|outcome| (outcome.tap_test, outcome.teardowns))
+            .unzip();
+        if bail_out.is_some() {
+            return (tests, bail_out);
+        }
+        let teardowns = teardowns.into_iter().flatten().collect();
+        (self.append_teardowns(tests, teardowns, emit), None)
+    }
+
+    /// Emit the failed teardowns after the tests, numbered on from them
+    fn append_teardowns<F: FnMut(usize, &TapTest, bool)>(
+        &self,
+        mut tests: Vec<TapTest>,
+        teardowns: Vec<TapTest>,
+        mut emit: F,
+    ) -> Vec<TapTest> {
+        for tap_test in teardowns {
+            emit(tests.len(), &tap_test, false);
+            tests.push(tap_test);
+        }
+        tests
+    }
+
+    /// The indexes of the tests to go through: the selected ones, or all of them if the rest are to be skipped
+    fn scheduled(&self) -> Vec<usize> {
+        (0..self.tests.len())
+            .filter(|&index| match &self.selection {
+                Some(selection) if !selection.skips_deselected() => {
+                    self.tests[index].selected_by(selection)
+                }
+                _ => true,
+            })
+            .collect()
+    }
+
+    /// Run the test at `index` within its fixtures, unless it wasn't selected, and tag the result
+    fn run_scheduled(&self, index: usize, started: Instant, live: bool) -> Outcome {
+        let registered = &self.tests[index];
+        let mut outcome = match &self.selection {
+            Some(selection) if !registered.selected_by(selection) => Outcome {
+                tap_test: selection.deselected(&registered.name),
+                bail_out: None,
+                teardowns: Vec::new(),
+                streamed: false,
+            },
+            _ => self.run_fixtured_test(index, started, live),
+        };
+        outcome
+            .tap_test
+            .tags
+            .extend(registered.tags.iter().cloned());
+        outcome
+    }
+
+    /// Set up the fixtures for every test, run the test at `index` if they were, printing its subtests as they come if `live` and it can, and tear them down again
+    fn run_fixtured_test(&self, index: usize, started: Instant, live: bool) -> Outcome {
+        let registered = &self.tests[index];
+        let (set_up, failure) = tap_fixture::set_up_all(&self.fixtures_each);
+        let mut streamed = false;
+        let (tap_test, bail_out) = match (failure, &registered.nested) {
+            (None, Some(nested)) if live && self.can_stream(registered) => {
+                let nested = nested.clone();
+                let check: TestFn = Arc::new(move || nested(&mut |line| println!("{}", line)));
+                streamed = true;
+                (
+                    run_test(&registered.name, &registered.location, &check),
+                    None,
+                )
+            }
+            (None, _) => {
+                let (tap_test, timed_out) = self.run_attempts(index, started);
+                let bail_out =
+                    (timed_out && self.bail_out_on_timeout).then(|| self.bail_out_message(index));
+                (tap_test, bail_out)
+            }
+            (Some(reason), _) => {
+                let bail_out = (!self.skip_on_setup_failure)
+                    .then(|| format!("{} for {}", reason, registered.name));
+                (self.setup_failed(index, &reason), bail_out)
+            }
+        };
+        Outcome {
+            tap_test,
+            bail_out,
+            teardowns: tap_fixture::tear_down_all(
+                &self.fixtures_each[..set_up],
+                Some(&registered.name),
+            ),
+            streamed,
+        }
+    }
+
+    /// Can this test run with nothing between it and the stream? Not if it may be cut short, run again, or run elsewhere.
+    fn can_stream(&self, registered: &HarnessTest) -> bool {
+        registered.attempts == 1
+            && registered.timeout.or(self.timeout).is_none()
+            && self.overall_timeout.is_none()
+            && self.capture_args.is_none()
+    }
+
+    /// The result of the test at `index` when a fixture it needed couldn't be set up: skipped, or failed if the run is to bail out
+    fn setup_failed(&self, index: usize, reason: &str) -> TapTest {
+        let registered = &self.tests[index];
+        let mut builder = TapTestBuilder::new();
+        builder
+            .name(registered.name.as_str())
+            .location(registered.location.clone());
+        if self.skip_on_setup_failure {
+            builder.passed(true).skip(reason);
+        } else {
+            builder.passed(false).diagnostics(&[reason]);
+        }
+        builder.finalize()
+    }
+
+    /// Run the test at `index`, retrying it if it's allowed more than one attempt, with whatever time is left of a run which started at `started`. Also says whether it timed out.
+    fn run_attempts(&self, index: usize, started: Instant) -> (TapTest, bool) {
+        let registered = &self.tests[index];
+        let remaining = self
+            .overall_timeout
+            .map(|overall| overall.saturating_sub(started.elapsed()));
+        if remaining == Some(Duration::ZERO) {
+            let diagnostic = format!(
+                "Not run: the whole run timed out after {}s",
+                seconds(self.overall_timeout.unwrap_or_default())
+            );
+            let tap_test = TapTestBuilder::new()
+                .name(registered.name.as_str())
+                .location(registered.location.clone())
+                .passed(false)
+                .diagnostics(&[&diagnostic])
+                .finalize();
+            return (tap_test, true);
+        }
+
+        let mut attempts = Vec::new();
+        let mut timed_out = false;
+        loop {
+            let remaining = self
+                .overall_timeout
+                .map(|overall| overall.saturating_sub(started.elapsed()));
+            // Out of time for another go, so the last attempt stands
+            if !attempts.is_empty() && remaining == Some(Duration::ZERO) {
+                break;
+            }
+            let timeout = match (registered.timeout.or(self.timeout), remaining) {
+                (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+                (timeout, remaining) => timeout.or(remaining),
+            };
+            let (tap_test, last_timed_out) = self.run_one(index, timeout);
+            let passed = tap_test.passed;
+            attempts.push(tap_test);
+            timed_out = last_timed_out;
+            if passed || timed_out || attempts.len() >= registered.attempts {
+                break;
+            }
+        }
+
+        (self.settle(registered, attempts, timed_out), timed_out)
+    }
+
+    /// Make one result out of every attempt at a test. The last attempt decides it, and those before it are recorded under `attempts`. If it `timed_out`, there were no more.
+    fn settle(
+        &self,
+        registered: &HarnessTest,
+        mut attempts: Vec<TapTest>,
+        timed_out: bool,
+    ) -> TapTest {
+        let cut_short = timed_out && attempts.len() < registered.attempts;
+        let mut tap_test = attempts.pop().unwrap_or_default();
+        if cut_short {
+            tap_test.diagnostics.push(format!(
+                "Not retried, as attempt {} timed out",
+                attempts.len() + 1
+            ));
+        }
+        if attempts.is_empty() {
+            return tap_test;
+        }
+        attempts.push(tap_test.clone());
+
+        let failures = attempts
+            .iter()
+            .enumerate()
+            .filter(|(_, attempt)| !attempt.passed)
+            .map(|(i, attempt)| {
+                let reason = attempt.diagnostics.first().map_or("", String::as_str);
+                format!("Attempt {} failed: {}", i + 1, reason)
+            });
+        if tap_test.passed {
+            let passed_on = format!(
+                "passed on attempt {} of {}",
+                attempts.len(),
+                registered.attempts
+            );
+            let mut diagnostics = vec![format!("Flaky: {}", passed_on)];
+            diagnostics.extend(failures);
+            diagnostics.append(&mut tap_test.diagnostics);
+            tap_test.diagnostics = diagnostics;
+            if self.flaky_as_todo {
+                tap_test.directive = Some(TapDirective::Todo(format!("flaky, {}", passed_on)));
+            }
+        } else if !cut_short {
+            tap_test
+                .diagnostics
+                .push(format!("Failed all {} attempts", attempts.len()));
+        }
+
+        tap_test.duration = attempts.iter().map(|attempt| attempt.duration).sum();
+        let attempts = attempts
+            .iter()
+            .map(|attempt| {
+                let mut fields = vec![(
+                    "passed".to_string(),
+                    YamlValue::scalar(attempt.passed.to_string()),
+                )];
+                if let Some(duration) = attempt.duration {
+                    fields.push((
+                        "duration_ms".to_string(),
+                        YamlValue::Scalar(format_duration_ms(duration)),
+                    ));
+                }
+                if let (false, Some(reason)) = (attempt.passed, attempt.diagnostics.first()) {
+                    fields.push(("reason".to_string(), YamlValue::scalar(reason.as_str())));
+                }
+                YamlValue::Map(fields)
+            })
+            .collect();
+        tap_test
+            .yaml
+            .push(("attempts".to_string(), YamlValue::List(attempts)));
+        tap_test
+    }
+
+    fn bail_out_message(&self, index: usize) -> String {
+        format!("{} timed out", self.tests[index].name)
+    }
+
+    /// Run the test at `index` within `timeout`, in a process of its own if capturing output. Also says whether it timed out.
+    fn run_one(&self, index: usize, timeout: Option<Duration>) -> (TapTest, bool) {
+        let registered = &self.tests[index];
+        let Some(args) = &self.capture_args else {
+            let test = registered.test.clone();
+            let (name, location) = (registered.name.clone(), registered.location.clone());
+            return run_within(registered, timeout, move || {
+                (run_test(&name, &location, &test), false)
+            });
+        };
+
+        let (harness_name, args) = (self.name.clone(), args.clone());
+        let (name, location) = (registered.name.clone(), registered.location.clone());
+        // Once this gives up on the test, its process must be killed, not left running
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        run_within(registered, timeout, move || {
+            let started = Instant::now();
+            let Some((mut tap_test, output)) =
+                tap_capture::run_in_parent(&harness_name, index, &args, deadline)
+            else {
+                return (
+                    timed_out(&name, &location, timeout.unwrap_or_default()),
+                    true,
+                );
+            };
+            tap_test.name = name;
+            tap_test.location = tap_test.location.or(Some(location));
+            tap_test.duration = tap_test.duration.or_else(|| Some(started.elapsed()));
+            if !output.is_empty() {
+                tap_test.yaml.push(("output".to_string(), output.to_yaml()));
+            }
+            (tap_test, false)
+        })
+    }
+
+    /// If this process was started to run one of this harness's tests, run it and exit
+    fn run_child_test(&self) {
+        if self.capture_args.is_none() {
+            return;
+        }
+        if let Some(index) = tap_capture::child_test(&self.name) {
+            tap_capture::run_in_child(|| match self.tests.get(index) {
+                // The parent keeps time, killing this process if it runs out
+                Some(registered) => {
+                    run_test(&registered.name, &registered.location, &registered.test)
+                }
+                None => TapTestBuilder::new()
+                    .name(format!("Test {}", index))
+                    .passed(false)
+                    .diagnostics(&["No such test in this harness"])
+                    .finalize(),
+            });
+        }
+    }
+}
+
+impl fmt::Debug for TapHarness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = self
+            .tests
+            .iter()
+            .map(|registered| &registered.name)
+            .collect::<Vec<&String>>();
+        f.debug_struct("TapHarness")
+            .field("name", &self.name)
+            .field("tests", &names)
+            .field("capture_output", &self.capture_args.is_some())
+            .field("jobs", &self.jobs)
+            .finish()
+    }
+}
+
+/// Run `run` on a thread of its own, giving up on it after `timeout`. Without a timeout, it just runs. Gives back the test, and whether it timed out, which `run` may say too.
+fn run_within<F>(registered: &HarnessTest, timeout: Option<Duration>, run: F) -> (TapTest, bool)
+where
+    F: FnOnce() -> (TapTest, bool) + Send + 'static,
+{
+    let Some(timeout) = timeout else {
+        return run();
+    };
+
+    let (sender, receiver) = mpsc::channel();
+    let spawned = thread::Builder::new()
+        .name(registered.name.clone())
+        .spawn(move || {
+            // Nobody's listening any more if the test timed out
+            let _ = sender.send(run());
+        });
+    if let Err(reason) = spawned {
+        let diagnostic = format!("Couldn't start a thread for the test: {}", reason);
+        let tap_test = TapTestBuilder::new()
+            .name(registered.name.as_str())
+            .location(registered.location.clone())
+            .passed(false)
+            .diagnostics(&[&diagnostic])
+            .finalize();
+        return (tap_test, false);
+    }
+
+    receiver.recv_timeout(timeout).unwrap_or_else(|_| {
+        (
+            timed_out(&registered.name, &registered.location, timeout),
+            true,
+        )
+    })
+}
+
+/// The failing test for one which ran out of `timeout`
+fn timed_out(name: &str, location: &TapLocation, timeout: Duration) -> TapTest {
+    let diagnostic = format!("Timed out after {}s", seconds(timeout));
+    TapTestBuilder::new()
+        .name(name)
+        .location(location.clone())
+        .passed(false)
+        .duration(timeout)
+        .diagnostics(&[&diagnostic])
+        .finalize()
+}
+
+/// Like "2" or "0.05"
+fn seconds(duration: Duration) -> String {
+    duration.as_secs_f64().to_string()
+}
+
+fn run_test(name: &str, location: &TapLocation, test: &TestFn) -> TapTest {
+    let started = Instant::now();
+    let mut tap_test = match catch_panic(|| test()) {
+        Ok(tap_test) => tap_test,
+        Err(details) => {
+            let diagnostics = details.diagnostics();
+            TapTestBuilder::new()
+                .passed(false)
+                .diagnostics(
+                    &diagnostics
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<&str>>(),
+                )
+                .finalize()
+        }
+    };
+    tap_test.name = name.to_string();
+    tap_test.location = tap_test.location.or_else(|| Some(location.clone()));
+    tap_test.duration = tap_test.duration.or_else(|| Some(started.elapsed()));
+    tap_test
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TapHarness, TestOptions, TestResult};
+    use crate::tap_comparison::TapComparison;
+    use crate::tap_directive::TapDirective;
+    use crate::tap_fixture::TapFixture;
+    use crate::tap_property::{ints, TapProperty};
+    use crate::tap_selection::TapSelection;
+    use crate::tap_test_builder::TapTestBuilder;
+    use crate::tap_yaml::YamlValue;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_run() {
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            .test("Panda", || Ok(()))
+            .test("Bamboo", || Err("Ran out of bamboo".to_string()));
+
+        let tap_suite = harness.run();
+
+        assert_eq!(tap_suite.name, "Example TAP harness");
+        assert!(tap_suite.tests[0].passed);
+        assert!(!tap_suite.tests[1].passed);
+        assert_eq!(tap_suite.tests[1].diagnostics, vec!["Ran out of bamboo"]);
+    }
+
+    #[test]
+    fn test_run_records_locations() {
+        let mut harness = TapHarness::new("Example TAP harness");
+        let line = line!() + 1;
+        harness.test("Panda", || Ok(()));
+
+        let location = harness.run().tests[0].location.clone().unwrap();
+
+        assert_eq!(location.file, file!());
+        assert_eq!(location.line, line);
+    }
+
+    #[test]
+    fn test_run_catches_panics() {
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            .test("Panda", || panic!("Out of bamboo"))
+            .test("Bamboo", || Ok(()));
+
+        let tap_suite = harness.run();
+
+        assert!(!tap_suite.tests[0].passed);
+        assert!(tap_suite.tests[0].diagnostics[0].starts_with("Panicked at"));
+        assert_eq!(tap_suite.tests[0].diagnostics[1], "  Out of bamboo");
+        assert!(tap_suite.tests[1].passed);
+    }
+
+    #[test]
+    fn test_capture_output() {
+        let mut harness = TapHarness::new("Capturing TAP harness");
+        harness
+            .capture_output_with_args([
+                "tap_harness::tests::test_capture_output",
+                "--exact",
+                "--nocapture",
+            ])
+            .test("Chatty panda", || {
+                println!("Hello from the panda");
+                eprintln!("Bamboo is running low");
+                Ok(())
+            })
+            .test("Quiet panda", || Err("Said nothing\nat all".to_string()))
+            .test("Vanishing panda", || std::process::exit(3))
+            .check("Unfinished panda", || {
+                TapTestBuilder::new()
+                    .passed(false)
+                    .todo("Not grown yet")
+                    .finalize()
+            })
+            .check("Weighed panda", || {
+                TapTestBuilder::new()
+                    .passed(false)
+                    .comparison(TapComparison::equal("99kg", "100kg"))
+                    .metadata("scale", "zoo")
+                    .finalize()
+            });
+
+        let tap_suite = harness.run();
+
+        let chatty = &tap_suite.tests[0];
+        assert!(chatty.passed);
+        let output = chatty.yaml_value("output").unwrap();
+        assert_eq!(
+            output.get("stdout").and_then(|s| s.as_str()),
+            Some("Hello from the panda\n")
+        );
+        assert_eq!(
+            output.get("stderr").and_then(|s| s.as_str()),
+            Some("Bamboo is running low\n")
+        );
+
+        assert!(!tap_suite.tests[1].passed);
+        assert_eq!(tap_suite.tests[1].diagnostics, vec!["Said nothing\nat all"]);
+        assert!(tap_suite.tests[1].yaml_value("output").is_none());
+
+        assert!(!tap_suite.tests[2].passed);
+        assert!(tap_suite.tests[2].diagnostics[0].contains("exited"));
+
+        let unfinished = &tap_suite.tests[3];
+        assert!(!unfinished.is_failure());
+        assert_eq!(
+            unfinished.directive,
+            Some(TapDirective::Todo("Not grown yet".to_string()))
+        );
+
+        let weighed = &tap_suite.tests[4];
+        assert_eq!(
+            weighed.comparison,
+            Some(TapComparison::equal("99kg", "100kg"))
+        );
+        assert_eq!(weighed.metadata["scale"], "zoo");
+        assert!(weighed.duration.is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_capture_output_timeout() {
+        let marker = |parent: u32| std::env::temp_dir().join(format!("tap-capture-{}", parent));
+        let mut harness = TapHarness::new("Timed capturing TAP harness");
+        harness
+            .capture_output_with_args([
+                "tap_harness::tests::test_capture_output_timeout",
+                "--exact",
+                "--nocapture",
+            ])
+            // The overall timeout applies to the process, not to the test inside it
+            .overall_timeout(Duration::from_millis(100))
+            .test("Sleepy panda", move || {
+                thread::sleep(Duration::from_millis(500));
+                std::fs::write(marker(std::os::unix::process::parent_id()), "Awake")
+                    .map_err(|reason| reason.to_string())
+            });
+        let marker = marker(std::process::id());
+        let _ = std::fs::remove_file(&marker);
+
+        let tap_suite = harness.run();
+        thread::sleep(Duration::from_secs(1));
+
+        assert!(tap_suite.tests[0].diagnostics[0].starts_with("Timed out after"));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            .timeout(Duration::from_millis(50))
+            .test("Sleepy panda", || {
+                thread::sleep(Duration::from_secs(1));
+                Ok(())
+            })
+            .test_with(
+                "Patient panda",
+                TestOptions::new().with_timeout(Duration::from_secs(5)),
+                || {
+                    thread::sleep(Duration::from_millis(100));
+                    Ok(())
+                },
+            )
+            .test("Panicking panda", || panic!("Out of bamboo"));
+
+        let tap_suite = harness.run();
+
+        assert!(!tap_suite.tests[0].passed);
+        assert_eq!(
+            tap_suite.tests[0].diagnostics,
+            vec!["Timed out after 0.05s"]
+        );
+        assert!(tap_suite.tests[1].passed);
+        assert_eq!(tap_suite.tests[2].diagnostics[1], "  Out of bamboo");
+        assert_eq!(tap_suite.bail_out, None);
+    }
+
+    #[test]
+    fn test_overall_timeout_and_bail_out() {
+        let sleepy = || {
+            thread::sleep(Duration::from_millis(100));
+            Ok(())
+        };
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            .overall_timeout(Duration::from_millis(150))
+            .test("First panda", sleepy)
+            .test("Second panda", sleepy)
+            .test("Third panda", sleepy);
+
+        let tap_suite = harness.run();
+        assert!(tap_suite.tests[0].passed);
+        assert!(tap_suite.tests[1].diagnostics[0].starts_with("Timed out after"));
+        assert_eq!(
+            tap_suite.tests[2].diagnostics,
+            vec!["Not run: the whole run timed out after 0.15s"]
+        );
+
+        harness.bail_out_on_timeout();
+        let tap_suite = harness.run();
+        assert_eq!(tap_suite.tests.len(), 2);
+        assert_eq!(
+            tap_suite.bail_out.as_deref(),
+            Some("Second panda timed out")
+        );
+    }
+
+    #[test]
+    fn test_failures_that_only_look_timed_out() {
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            .timeout(Duration::from_secs(5))
+            .bail_out_on_timeout()
+            .test("Panda", || {
+                Err("Timed out after 1s waiting for bamboo".to_string())
+            })
+            .test("Tiger", || Ok(()));
+
+        let tap_suite = harness.run();
+        assert_eq!(tap_suite.tests.len(), 2);
+        assert_eq!(tap_suite.bail_out, None);
+    }
+
+    #[test]
+    fn test_parallel_runs() {
+        let mut harness = TapHarness::new("Example TAP harness");
+        for (name, millis) in [
+            ("Slow", 300),
+            ("Quick", 100),
+            ("Middling", 200),
+            ("Instant", 0),
+        ] {
+            harness.test(name, move || {
+                thread::sleep(Duration::from_millis(millis));
+                Ok(())
+            });
+        }
+        harness.jobs(4);
+
+        let started = Instant::now();
+        let mut order = Vec::new();
+        let (tests, bail_out) = harness.run_all(false, |i, _, _| order.push(i));
+        assert!(started.elapsed() < Duration::from_millis(550));
+        assert_eq!(order, vec![0, 1, 2, 3]);
+        assert_eq!(tests[2].name, "Middling");
+        assert_eq!(bail_out, None);
+
+        harness.in_completion_order();
+        let mut order = Vec::new();
+        let (tests, _) = harness.run_all(false, |i, _, _| order.push(i));
+        assert_eq!(order, vec![3, 1, 2, 0]);
+        assert_eq!(tests[0].name, "Slow");
+    }
+
+    #[test]
+    fn test_live_subtests() {
+        let nested = |emit: &mut dyn FnMut(&str)| {
+            emit("    1..1");
+            emit("    ok 1 Cub");
+            TapTestBuilder::new().passed(true).finalize()
+        };
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            .nested_check("Pandas", nested)
+            .test("Bamboo", || Ok(()));
+
+        let mut streamed = Vec::new();
+        let (tests, _) = harness.run_all(true, |_, _, live| streamed.push(live));
+        assert_eq!(streamed, vec![true, false]);
+        assert_eq!(tests[0].name, "Pandas");
+
+        streamed.clear();
+        harness.run_all(false, |_, _, live| streamed.push(live));
+        assert_eq!(streamed, vec![false, false]);
+
+        // Its lines could come out after it timed out
+        streamed.clear();
+        harness.timeout(Duration::from_secs(5));
+        harness.run_all(true, |_, _, live| streamed.push(live));
+        assert_eq!(streamed, vec![false, false]);
+    }
+
+    #[test]
+    fn test_retries() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            .test_with(
+                "Flaky panda",
+                TestOptions::new().with_attempts(3),
+                move || match counted.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => Err("Connection reset".to_string()),
+                    _ => Ok(()),
+                },
+            )
+            .test_with("Broken panda", TestOptions::new().with_attempts(2), || {
+                Err("Out of bamboo".to_string())
+            });
+
+        let tap_suite = harness.run();
+
+        let flaky = &tap_suite.tests[0];
+        assert!(flaky.passed);
+        assert!(flaky.is_flaky());
+        assert_eq!(flaky.attempts(), 3);
+        assert_eq!(
+            flaky.diagnostics,
+            vec![
+                "Flaky: passed on attempt 3 of 3",
+                "Attempt 1 failed: Connection reset",
+                "Attempt 2 failed: Connection reset",
+            ]
+        );
+        let Some(YamlValue::List(attempts)) = flaky.yaml_value("attempts") else {
+            panic!("No attempts recorded");
+        };
+        assert_eq!(
+            attempts[0].get("reason"),
+            Some(&YamlValue::scalar("Connection reset"))
+        );
+        assert_eq!(attempts[2].get("passed"), Some(&YamlValue::scalar("true")));
+
+        let broken = &tap_suite.tests[1];
+        assert!(!broken.passed);
+        assert!(!broken.is_flaky());
+        assert_eq!(
+            broken.diagnostics,
+            vec!["Out of bamboo", "Failed all 2 attempts"]
+        );
+    }
+
+    #[test]
+    fn test_no_retry_after_timeout() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let mut harness = TapHarness::new("Example TAP harness");
+        let options = TestOptions::new()
+            .with_timeout(Duration::from_millis(50))
+            .with_attempts(3)
+            .with_tag("slow");
+        harness.test_with("Sleepy panda", options, move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(200));
+            Ok(())
+        });
+
+        let tap_suite = harness.run();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            tap_suite.tests[0].diagnostics,
+            vec![
+                "Timed out after 0.05s",
+                "Not retried, as attempt 1 timed out"
+            ]
+        );
+        assert!(tap_suite.tests[0].tags.contains("slow"));
+    }
+
+    #[test]
+    fn test_flaky_as_todo() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            .flaky_as_todo()
+            .test_with(
+                "Flaky panda",
+                TestOptions::new().with_attempts(2),
+                move || {
+                    if counted.fetch_add(1, Ordering::SeqCst) == 0 {
+                        Err("Connection reset".to_string())
+                    } else {
+                        Ok(())
+                    }
+                },
+            )
+            .test_with("Steady panda", TestOptions::new().with_attempts(2), || {
+                Ok(())
+            });
+
+        let tap_suite = harness.run();
+
+        assert_eq!(
+            tap_suite.tests[0].status_line(1),
+            "ok 1 Flaky panda # TODO flaky, passed on attempt 2 of 2"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(tap_suite.tests[1].directive, None);
+        assert_eq!(tap_suite.tests[1].attempts(), 1);
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            .test("Panda", || Ok(()))
+            .test("Bamboo", || Err("Out of bamboo".to_string()))
+            .test("Red panda", || Ok(()));
+
+        harness.select(TapSelection::new().with_name("*anda"));
+        let mut numbers = Vec::new();
+        let (tests, _) = harness.run_all(false, |i, _, _| numbers.push(i + 1));
+        assert_eq!(numbers, vec![1, 2]);
+        assert_eq!(tests[1].name, "Red panda");
+
+        harness.jobs(2).select(
+            TapSelection::new()
+                .with_name("*anda")
+                .with_skip_deselected(),
+        );
+        let tap_suite = harness.run();
+        assert_eq!(tap_suite.tests.len(), 3);
+        assert_eq!(
+            tap_suite.tests[1].status_line(2),
+            "ok 2 Bamboo # SKIP filtered"
+        );
+        assert!(tap_suite.tests[2].passed);
+
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            .test_with(
+                "Panda",
+                TestOptions::new().with_tag("slow").with_tag("owner:team-x"),
+                || Ok(()),
+            )
+            .test_with("Bamboo", TestOptions::new().with_tag("network"), || Ok(()))
+            .select(TapSelection::new().without_tag("network"));
+        let tap_suite = harness.run();
+        assert_eq!(tap_suite.tests.len(), 1);
+        assert!(tap_suite.tests[0].has_tag("owner:team-x"));
+        assert_eq!(tap_suite.tests[0].tags.len(), 2);
+    }
+
+    #[test]
+    fn test_fixtures() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let logged = |entry: &'static str, result: TestResult| {
+            let log = log.clone();
+            move || {
+                log.lock().unwrap().push(entry);
+                result.clone()
+            }
+        };
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            // Which per-test fixtures overrule
+            .jobs(4)
+            .fixture(TapFixture::new(
+                "Server",
+                logged("Start server", Ok(())),
+                logged("Stop server", Err("Still running".to_string())),
+            ))
+            .fixture_each(TapFixture::new(
+                "Temp dir",
+                logged("Make temp dir", Ok(())),
+                logged("Remove temp dir", Ok(())),
+            ))
+            .test("Panda", logged("Panda", Ok(())))
+            .test("Bamboo", logged("Bamboo", Ok(())));
+
+        let tap_suite = harness.run();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                "Start server",
+                "Make temp dir",
+                "Panda",
+                "Remove temp dir",
+                "Make temp dir",
+                "Bamboo",
+                "Remove temp dir",
+                "Stop server",
+            ]
+        );
+        assert_eq!(tap_suite.tests.len(), 3);
+        assert_eq!(
+            tap_suite.tests[2].status_line(3),
+            "not ok 3 Teardown of Server"
+        );
+        assert_eq!(tap_suite.tests[2].diagnostics, vec!["Still running"]);
+    }
+
+    #[test]
+    fn test_setup_failures() {
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            .fixture_each(TapFixture::new(
+                "Temp dir",
+                || Err("Disk full".to_string()),
+                || Ok(()),
+            ))
+            .test("Panda", || Ok(()))
+            .test("Bamboo", || Ok(()));
+
+        let tap_suite = harness.run();
+        assert_eq!(tap_suite.tests.len(), 1);
+        assert!(!tap_suite.tests[0].passed);
+        assert_eq!(
+            tap_suite.bail_out.as_deref(),
+            Some("Setup of Temp dir failed: Disk full for Panda")
+        );
+
+        harness.skip_on_setup_failure();
+        let tap_suite = harness.run();
+        assert_eq!(tap_suite.bail_out, None);
+        assert_eq!(
+            tap_suite.tests[1].status_line(2),
+            "ok 2 Bamboo # SKIP Setup of Temp dir failed: Disk full"
+        );
+
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness
+            .fixture(TapFixture::new(
+                "Server",
+                || panic!("Port taken"),
+                || Ok(()),
+            ))
+            .test("Panda", || Ok(()));
+        let tap_suite = harness.run();
+        assert!(tap_suite.tests.is_empty());
+        assert_eq!(
+            tap_suite.bail_out.as_deref(),
+            Some("Setup of Server failed: Port taken")
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness.check("Small numbers", || {
+            TapProperty::new("Anything")
+                .with_seed(3)
+                .check(&ints(0, 100), |n| *n < 50)
+        });
+
+        let tap_suite = harness.run();
+
+        assert!(!tap_suite.tests[0].passed);
+        assert_eq!(tap_suite.tests[0].name, "Small numbers");
+        assert_eq!(tap_suite.tests[0].diagnostics[1], "Counterexample: 50");
+        assert!(tap_suite.tests[0].duration.is_some());
+    }
+
+    #[test]
+    fn test_run_measures_duration() {
+        let mut harness = TapHarness::new("Example TAP harness");
+        harness.test("Sleepy panda", || {
+            thread::sleep(Duration::from_millis(20));
+            Ok(())
+        });
+
+        let tap_suite = harness.run();
+
+        assert!(tap_suite.tests[0].duration.unwrap() >= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,380 @@
+//! `from_junit` -- For reading JUnit XML reports into a `TapSuite`
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::time::Duration;
+
+use crate::tap_directive::TapDirective;
+use crate::tap_suite::TapSuite;
+use crate::tap_test::TapTest;
+use crate::tap_version::TapVersion;
+
+/// Read a JUnit XML report into a `TapSuite`, written as TAP 14.
+///
+/// Each `<testcase>` becomes a test named "classname.name". A `<failure>` or `<error>` fails the test, its message and body becoming diagnostics; a `<skipped>` marks it SKIP. Nested `<testsuite>`s become tests of their own with subtests, as do the suites of a `<testsuites>` document holding more than one.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_junit::from_junit;
+///
+/// let xml = r#"<testsuite name="Zoo">
+///   <testcase classname="zoo" name="panda" time="0.5"/>
+///   <testcase classname="zoo" name="tiger"><failure message="Escaped"/></testcase>
+/// </testsuite>"#;
+///
+/// let tap_suite = from_junit(xml).unwrap();
+///
+/// assert_eq!(tap_suite.name, "Zoo");
+/// assert_eq!(tap_suite.tests[1].status_line(2), "not ok 2 zoo.tiger");
+/// ```
+pub fn from_junit(xml: &str) -> Result<TapSuite, String> {
+    let mut root = parse_document(xml)?;
+
+    if root.name == "testsuites" {
+        let suites = root
+            .children
+            .iter()
+            .filter(|child| child.name == "testsuite")
+            .count();
+        if suites == 1 {
+            root = root
+                .children
+                .into_iter()
+                .find(|child| child.name == "testsuite")
+                .unwrap();
+        }
+    } else if root.name != "testsuite" {
+        return Err(format!(
+            "Expected <testsuites> or <testsuite>, found <{}>",
+            root.name
+        ));
+    }
+
+    Ok(TapSuite {
+        name: root.attribute("name").unwrap_or_default().to_string(),
+        tests: suite_tests(&root),
+        version: TapVersion::Tap14,
+        bail_out: None,
+        plan: None,
+    })
+}
+
+fn suite_tests(suite: &Element) -> Vec<TapTest> {
+    suite
+        .children
+        .iter()
+        .filter_map(|child| match child.name.as_str() {
+            "testcase" => Some(test_case(child)),
+            "testsuite" => {
+                let subtests = suite_tests(child);
+                Some(TapTest {
+                    name: child.attribute("name").unwrap_or_default().to_string(),
+                    passed: !subtests.iter().any(TapTest::is_failure),
+                    duration: parse_time(child),
+                    subtests,
+                    ..Default::default()
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn test_case(case: &Element) -> TapTest {
+    let name = case.attribute("name").unwrap_or_default();
+    let name = match case.attribute("classname") {
+        Some(classname) if !classname.is_empty() => format!("{}.{}", classname, name),
+        _ => name.to_string(),
+    };
+
+    let mut test = TapTest {
+        name,
+        passed: true,
+        duration: parse_time(case),
+        ..Default::default()
+    };
+
+    for child in &case.children {
+        match child.name.as_str() {
+            "failure" | "error" => {
+                test.passed = false;
+                let mut heading = child.name.clone();
+                if let Some(kind) = child.attribute("type") {
+                    heading = format!("{} ({})", heading, kind);
+                }
+                if let Some(message) = child.attribute("message") {
+                    heading = format!("{}: {}", heading, message);
+                }
+                test.diagnostics.push(heading);
+                test.diagnostics
+                    .extend(child.text.trim().lines().map(|line| line.to_string()));
+            }
+            "skipped" => {
+                let reason = child
+                    .attribute("message")
+                    .map(|message| message.to_string())
+                    .unwrap_or_else(|| child.text.trim().to_string());
+                test.directive = Some(TapDirective::Skip(reason));
+            }
+            _ => {}
+        }
+    }
+
+    test
+}
+
+/// JUnit times are in seconds, with a fractional part.
+fn parse_time(element: &Element) -> Option<Duration> {
+    let seconds = element.attribute("time")?.trim().parse::<f64>().ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// An XML element, with its text content all run together.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse just enough XML for a test report: elements, attributes, text, CDATA and the predefined and numeric entities. Declarations, comments and processing instructions are skipped over.
+fn parse_document(xml: &str) -> Result<Element, String> {
+    // The element being built sits at the top of the stack, its ancestors beneath it
+    let mut stack: Vec<Element> = vec![Element::default()];
+    let mut rest = xml;
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            append_text(&mut stack, &unescape(rest));
+            break;
+        };
+        append_text(&mut stack, &unescape(&rest[..start]));
+        rest = &rest[start..];
+
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").ok_or("Unterminated CDATA section")?;
+            append_text(&mut stack, &cdata[..end]);
+            rest = &cdata[end + 3..];
+        } else if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").ok_or("Unterminated comment")?;
+            rest = &comment[end + 3..];
+        } else if rest.starts_with("<?") || rest.starts_with("<!") {
+            let end = rest.find('>').ok_or("Unterminated declaration")?;
+            rest = &rest[end + 1..];
+        } else if let Some(closing) = rest.strip_prefix("</") {
+            let end = closing.find('>').ok_or("Unterminated closing tag")?;
+            let name = closing[..end].trim();
+            let element = stack.pop().filter(|_| !stack.is_empty());
+            match element {
+                Some(element) if element.name == name => {
+                    stack.last_mut().unwrap().children.push(element);
+                }
+                _ => return Err(format!("Unexpected closing tag </{}>", name)),
+            }
+            rest = &closing[end + 1..];
+        } else {
+            let (element, self_closing, remainder) = parse_tag(&rest[1..])?;
+            if self_closing {
+                stack.last_mut().unwrap().children.push(element);
+            } else {
+                stack.push(element);
+            }
+            rest = remainder;
+        }
+    }
+
+    if stack.len() > 1 {
+        return Err(format!("Unclosed tag <{}>", stack.last().unwrap().name));
+    }
+    stack
+        .pop()
+        .and_then(|document| document.children.into_iter().next())
+        .ok_or_else(|| "No root element".to_string())
+}
+
+/// Parse an opening tag, starting just after its "<". Yields the element, whether it closed itself, and what follows the tag.
+fn parse_tag(tag: &str) -> Result<(Element, bool, &str), String> {
+    let name_end = tag
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .ok_or("Unterminated tag")?;
+    let mut element = Element {
+        name: tag[..name_end].to_string(),
+        ..Default::default()
+    };
+    let mut rest = &tag[name_end..];
+
+    loop {
+        rest = rest.trim_start();
+        if let Some(remainder) = rest.strip_prefix("/>") {
+            return Ok((element, true, remainder));
+        }
+        if let Some(remainder) = rest.strip_prefix('>') {
+            return Ok((element, false, remainder));
+        }
+
+        let equals = rest
+            .find('=')
+            .ok_or_else(|| format!("Malformed attribute in <{}>", element.name))?;
+        let key = rest[..equals].trim().to_string();
+        let value = rest[equals + 1..].trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| format!("Unquoted attribute {} in <{}>", key, element.name))?;
+        let end = value[1..]
+            .find(quote)
+            .ok_or_else(|| format!("Unterminated attribute {} in <{}>", key, element.name))?;
+        element.attributes.push((key, unescape(&value[1..end + 1])));
+        rest = &value[end + 2..];
+    }
+}
+
+fn append_text(stack: &mut [Element], text: &str) {
+    if let Some(element) = stack.last_mut() {
+        element.text.push_str(text);
+    }
+}
+
+fn unescape(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        buf.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                entity => entity
+                    .strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| {
+                        entity
+                            .strip_prefix('#')
+                            .map(|decimal| decimal.parse::<u32>())
+                    })
+                    .and_then(|code| code.ok())
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                buf.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                buf.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    buf.push_str(rest);
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::from_junit;
+    use crate::tap_directive::TapDirective;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_from_junit() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <!-- One suite only, so its tests are the suite's tests -->
+  <testsuite name="Zoo" tests="3">
+    <testcase classname="zoo.Animals" name="panda" time="0.25"/>
+    <testcase classname="zoo.Animals" name="tiger">
+      <failure message="Escaped &amp; hungry" type="AssertionError"><![CDATA[expected <cage>
+got <street>]]></failure>
+    </testcase>
+    <testcase name="penguin" time="1e30"><skipped message="Too cold"/></testcase>
+  </testsuite>
+</testsuites>"#;
+
+        let tap_suite = from_junit(xml).unwrap();
+
+        assert_eq!(tap_suite.name, "Zoo");
+        assert_eq!(tap_suite.tests.len(), 3);
+        assert_eq!(tap_suite.tests[0].name, "zoo.Animals.panda");
+        assert_eq!(
+            tap_suite.tests[0].duration,
+            Some(Duration::from_millis(250))
+        );
+        assert!(!tap_suite.tests[1].passed);
+        assert_eq!(
+            tap_suite.tests[1].diagnostics,
+            vec![
+                "failure (AssertionError): Escaped & hungry",
+                "expected <cage>",
+                "got <street>"
+            ]
+        );
+        assert_eq!(
+            tap_suite.tests[2].directive,
+            Some(TapDirective::Skip("Too cold".to_string()))
+        );
+        // Too long to be a real time
+        assert_eq!(tap_suite.tests[2].duration, None);
+    }
+
+    #[test]
+    fn test_from_junit_nested_suites() {
+        let xml = r#"<testsuites name="All">
+  <testsuite name="First"><testcase name="a"/></testsuite>
+  <testsuite name="Second">
+    <testsuite name="Inner"><testcase name="b"><error message="Boom"/></testcase></testsuite>
+  </testsuite>
+</testsuites>"#;
+
+        let tap_suite = from_junit(xml).unwrap();
+
+        let expected = vec![
+            "TAP version 14",
+            "1..2",
+            "    # Subtest: First",
+            "    1..1",
+            "    ok 1 a",
+            "ok 1 First",
+            "    # Subtest: Second",
+            "    1..1",
+            "        # Subtest: Inner",
+            "        1..1",
+            "        not ok 1 b",
+            "        # error: Boom",
+            "    not ok 1 Inner",
+            "not ok 2 Second",
+        ];
+
+        assert_eq!(expected, tap_suite.lines());
+    }
+
+    #[test]
+    fn test_from_junit_malformed() {
+        assert!(from_junit("<testsuite><testcase></testsuite>").is_err());
+        assert!(from_junit("<html></html>").is_err());
+    }
+}
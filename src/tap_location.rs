@@ -0,0 +1,190 @@
+//! `TapLocation` -- Where in the source a test lives, so a failure can be traced back without grepping for its name
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    string::{String, ToString},
+    vec,
+};
+use core::fmt;
+use core::panic::Location;
+
+use crate::tap_yaml::YamlValue;
+
+/// A place in the source code. Lines and columns count from 1, with 0 meaning unknown.
+///
+/// Rendered in a test's YAML block under the `at` key, as a mapping of `file`, `line`, `column` and `module`.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_location;
+/// use testanything::tap_test_builder::TapTestBuilder;
+///
+/// let tap_test = TapTestBuilder::new()
+///     .name("Panda")
+///     .passed(false)
+///     .location(tap_location!())
+///     .finalize();
+///
+/// let location = tap_test.location.unwrap();
+/// assert_eq!(location.module_path.as_deref(), Some(module_path!()));
+/// assert!(location.line > 0);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TapLocation {
+    /// The path of the source file, as `file!()` gives it
+    pub file: String,
+    /// The line number, or 0 if not known
+    pub line: u32,
+    /// The column number, or 0 if not known
+    pub column: u32,
+    /// The module the code is in, as `module_path!()` gives it, if known
+    pub module_path: Option<String>,
+}
+
+impl TapLocation {
+    /// Make a location from its parts, with no module path.
+    pub fn new<S: Into<String>>(file: S, line: u32, column: u32) -> TapLocation {
+        TapLocation {
+            file: file.into(),
+            line,
+            column,
+            module_path: None,
+        }
+    }
+
+    /// Set the module the location is in
+    pub fn with_module_path<S: Into<String>>(mut self, module_path: S) -> TapLocation {
+        self.module_path = Some(module_path.into());
+        self
+    }
+
+    /// The location of the code calling this function. Mark a function `#[track_caller]` and calls to this inside it yield *its* caller instead. The module path isn't known this way.
+    #[track_caller]
+    pub fn caller() -> TapLocation {
+        let location = Location::caller();
+        TapLocation::new(location.file(), location.line(), location.column())
+    }
+
+    /// Render the location for a YAML block.
+    pub fn to_yaml(&self) -> YamlValue {
+        let mut fields = vec![("file".to_string(), YamlValue::scalar(self.file.as_str()))];
+        if self.line > 0 {
+            fields.push(("line".to_string(), YamlValue::scalar(self.line.to_string())));
+        }
+        if self.column > 0 {
+            fields.push((
+                "column".to_string(),
+                YamlValue::scalar(self.column.to_string()),
+            ));
+        }
+        if let Some(module_path) = &self.module_path {
+            fields.push((
+                "module".to_string(),
+                YamlValue::scalar(module_path.as_str()),
+            ));
+        }
+        YamlValue::Map(fields)
+    }
+
+    /// Read a location back out of a YAML value: either a mapping as `to_yaml` writes it, or a scalar like "src/zoo.rs:12:5" as some other producers write it.
+    pub fn from_yaml(value: &YamlValue) -> Option<TapLocation> {
+        match value {
+            YamlValue::Map(_) => {
+                let number = |key| {
+                    value
+                        .get(key)
+                        .and_then(YamlValue::as_str)
+                        .and_then(|n| n.parse::<u32>().ok())
+                        .unwrap_or(0)
+                };
+                Some(TapLocation {
+                    file: value.get("file").and_then(YamlValue::as_str)?.to_string(),
+                    line: number("line"),
+                    column: number("column"),
+                    module_path: value
+                        .get("module")
+                        .and_then(YamlValue::as_str)
+                        .map(|module_path| module_path.to_string()),
+                })
+            }
+            YamlValue::Scalar(at) => {
+                let mut parts = at.splitn(3, ':');
+                let file = parts.next().filter(|file| !file.is_empty())?;
+                let mut number = || {
+                    parts
+                        .next()
+                        .and_then(|n| n.parse::<u32>().ok())
+                        .unwrap_or(0)
+                };
+                let line = number();
+                let column = number();
+                Some(TapLocation::new(file, line, column))
+            }
+            YamlValue::List(_) => None,
+        }
+    }
+}
+
+/// Like "src/zoo.rs:12:5", leaving out what isn't known
+impl fmt::Display for TapLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if self.line > 0 {
+            write!(f, ":{}", self.line)?;
+            if self.column > 0 {
+                write!(f, ":{}", self.column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The location of the macro call as a `TapLocation`, module path and all.
+#[macro_export]
+macro_rules! tap_location {
+    () => {
+        $crate::tap_location::TapLocation::new(file!(), line!(), column!())
+            .with_module_path(module_path!())
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TapLocation;
+    use crate::tap_yaml::YamlValue;
+
+    #[test]
+    fn test_caller() {
+        #[track_caller]
+        fn located() -> TapLocation {
+            TapLocation::caller()
+        }
+
+        let line = line!() + 1;
+        let location = located();
+
+        assert_eq!(location.file, file!());
+        assert_eq!(location.line, line);
+        assert_eq!(location.module_path, None);
+        assert_eq!(
+            tap_location!().module_path.as_deref(),
+            Some("testanything::tap_location::tests")
+        );
+    }
+
+    #[test]
+    fn test_yaml_roundtrip() {
+        let location = TapLocation::new("src/zoo.rs", 12, 5).with_module_path("zoo::cats");
+
+        assert_eq!(TapLocation::from_yaml(&location.to_yaml()), Some(location));
+        assert_eq!(
+            TapLocation::from_yaml(&YamlValue::scalar("src/zoo.rs:12")),
+            Some(TapLocation::new("src/zoo.rs", 12, 0))
+        );
+        assert_eq!(
+            TapLocation::new("src/zoo.rs", 12, 0).to_string(),
+            "src/zoo.rs:12"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! `merge` -- For combining several TAP results, such as those of sharded test jobs, into one `TapSuite`
+
+#[cfg(feature = "alloc")]
+use alloc::{format, string::ToString, vec::Vec};
+
+use crate::tap_suite::TapSuite;
+use crate::tap_test::TapTest;
+
+/// How the tests of the merged suites are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergeMode {
+    /// One after another, renumbered as a single list
+    #[default]
+    Flat,
+    /// Each suite becomes a test of its own, named after the suite, with the suite's tests as subtests
+    Nested,
+}
+
+/// Combine `suites` into a single suite called `name`. The merged suite is written in the newest version of TAP any of its sources used.
+///
+/// Diagnostics travel with their tests. Should any source have bailed out, so does the merged suite, with the reason prefixed by the source's name; the tests of every source are kept all the same. In nested mode, the bail out also fails that source's test.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_merge::{merge, MergeMode};
+/// use testanything::tap_parser::TapParser;
+///
+/// let first = TapParser::new().parse_suite("shard-1", "1..1\nok 1 Panda");
+/// let second = TapParser::new().parse_suite("shard-2", "1..1\nnot ok 1 Bamboo");
+///
+/// let merged = merge("All shards", &[first, second], MergeMode::Flat);
+///
+/// assert_eq!(merged.lines(), vec!["1..2", "ok 1 Panda", "not ok 2 Bamboo"]);
+/// ```
+pub fn merge(name: &str, suites: &[TapSuite], mode: MergeMode) -> TapSuite {
+    let bail_out = suites.iter().find_map(|suite| {
+        suite
+            .bail_out
+            .as_ref()
+            .map(|reason| format!("{}: {}", suite.name, reason))
+    });
+
+    let tests = match mode {
+        MergeMode::Flat => suites
+            .iter()
+            .flat_map(|suite| suite.tests.iter().cloned())
+            .collect::<Vec<TapTest>>(),
+        MergeMode::Nested => suites.iter().map(nest).collect::<Vec<TapTest>>(),
+    };
+
+    TapSuite {
+        name: name.to_string(),
+        tests,
+        version: suites
+            .iter()
+            .map(|suite| suite.version)
+            .max()
+            .unwrap_or_default(),
+        bail_out,
+        plan: None,
+    }
+}
+
+/// Turn a suite into a single test which passes only if the whole suite did.
+fn nest(suite: &TapSuite) -> TapTest {
+    let mut diagnostics = Vec::new();
+    if let Some(reason) = &suite.bail_out {
+        diagnostics.push(format!("Bail out! {}", reason).trim_end().to_string());
+    }
+
+    TapTest {
+        name: suite.name.clone(),
+        passed: suite.bail_out.is_none() && !suite.tests.iter().any(|test| test.is_failure()),
+        diagnostics,
+        subtests: suite.tests.clone(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge, MergeMode};
+    use crate::tap_parser::TapParser;
+    use crate::tap_suite::TapSuite;
+    use crate::tap_version::TapVersion;
+
+    fn shards() -> Vec<TapSuite> {
+        vec![
+            TapParser::new().parse_suite("shard-1", "1..2\nok 1 Panda\nok 2 Bamboo\n# Tasty"),
+            TapParser::new().parse_suite(
+                "shard-2",
+                "TAP version 14\n1..2\nnot ok 1 Curry\nBail out! Out of noodles",
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_merge_flat() {
+        let merged = merge("All shards", &shards(), MergeMode::Flat);
+
+        let expected = vec![
+            "TAP version 14",
+            "1..3",
+            "ok 1 Panda",
+            "ok 2 Bamboo",
+            "# Tasty",
+            "not ok 3 Curry",
+            "Bail out! shard-2: Out of noodles",
+        ];
+
+        assert_eq!(merged.version, TapVersion::Tap14);
+        assert_eq!(expected, merged.lines());
+    }
+
+    #[test]
+    fn test_merge_nested() {
+        let merged = merge("All shards", &shards(), MergeMode::Nested);
+
+        let expected = vec![
+            "TAP version 14",
+            "1..2",
+            "    # Subtest: shard-1",
+            "    1..2",
+            "    ok 1 Panda",
+            "    ok 2 Bamboo",
+            "    # Tasty",
+            "ok 1 shard-1",
+            "    # Subtest: shard-2",
+            "    1..1",
+            "    not ok 1 Curry",
+            "not ok 2 shard-2",
+            "# Bail out! Out of noodles",
+            "Bail out! shard-2: Out of noodles",
+        ];
+
+        assert_eq!(expected, merged.lines());
+    }
+}
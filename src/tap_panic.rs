@@ -0,0 +1,174 @@
+//! `catch_panic` -- Turn a panic in code under test into a failing test, instead of a TAP stream cut off mid-way
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::sync::Once;
+use std::time::Instant;
+
+use crate::tap_location::TapLocation;
+use crate::tap_test::TapTest;
+use crate::tap_test_builder::TapTestBuilder;
+
+/// What we know about a panic: what it said and where it happened.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PanicDetails {
+    /// The panic message, if it was a string
+    pub message: String,
+    /// Where the panic happened, if known
+    pub location: Option<TapLocation>,
+}
+
+impl PanicDetails {
+    /// The panic as diagnostics, like "Panicked at src/zoo.rs:12:5:" followed by the message
+    pub fn diagnostics(&self) -> Vec<String> {
+        let heading = match &self.location {
+            Some(location) => format!("Panicked at {}:", location),
+            None => "Panicked:".to_string(),
+        };
+        let mut lines = vec![heading];
+        lines.extend(self.message.lines().map(|line| format!("  {}", line)));
+        lines
+    }
+
+    /// A failing test called `name` recording the panic, located where the panic happened
+    pub fn to_test(&self, name: &str) -> TapTest {
+        let mut builder = TapTestBuilder::new();
+        builder.name(name).passed(false);
+        if let Some(location) = &self.location {
+            builder.location(location.clone());
+        }
+        let diagnostics = self.diagnostics();
+        builder
+            .diagnostics(
+                &diagnostics
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<&str>>(),
+            )
+            .finalize()
+    }
+}
+
+thread_local! {
+    /// How many `catch_panic` calls this thread is inside of
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+    /// What the panic hook saw of the last panic caught on this thread
+    static CAUGHT: RefCell<Option<PanicDetails>> = const { RefCell::new(None) };
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+/// Run `f`, catching any panic instead of letting it unwind further. The panic's message and location are handed back instead of printed.
+///
+/// A panic hook which records panics is installed the first time this is called. It only acts on the thread inside `catch_panic`, so panics elsewhere still go to whatever hook was in place before. Setting another hook after that stops panics being recorded, though they are still caught.
+///
+/// Anything `f` borrows may be left half-updated by a panic, so treat it with suspicion afterwards.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_panic::catch_panic;
+///
+/// let details = catch_panic(|| -> () { panic!("Out of bamboo") }).unwrap_err();
+///
+/// assert_eq!(details.message, "Out of bamboo");
+/// assert_eq!(details.location.unwrap().file, file!());
+/// ```
+pub fn catch_panic<F: FnOnce() -> R, R>(f: F) -> Result<R, PanicDetails> {
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.with(Cell::get) > 0 {
+                CAUGHT.with(|caught| *caught.borrow_mut() = Some(details_from_hook(info)));
+            } else {
+                previous(info);
+            }
+        }));
+    });
+
+    CATCHING.with(|catching| catching.set(catching.get() + 1));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|catching| catching.set(catching.get() - 1));
+
+    result.map_err(|payload| {
+        CAUGHT
+            .with(|caught| caught.borrow_mut().take())
+            .unwrap_or_else(|| PanicDetails {
+                message: payload_message(payload.as_ref()),
+                location: None,
+            })
+    })
+}
+
+/// Run `check`, producing a test called `name` which passes if it returns true. A panic makes a failing test with the panic's details as diagnostics.
+pub fn test_caught<F: FnOnce() -> bool>(name: &str, check: F) -> TapTest {
+    let started = Instant::now();
+    let mut tap_test = match catch_panic(check) {
+        Ok(passed) => TapTestBuilder::new().name(name).passed(passed).finalize(),
+        Err(details) => details.to_test(name),
+    };
+    tap_test.duration = Some(started.elapsed());
+    tap_test
+}
+
+fn details_from_hook(info: &PanicHookInfo) -> PanicDetails {
+    PanicDetails {
+        message: payload_message(info.payload()),
+        location: info
+            .location()
+            .map(|location| TapLocation::new(location.file(), location.line(), location.column())),
+    }
+}
+
+fn payload_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{catch_panic, test_caught};
+
+    #[test]
+    fn test_catch_panic() {
+        assert_eq!(catch_panic(|| 7), Ok(7));
+
+        let line = line!() + 1;
+        let details = catch_panic(|| -> () { panic!("Out of {}", "bamboo") }).unwrap_err();
+        assert_eq!(details.message, "Out of bamboo");
+        let location = details.location.clone().unwrap();
+        assert_eq!((location.file.as_str(), location.line), (file!(), line));
+        assert_eq!(
+            details.diagnostics()[0],
+            format!("Panicked at {}:", location)
+        );
+        assert_eq!(details.diagnostics()[1], "  Out of bamboo");
+
+        // Nested catches each see their own panic
+        let outer = catch_panic(|| {
+            let inner = catch_panic(|| -> () { panic!("Inner") });
+            assert_eq!(inner.unwrap_err().message, "Inner");
+            panic!("Outer")
+        });
+        assert_eq!(outer.unwrap_err().message, "Outer");
+    }
+
+    #[test]
+    fn test_test_caught() {
+        let passing = test_caught("Panda", || true);
+        assert!(passing.passed);
+        assert!(passing.duration.is_some());
+
+        let panicking = test_caught("Bamboo", || panic!("Out of bamboo"));
+        assert!(!panicking.passed);
+        assert_eq!(panicking.name, "Bamboo");
+        assert_eq!(panicking.location.unwrap().file, file!());
+        assert_eq!(panicking.diagnostics[1], "  Out of bamboo");
+    }
+}
@@ -0,0 +1,536 @@
+//! `TapParser` -- For reading TAP streams back in, line by line or all at once
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::tap_directive::TapDirective;
+use crate::tap_suite::TapSuite;
+use crate::tap_test::{self, TapTest};
+use crate::tap_version::TapVersion;
+use crate::tap_yaml::{self, YamlValue};
+use crate::{NOT_OK_SYMBOL, OK_SYMBOL};
+
+/// One meaningful line of a TAP stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TapEvent {
+    /// A "TAP version N" line
+    Version(u32),
+    /// A plan line like "1..5"
+    Plan {
+        /// The first test number, which is always 1 in practice
+        start: i64,
+        /// The last test number
+        end: i64,
+    },
+    /// An "ok" or "not ok" line. Diagnostics arrive separately, as the `Diagnostic` events that follow.
+    Test {
+        /// The test number, either as written in the stream or counted by the parser when it was left out
+        number: i64,
+        /// The test itself, without diagnostics
+        test: Box<TapTest>,
+    },
+    /// A YAML block of structured diagnostics belonging to the test just before it
+    Yaml(Vec<(String, YamlValue)>),
+    /// A line beginning with a # mark, with the mark stripped
+    Diagnostic(String),
+    /// A "Bail out!" line, with the reason (which may be blank)
+    BailOut(String),
+    /// A line of an indented subtest stream, which will be summed up by the next test line at this level
+    Subtest(Box<TapEvent>),
+    /// Anything else. TAP consumers are required to ignore these, but they are handed back in case you care.
+    Unknown(String),
+}
+
+/// Turns TAP text into `TapEvent`s. This keeps just enough state to number tests that were written without a number and to gather up YAML blocks.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_parser::{TapEvent, TapParser};
+///
+/// let mut parser = TapParser::new();
+///
+/// match parser.parse_line("not ok 4 Noodle") {
+///     Some(TapEvent::Test { number, test }) => {
+///         assert_eq!(number, 4);
+///         assert!(!test.passed);
+///     }
+///     _ => unreachable!(),
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct TapParser {
+    last_number: i64,
+    // Whether the previous line was a test line, which is the only place a YAML block may start
+    after_test: bool,
+    // The lines of the YAML block in progress, and how far its opening "---" was indented
+    yaml: Option<(usize, Vec<String>)>,
+    // Reads the indented stream of the subtest in progress, if there is one
+    child: Option<Box<TapParser>>,
+}
+
+impl TapParser {
+    /// Make a parser positioned at the start of a stream
+    pub fn new() -> TapParser {
+        TapParser {
+            last_number: 0,
+            after_test: false,
+            yaml: None,
+            child: None,
+        }
+    }
+
+    /// Interpret one line of a TAP stream. Trailing whitespace, including a carriage return, is ignored outside of YAML blocks.
+    ///
+    /// Lines inside a YAML block yield nothing; the whole block comes out as a single `TapEvent::Yaml` once a closing "..." is seen, indented as far as the "---" which opened it. A block cut short by a line indented less than its "---", like the next test line, wasn't YAML after all: it's dropped, and that line is read as usual. Lines indented by four spaces belong to a subtest and come out wrapped in `TapEvent::Subtest`.
+    pub fn parse_line(&mut self, line: &str) -> Option<TapEvent> {
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        if let Some((indent, yaml)) = self.yaml.as_mut() {
+            let line_indent = tap_yaml::indent_of(line);
+            if line_indent == *indent && line.trim() == "..." {
+                return self.finish();
+            }
+            if line_indent >= *indent || line.trim().is_empty() {
+                yaml.push(line.to_string());
+                return None;
+            }
+            self.yaml = None;
+        }
+
+        let line = line.trim_end();
+
+        let after_test = self.after_test;
+        self.after_test = false;
+        if after_test && line.starts_with(' ') && line.trim() == "---" {
+            self.yaml = Some((tap_yaml::indent_of(line), Vec::new()));
+            return None;
+        }
+
+        if let Some(child_line) = line.strip_prefix("    ") {
+            return self
+                .child
+                .get_or_insert_with(Default::default)
+                .parse_line(child_line)
+                .map(|event| TapEvent::Subtest(Box::new(event)));
+        }
+        if !line.is_empty() {
+            // Whatever comes next at the indented level is a new subtest
+            self.child = None;
+        }
+
+        let event = self.parse_event(line);
+        self.after_test = matches!(event, TapEvent::Test { .. });
+        Some(event)
+    }
+
+    /// Close a YAML block left open at the end of the stream, which comes out as the `TapEvent::Yaml` it would have, if there is one
+    pub fn finish(&mut self) -> Option<TapEvent> {
+        let (_, yaml) = self.yaml.take()?;
+        Some(TapEvent::Yaml(tap_yaml::parse_block(&yaml)))
+    }
+
+    fn parse_event(&mut self, line: &str) -> TapEvent {
+        if let Some(version) = line.strip_prefix("TAP version ") {
+            if let Ok(version) = version.trim().parse::<u32>() {
+                return TapEvent::Version(version);
+            }
+        }
+
+        if let Some(plan) = parse_plan(line) {
+            return plan;
+        }
+
+        if let Some(rest) = line.strip_prefix("Bail out!") {
+            return TapEvent::BailOut(rest.trim().to_string());
+        }
+
+        if let Some(rest) = line.strip_prefix('#') {
+            return TapEvent::Diagnostic(rest.strip_prefix(' ').unwrap_or(rest).to_string());
+        }
+
+        let status = if let Some(rest) = strip_symbol(line, NOT_OK_SYMBOL) {
+            Some((false, rest))
+        } else {
+            strip_symbol(line, OK_SYMBOL).map(|rest| (true, rest))
+        };
+
+        match status {
+            Some((passed, rest)) => self.parse_test(passed, rest),
+            None => TapEvent::Unknown(line.to_string()),
+        }
+    }
+
+    /// Read an entire TAP stream into a `TapSuite`. Diagnostics and YAML blocks are attached to the test that precedes them, and subtests to the test that follows them; diagnostics that come before the first test are dropped. Nothing after a bail out is read.
+    pub fn parse_suite(&mut self, name: &str, input: &str) -> TapSuite {
+        let mut collector = SuiteCollector::default();
+
+        for line in input.lines() {
+            if let Some(event) = self.parse_line(line) {
+                collector.push(event);
+            }
+            if collector.bail_out.is_some() {
+                break;
+            }
+        }
+        if let (None, Some(event)) = (&collector.bail_out, self.finish()) {
+            collector.push(event);
+        }
+
+        TapSuite {
+            name: name.to_string(),
+            tests: collector.tests,
+            version: collector.version,
+            bail_out: collector.bail_out,
+            plan: collector.plan,
+        }
+    }
+
+    fn parse_test(&mut self, passed: bool, rest: &str) -> TapEvent {
+        let rest = rest.trim_start();
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+
+        let (number, rest) = match rest[..digits].parse::<i64>() {
+            Ok(number) => (number, &rest[digits..]),
+            Err(_) => (self.last_number + 1, rest),
+        };
+        self.last_number = number;
+
+        let rest = rest.trim_start();
+        let (name, directive) = TapDirective::split(rest.strip_prefix("- ").unwrap_or(rest));
+
+        TapEvent::Test {
+            number,
+            test: Box::new(TapTest {
+                name: tap_test::unescape_name(name),
+                passed,
+                directive,
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+/// Builds up a `TapSuite` a line at a time, for reading a stream while it's still being written.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_parser::TapSuiteReader;
+///
+/// let mut reader = TapSuiteReader::new();
+/// for line in ["1..2", "ok 1 Panda", "not ok 2 Bamboo", "# Ran out"] {
+///     reader.read_line(line);
+/// }
+///
+/// assert_eq!(reader.plan(), Some((1, 2)));
+/// let tap_suite = reader.finish("Zoo");
+/// assert_eq!(tap_suite.tests[1].diagnostics, vec!["Ran out"]);
+/// ```
+#[derive(Debug, Default)]
+pub struct TapSuiteReader {
+    parser: TapParser,
+    collector: SuiteCollector,
+}
+
+impl TapSuiteReader {
+    /// Make a reader positioned at the start of a stream
+    pub fn new() -> TapSuiteReader {
+        TapSuiteReader::default()
+    }
+
+    /// Take in the next line of the stream. Nothing after a bail out is read.
+    pub fn read_line(&mut self, line: &str) {
+        if self.collector.bail_out.is_some() {
+            return;
+        }
+        if let Some(event) = self.parser.parse_line(line) {
+            self.collector.push(event);
+        }
+    }
+
+    /// The first and last test numbers of the stream's plan, if it's had one yet
+    pub fn plan(&self) -> Option<(i64, i64)> {
+        self.collector.plan
+    }
+
+    /// The suite read so far, under the name `name`
+    pub fn finish(mut self, name: &str) -> TapSuite {
+        if let (None, Some(event)) = (&self.collector.bail_out, self.parser.finish()) {
+            self.collector.push(event);
+        }
+        TapSuite {
+            name: name.to_string(),
+            tests: self.collector.tests,
+            version: self.collector.version,
+            bail_out: self.collector.bail_out,
+            plan: self.collector.plan,
+        }
+    }
+}
+
+/// Assembles a stream's worth of events into tests.
+#[derive(Debug, Default)]
+struct SuiteCollector {
+    tests: Vec<TapTest>,
+    version: TapVersion,
+    bail_out: Option<String>,
+    plan: Option<(i64, i64)>,
+    subtests: Option<Box<SuiteCollector>>,
+}
+
+impl SuiteCollector {
+    fn push(&mut self, event: TapEvent) {
+        match event {
+            TapEvent::Version(number) => self.version = TapVersion::from_number(number),
+            TapEvent::Test { mut test, .. } => {
+                if let Some(subtests) = self.subtests.take() {
+                    test.subtests = subtests.tests;
+                }
+                self.tests.push(*test);
+            }
+            TapEvent::Yaml(fields) => {
+                if let Some(test) = self.tests.last_mut() {
+                    test.apply_yaml(&fields);
+                }
+            }
+            TapEvent::Diagnostic(diagnostic) => {
+                if let Some(test) = self.tests.last_mut() {
+                    test.diagnostics.push(diagnostic);
+                }
+            }
+            TapEvent::BailOut(reason) => self.bail_out = Some(reason),
+            TapEvent::Subtest(event) => self
+                .subtests
+                .get_or_insert_with(Default::default)
+                .push(*event),
+            TapEvent::Plan { start, end } => self.plan = Some((start, end)),
+            TapEvent::Unknown(_) => {}
+        }
+    }
+}
+
+/// Strip "ok" or "not ok" off the front of a line, provided it's a whole word.
+fn strip_symbol<'a>(line: &'a str, symbol: &str) -> Option<&'a str> {
+    let rest = line.strip_prefix(symbol)?;
+    if rest.is_empty() || rest.starts_with(' ') {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+fn parse_plan(line: &str) -> Option<TapEvent> {
+    let (start, end) = line.split_once("..")?;
+    // A plan may carry a trailing directive, as in "1..0 # SKIP no database"
+    let end = end.split('#').next()?.trim();
+    Some(TapEvent::Plan {
+        start: start.parse::<i64>().ok()?,
+        end: end.parse::<i64>().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TapEvent, TapParser, TapSuiteReader};
+    use crate::tap_suite::TapSuite;
+    use crate::tap_test::TapTest;
+    use crate::tap_test_builder::TapTestBuilder;
+    use crate::tap_version::TapVersion;
+    use crate::tap_yaml::YamlValue;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_lines() {
+        let mut parser = TapParser::new();
+
+        assert_eq!(
+            parser.parse_line("TAP version 13"),
+            Some(TapEvent::Version(13))
+        );
+        assert_eq!(
+            parser.parse_line("1..6"),
+            Some(TapEvent::Plan { start: 1, end: 6 })
+        );
+        assert_eq!(
+            parser.parse_line("# Doing fine"),
+            Some(TapEvent::Diagnostic("Doing fine".to_string()))
+        );
+        assert_eq!(
+            parser.parse_line("Bail out! Destabilized warp core!"),
+            Some(TapEvent::BailOut("Destabilized warp core!".to_string()))
+        );
+        assert_eq!(
+            parser.parse_line("okay then"),
+            Some(TapEvent::Unknown("okay then".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_test_lines() {
+        let mut parser = TapParser::new();
+
+        let expected = TapEvent::Test {
+            number: 1,
+            test: Box::new(TapTestBuilder::new().name("Panda").passed(true).finalize()),
+        };
+        assert_eq!(parser.parse_line("ok 1 Panda"), Some(expected));
+
+        let expected = TapEvent::Test {
+            number: 2,
+            test: Box::new(
+                TapTestBuilder::new()
+                    .name("Curry Noodle")
+                    .passed(false)
+                    .finalize(),
+            ),
+        };
+        assert_eq!(parser.parse_line("not ok - Curry Noodle\r"), Some(expected));
+
+        let expected = TapEvent::Test {
+            number: 3,
+            test: Box::new(
+                TapTestBuilder::new()
+                    .name("Tree")
+                    .passed(true)
+                    .skip("Winter")
+                    .finalize(),
+            ),
+        };
+        assert_eq!(parser.parse_line("ok 3 Tree # skip Winter"), Some(expected));
+    }
+
+    #[test]
+    fn test_parse_suite_round_trip() {
+        let tap_suite = TapSuite {
+            name: "Example TAP Suite".to_string(),
+            tests: vec![
+                TapTestBuilder::new()
+                    .name("Panda Bamboo")
+                    .passed(true)
+                    .finalize(),
+                TapTestBuilder::new()
+                    .name("Curry Noodle")
+                    .passed(false)
+                    .diagnostics(&["Tree", "Flower"])
+                    .duration(Duration::from_micros(1_250))
+                    .finalize(),
+            ],
+            version: TapVersion::Tap13,
+            plan: Some((1, 2)),
+            ..Default::default()
+        };
+
+        let input = tap_suite.lines().join("\n");
+        let parsed = TapParser::new().parse_suite("Example TAP Suite", &input);
+
+        assert_eq!(tap_suite, parsed);
+    }
+
+    #[test]
+    fn test_parse_unterminated_yaml_block() {
+        let input = "1..3\nok 1 a\n  ---\n  foo: bar\nnot ok 2 b\nnot ok 3 c\n  ---\n  foo: baz";
+        let tap_suite = TapParser::new().parse_suite("Zoo", input);
+
+        assert_eq!(tap_suite.tests.len(), 3);
+        assert!(tap_suite.tests[1].is_failure() && tap_suite.tests[2].is_failure());
+        assert!(tap_suite.tests[0].yaml.is_empty());
+        // Still open when the stream ends, so it's closed then
+        assert_eq!(
+            tap_suite.tests[2].yaml_value("foo"),
+            Some(&YamlValue::scalar("baz"))
+        );
+
+        let mut reader = TapSuiteReader::new();
+        for line in input.lines() {
+            reader.read_line(line);
+        }
+        assert_eq!(reader.finish("Zoo"), tap_suite);
+    }
+
+    #[test]
+    fn test_parse_yaml_block_with_dots_in_it() {
+        let tap_test = TapTest {
+            name: "Panda".to_string(),
+            yaml: vec![(
+                "output".to_string(),
+                YamlValue::scalar("line1\n...\nline3  \n---"),
+            )],
+            ..Default::default()
+        };
+        let tap_suite = TapSuite {
+            name: "Zoo".to_string(),
+            tests: vec![tap_test],
+            version: TapVersion::Tap13,
+            plan: Some((1, 1)),
+            ..Default::default()
+        };
+
+        let input = tap_suite.lines().join("\r\n");
+        let parsed = TapParser::new().parse_suite("Zoo", &input);
+
+        assert_eq!(tap_suite, parsed);
+    }
+
+    #[test]
+    fn test_parse_yaml_block() {
+        let mut parser = TapParser::new();
+
+        let events = [
+            "ok 1 Panda",
+            "  ---",
+            "  duration_ms: 3.000",
+            "  ...",
+            "  ---",
+        ]
+        .iter()
+        .filter_map(|line| parser.parse_line(line))
+        .collect::<Vec<TapEvent>>();
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[1],
+            TapEvent::Yaml(vec![(
+                "duration_ms".to_string(),
+                YamlValue::scalar("3.000")
+            )])
+        );
+        // Not after a test line, so not the start of a YAML block
+        assert_eq!(events[2], TapEvent::Unknown("  ---".to_string()));
+    }
+
+    #[test]
+    fn test_parse_suite_with_subtests_and_bail_out() {
+        let input = [
+            "1..3",
+            "    # Subtest: Zoo",
+            "    1..2",
+            "    ok 1 Panda",
+            "    not ok 2 Tiger",
+            "    # Escaped",
+            "not ok 1 Zoo",
+            "ok 2 Bamboo",
+            "Bail out! Out of bamboo",
+            "ok 3 Never seen",
+        ]
+        .join("\n");
+
+        let parsed = TapParser::new().parse_suite("Example TAP Suite", &input);
+
+        assert_eq!(parsed.tests.len(), 2);
+        assert_eq!(parsed.tests[0].subtests.len(), 2);
+        assert_eq!(parsed.tests[0].subtests[1].diagnostics, vec!["Escaped"]);
+        assert!(parsed.tests[1].subtests.is_empty());
+        assert_eq!(parsed.bail_out, Some("Out of bamboo".to_string()));
+        // Everything but the plan (which is recalculated) and the ignored test comes back out
+        let expected = input.lines().skip(1).take(8).collect::<Vec<&str>>();
+        assert_eq!(parsed.lines()[1..], expected);
+    }
+}
@@ -0,0 +1,392 @@
+//! `TapPrettyReporter` -- A colorized, human-friendly view of TAP results for use in a terminal
+
+use std::env;
+use std::io::{self, IsTerminal, Stdout, Write};
+use std::time::Instant;
+
+use crate::tap_parser::TapEvent;
+use crate::tap_suite::TapSuite;
+use crate::tap_summary::{FailedTest, TapSummary};
+use crate::tap_test::TapTest;
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Whether to color the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorChoice {
+    /// Color only when writing to a terminal and the `NO_COLOR` environment variable is unset or empty
+    #[default]
+    Auto,
+    /// Always color
+    Always,
+    /// Never color
+    Never,
+}
+
+impl ColorChoice {
+    /// Settle on yes or no, given whether the output is a terminal.
+    pub fn should_colorize(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                is_terminal && env::var_os("NO_COLOR").map_or(true, |value| value.is_empty())
+            }
+        }
+    }
+}
+
+/// How progress is shown while results are coming in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProgressStyle {
+    /// One line per test
+    #[default]
+    Lines,
+    /// One character per test: a "." for a pass, an "F" for a failure, an "s" for a skipped or TODO test
+    Dots,
+    /// A single line, redrawn in place, counting tests as they go by. Best kept to terminals.
+    Spinner,
+}
+
+/// Renders TAP results for a human in front of a terminal. Progress is written as results arrive; details of every failure are held back and printed together by `finish`, followed by a `TapSummary`.
+///
+/// Results may come from a finished `TapSuite` or one `TapEvent` at a time, e.g. from a `TapParser` reading a live stream.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_parser::TapParser;
+/// use testanything::tap_pretty::{ColorChoice, ProgressStyle, TapPrettyReporter};
+///
+/// let mut parser = TapParser::new();
+/// let mut reporter = TapPrettyReporter::stdout(ColorChoice::Auto).style(ProgressStyle::Dots);
+///
+/// for line in ["1..2", "ok 1 Panda", "not ok 2 Bamboo", "# Ran out of bamboo"] {
+///     if let Some(event) = parser.parse_line(line) {
+///         reporter.event(&event).unwrap();
+///     }
+/// }
+///
+/// let all_passed = reporter.finish().unwrap();
+/// assert!(!all_passed);
+/// ```
+#[derive(Debug)]
+pub struct TapPrettyReporter<W: Write> {
+    sink: W,
+    color: bool,
+    style: ProgressStyle,
+    name: String,
+    total: usize,
+    passed: usize,
+    skipped: usize,
+    failures: Vec<(FailedTest, Vec<String>)>,
+    // Diagnostics only belong to a failure if they come right after it
+    collecting_diagnostics: bool,
+    bail_out: Option<String>,
+    started: Instant,
+}
+
+impl TapPrettyReporter<Stdout> {
+    /// Report to STDOUT, coloring according to `choice` and whether STDOUT is a terminal.
+    pub fn stdout(choice: ColorChoice) -> TapPrettyReporter<Stdout> {
+        let is_terminal = io::stdout().is_terminal();
+        TapPrettyReporter::new(io::stdout(), choice.should_colorize(is_terminal))
+    }
+}
+
+impl<W: Write> TapPrettyReporter<W> {
+    /// Report to any sink. Since a sink can't be asked whether it's a terminal, say outright whether to color.
+    pub fn new(sink: W, color: bool) -> TapPrettyReporter<W> {
+        TapPrettyReporter {
+            sink,
+            color,
+            style: ProgressStyle::default(),
+            name: String::new(),
+            total: 0,
+            passed: 0,
+            skipped: 0,
+            failures: Vec::new(),
+            collecting_diagnostics: false,
+            bail_out: None,
+            started: Instant::now(),
+        }
+    }
+
+    /// Set the progress style
+    pub fn style(mut self, style: ProgressStyle) -> TapPrettyReporter<W> {
+        self.style = style;
+        self
+    }
+
+    /// Set the name shown in the summary
+    pub fn name<S: Into<String>>(mut self, name: S) -> TapPrettyReporter<W> {
+        self.name = name.into();
+        self
+    }
+
+    /// Take in the next piece of a TAP stream.
+    pub fn event(&mut self, event: &TapEvent) -> io::Result<()> {
+        match event {
+            TapEvent::Test { number, test } => {
+                self.total += 1;
+                self.collecting_diagnostics = test.is_failure();
+                if test.is_skipped() {
+                    self.skipped += 1;
+                }
+                if !test.is_failure() {
+                    self.passed += 1;
+                } else {
+                    let mut diagnostics = failure_details(test);
+                    diagnostics.extend(test.diagnostics.iter().cloned());
+                    self.failures.push((
+                        FailedTest {
+                            number: *number,
+                            name: test.name.clone(),
+                        },
+                        diagnostics,
+                    ));
+                }
+                self.progress(*number, test)
+            }
+            TapEvent::Diagnostic(diagnostic) => {
+                if self.collecting_diagnostics {
+                    if let Some((_, diagnostics)) = self.failures.last_mut() {
+                        diagnostics.push(diagnostic.clone());
+                    }
+                }
+                Ok(())
+            }
+            // YAML sits between a test and its diagnostics
+            TapEvent::Yaml(fields) => {
+                if self.collecting_diagnostics {
+                    let mut structured = TapTest::default();
+                    structured.apply_yaml(fields);
+                    if let Some((_, diagnostics)) = self.failures.last_mut() {
+                        diagnostics.extend(failure_details(&structured));
+                    }
+                }
+                Ok(())
+            }
+            TapEvent::BailOut(reason) => {
+                self.collecting_diagnostics = false;
+                self.bail_out = Some(reason.clone());
+                Ok(())
+            }
+            _ => {
+                self.collecting_diagnostics = false;
+                Ok(())
+            }
+        }
+    }
+
+    /// Report a whole suite, start to finish. Returns whether every test passed.
+    pub fn report_suite(&mut self, suite: &TapSuite) -> io::Result<bool> {
+        if self.name.is_empty() {
+            self.name = suite.name.clone();
+        }
+        for (i, test) in suite.tests.iter().enumerate() {
+            self.event(&TapEvent::Test {
+                number: i as i64 + 1,
+                test: Box::new(test.clone()),
+            })?;
+        }
+        self.finish()
+    }
+
+    /// Print the details of every failure and a summary. Returns whether every test passed and nobody bailed out.
+    pub fn finish(&mut self) -> io::Result<bool> {
+        match self.style {
+            ProgressStyle::Lines => {}
+            ProgressStyle::Dots => writeln!(self.sink)?,
+            ProgressStyle::Spinner => {
+                let line = format!("{} tests, {} failed", self.total, self.failures.len());
+                writeln!(self.sink, "\r\x1b[2K{}", line)?;
+            }
+        }
+
+        if !self.failures.is_empty() {
+            writeln!(self.sink)?;
+            writeln!(self.sink, "{}", self.paint(BOLD, "Failures:"))?;
+            for (failure, diagnostics) in &self.failures {
+                let heading = format!("  {} - {}", failure.number, failure.name);
+                writeln!(self.sink, "{}", self.paint(RED, &heading))?;
+                for diagnostic in diagnostics {
+                    writeln!(self.sink, "      {}", diagnostic)?;
+                }
+            }
+        }
+
+        if let Some(reason) = &self.bail_out {
+            let line = format!("Bail out! {}", reason);
+            writeln!(self.sink)?;
+            writeln!(self.sink, "{}", self.paint(YELLOW, &line))?;
+        }
+
+        let summary = TapSummary {
+            name: self.name.clone(),
+            total: self.total,
+            passed: self.passed,
+            skipped: self.skipped,
+            failures: self
+                .failures
+                .iter()
+                .map(|(failure, _)| failure.clone())
+                .collect(),
+            elapsed: Some(self.started.elapsed()),
+            ..Default::default()
+        };
+        let success = summary.result_string() != "FAIL" && self.bail_out.is_none();
+
+        writeln!(self.sink)?;
+        let mut lines = summary.lines();
+        let result = lines.pop().unwrap_or_default();
+        for line in lines {
+            writeln!(self.sink, "{}", line)?;
+        }
+        let color = if success { GREEN } else { RED };
+        writeln!(self.sink, "{}", self.paint(color, &result))?;
+        self.sink.flush()?;
+
+        Ok(success)
+    }
+
+    fn progress(&mut self, number: i64, test: &TapTest) -> io::Result<()> {
+        let name = &test.name;
+        match self.style {
+            ProgressStyle::Lines => {
+                let line = match &test.directive {
+                    Some(directive) => self.paint(
+                        YELLOW,
+                        &format!("  - {} {} {}", number, name, directive.directive_string()),
+                    ),
+                    None if test.passed => self.paint(GREEN, &format!("  ✓ {} {}", number, name)),
+                    None => self.paint(RED, &format!("  ✗ {} {}", number, name)),
+                };
+                writeln!(self.sink, "{}", line)?;
+            }
+            ProgressStyle::Dots => {
+                let dot = if test.directive.is_some() {
+                    self.paint(YELLOW, "s")
+                } else if test.passed {
+                    self.paint(GREEN, ".")
+                } else {
+                    self.paint(RED, "F")
+                };
+                write!(self.sink, "{}", dot)?;
+                if self.total % 80 == 0 {
+                    writeln!(self.sink)?;
+                }
+            }
+            ProgressStyle::Spinner => {
+                let frame = SPINNER_FRAMES[self.total % SPINNER_FRAMES.len()];
+                let failed = self.failures.len().to_string();
+                let failed = if self.failures.is_empty() {
+                    failed
+                } else {
+                    self.paint(RED, &failed)
+                };
+                write!(
+                    self.sink,
+                    "\r\x1b[2K{} {} tests, {} failed  {}",
+                    frame, self.total, failed, name
+                )?;
+            }
+        }
+        self.sink.flush()
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Where a failure happened and what it compared, for showing above its diagnostics
+fn failure_details(test: &TapTest) -> Vec<String> {
+    let mut details = Vec::new();
+    details.extend(
+        test.location
+            .iter()
+            .map(|location| format!("at {}", location)),
+    );
+    if let Some(comparison) = &test.comparison {
+        details.extend(
+            comparison
+                .classic_lines()
+                .iter()
+                .map(|line| line.strip_prefix("  ").unwrap_or(line).to_string()),
+        );
+    }
+    details
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorChoice, ProgressStyle, TapPrettyReporter};
+    use crate::tap_parser::TapParser;
+    use crate::tap_suite::TapSuite;
+    use crate::tap_test_builder::TapTestBuilder;
+
+    #[test]
+    fn test_color_choice() {
+        assert!(ColorChoice::Always.should_colorize(false));
+        assert!(!ColorChoice::Never.should_colorize(true));
+        assert!(!ColorChoice::Auto.should_colorize(false));
+    }
+
+    #[test]
+    fn test_report_suite_lines() {
+        let tap_suite = TapSuite {
+            name: "Example TAP Suite".to_string(),
+            tests: vec![
+                TapTestBuilder::new().name("Panda").passed(true).finalize(),
+                TapTestBuilder::new()
+                    .name("Bamboo")
+                    .passed(false)
+                    .diagnostics(&["Ran out"])
+                    .finalize(),
+            ],
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        let success = TapPrettyReporter::new(&mut output, false)
+            .report_suite(&tap_suite)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(!success);
+        assert!(output
+            .starts_with("  ✓ 1 Panda\n  ✗ 2 Bamboo\n\nFailures:\n  2 - Bamboo\n      Ran out\n"));
+        assert!(output.contains("Example TAP Suite (Tests: 2 Failed: 1)"));
+        assert!(output.ends_with("Result: FAIL\n"));
+    }
+
+    #[test]
+    fn test_report_events_dots() {
+        let mut parser = TapParser::new();
+        let mut output = Vec::new();
+        let mut reporter = TapPrettyReporter::new(&mut output, true).style(ProgressStyle::Dots);
+
+        for line in ["1..2", "ok 1 Panda", "# Not a failure", "ok 2 Bamboo"] {
+            if let Some(event) = parser.parse_line(line) {
+                reporter.event(&event).unwrap();
+            }
+        }
+        assert!(reporter.finish().unwrap());
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("\x1b[32m.\x1b[0m\x1b[32m.\x1b[0m\n"));
+        assert!(!output.contains("Failures:"));
+        assert!(output.ends_with("\x1b[32mResult: PASS\x1b[0m\n"));
+    }
+}
@@ -0,0 +1,599 @@
+//! `TapProperty` -- Property-based testing: check a property against many generated values, and report it as a single `TapTest`
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt::Debug;
+#[cfg(feature = "std")]
+use std::env;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::tap_location::TapLocation;
+use crate::tap_test::TapTest;
+use crate::tap_test_builder::TapTestBuilder;
+
+/// Set this environment variable to a seed, in decimal or 0x-prefixed hex, to replay a run of properties.
+pub const PROPERTY_SEED_VAR: &str = "TAP_PROPERTY_SEED";
+
+/// A small, fast, seedable random number generator (SplitMix64). The same seed always yields the same numbers.
+#[derive(Debug, Clone)]
+pub struct TapRng {
+    state: u64,
+}
+
+impl TapRng {
+    /// Make a generator from a seed
+    pub fn new(seed: u64) -> TapRng {
+        TapRng { state: seed }
+    }
+
+    /// The next 64 random bits
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`, or 0 if `bound` is 0
+    pub fn below(&mut self, bound: u64) -> u64 {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+
+    /// A number in `min..=max`
+    pub fn between(&mut self, min: i64, max: i64) -> i64 {
+        let span = (max as u64).wrapping_sub(min as u64).wrapping_add(1);
+        let offset = if span == 0 {
+            // The whole range of i64
+            self.next_u64()
+        } else {
+            self.below(span)
+        };
+        min.wrapping_add(offset as i64)
+    }
+
+    /// A number in `0.0..1.0`
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Makes random values for a property, and simpler versions of a value for shrinking a counterexample.
+pub trait Generator {
+    /// The type of value made
+    type Value: Clone + Debug;
+
+    /// Make a value. `size` grows over a run, from 0 up to 100, and bounds how big a collection may get.
+    fn generate(&self, rng: &mut TapRng, size: usize) -> Self::Value;
+
+    /// Simpler versions of `value`, simplest first. Empty once there's nothing simpler.
+    fn shrink(&self, value: &Self::Value) -> Vec<Self::Value>;
+}
+
+/// What a property hands back: whether it held, and if it didn't, perhaps why.
+pub trait PropertyResult {
+    /// Nothing if the property held, an explanation if it didn't
+    fn into_result(self) -> Result<(), String>;
+}
+
+impl PropertyResult for bool {
+    fn into_result(self) -> Result<(), String> {
+        if self {
+            Ok(())
+        } else {
+            Err("Property returned false".to_string())
+        }
+    }
+}
+
+impl PropertyResult for Result<(), String> {
+    fn into_result(self) -> Result<(), String> {
+        self
+    }
+}
+
+/// Generates `true` and `false`
+#[derive(Debug, Clone, Copy)]
+pub struct Bools;
+
+/// Make booleans. `false` is simpler than `true`.
+pub fn bools() -> Bools {
+    Bools
+}
+
+impl Generator for Bools {
+    type Value = bool;
+
+    fn generate(&self, rng: &mut TapRng, _size: usize) -> bool {
+        rng.next_u64() & 1 == 1
+    }
+
+    fn shrink(&self, value: &bool) -> Vec<bool> {
+        if *value {
+            vec![false]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Generates integers in a range
+#[derive(Debug, Clone, Copy)]
+pub struct Ints {
+    min: i64,
+    max: i64,
+}
+
+/// Make integers in `min..=max`, favoring the bounds and zero now and then. Values shrink towards zero, or the bound nearest it.
+pub fn ints(min: i64, max: i64) -> Ints {
+    assert!(min <= max, "ints({}, {}) is an empty range", min, max);
+    Ints { min, max }
+}
+
+impl Ints {
+    fn target(&self) -> i64 {
+        0.clamp(self.min, self.max)
+    }
+}
+
+impl Generator for Ints {
+    type Value = i64;
+
+    fn generate(&self, rng: &mut TapRng, _size: usize) -> i64 {
+        match rng.below(16) {
+            0 => self.min,
+            1 => self.max,
+            2 => self.target(),
+            _ => rng.between(self.min, self.max),
+        }
+    }
+
+    fn shrink(&self, value: &i64) -> Vec<i64> {
+        let target = self.target();
+        if *value == target {
+            return Vec::new();
+        }
+        // Halve the distance to the target, again and again, ending one step away
+        let distance = (*value as i128) - (target as i128);
+        let mut candidates = vec![target];
+        let mut step = distance / 2;
+        while step != 0 {
+            candidates.push((*value as i128 - step) as i64);
+            step /= 2;
+        }
+        candidates.dedup();
+        candidates
+    }
+}
+
+/// Generates floating-point numbers in a range
+#[derive(Debug, Clone, Copy)]
+pub struct Floats {
+    min: f64,
+    max: f64,
+}
+
+/// Make finite floats in `min..=max`, favoring the bounds now and then. Values shrink towards zero, or the bound nearest it, and towards whole numbers.
+pub fn floats(min: f64, max: f64) -> Floats {
+    assert!(
+        min.is_finite() && max.is_finite() && min <= max,
+        "floats({}, {}) is not a finite range",
+        min,
+        max
+    );
+    Floats { min, max }
+}
+
+impl Generator for Floats {
+    type Value = f64;
+
+    fn generate(&self, rng: &mut TapRng, _size: usize) -> f64 {
+        match rng.below(16) {
+            0 => self.min,
+            1 => self.max,
+            _ => (self.min + (self.max - self.min) * rng.unit()).clamp(self.min, self.max),
+        }
+    }
+
+    fn shrink(&self, value: &f64) -> Vec<f64> {
+        let target = 0.0_f64.clamp(self.min, self.max);
+        let mut candidates = vec![target];
+        let whole = if *value < 0.0 {
+            -((-*value) as i64 as f64)
+        } else {
+            *value as i64 as f64
+        };
+        if whole >= self.min && whole <= self.max {
+            candidates.push(whole);
+        }
+        candidates.push(target + (*value - target) / 2.0);
+        candidates.retain(|candidate| candidate != value);
+        candidates.dedup();
+        candidates
+    }
+}
+
+/// Generates characters
+#[derive(Debug, Clone, Copy)]
+pub struct Chars;
+
+/// Make characters: mostly printable ASCII, sometimes something further afield. Characters shrink towards 'a'.
+pub fn chars() -> Chars {
+    Chars
+}
+
+impl Generator for Chars {
+    type Value = char;
+
+    fn generate(&self, rng: &mut TapRng, _size: usize) -> char {
+        let code = match rng.below(8) {
+            0 => rng.between(0x80, 0x2FFF),
+            1 => rng.between(0x1F300, 0x1F64F),
+            _ => rng.between(0x20, 0x7E),
+        };
+        char::from_u32(code as u32).unwrap_or('?')
+    }
+
+    fn shrink(&self, value: &char) -> Vec<char> {
+        let mut candidates = vec!['a'];
+        if value.is_ascii_uppercase() {
+            candidates.push(value.to_ascii_lowercase());
+        }
+        candidates.retain(|candidate| candidate != value);
+        candidates
+    }
+}
+
+/// Generates strings
+#[derive(Debug, Clone, Copy)]
+pub struct Strings {
+    max_length: usize,
+}
+
+/// Make strings of up to `max_length` characters, drawn from `chars()`. Strings shrink by dropping characters and simplifying the rest.
+pub fn strings(max_length: usize) -> Strings {
+    Strings { max_length }
+}
+
+impl Generator for Strings {
+    type Value = String;
+
+    fn generate(&self, rng: &mut TapRng, size: usize) -> String {
+        Vecs {
+            element: Chars,
+            max_length: self.max_length,
+        }
+        .generate(rng, size)
+        .into_iter()
+        .collect()
+    }
+
+    fn shrink(&self, value: &String) -> Vec<String> {
+        Vecs {
+            element: Chars,
+            max_length: self.max_length,
+        }
+        .shrink(&value.chars().collect())
+        .into_iter()
+        .map(|chars| chars.into_iter().collect())
+        .collect()
+    }
+}
+
+/// Generates vectors
+#[derive(Debug, Clone, Copy)]
+pub struct Vecs<G> {
+    element: G,
+    max_length: usize,
+}
+
+/// Make vectors of up to `max_length` elements, each made by `element`. Vectors shrink by dropping elements and shrinking the rest.
+pub fn vecs<G: Generator>(element: G, max_length: usize) -> Vecs<G> {
+    Vecs {
+        element,
+        max_length,
+    }
+}
+
+impl<G: Generator> Generator for Vecs<G> {
+    type Value = Vec<G::Value>;
+
+    fn generate(&self, rng: &mut TapRng, size: usize) -> Vec<G::Value> {
+        let length = rng.below(self.max_length.min(size) as u64 + 1) as usize;
+        (0..length)
+            .map(|_| self.element.generate(rng, size))
+            .collect()
+    }
+
+    fn shrink(&self, value: &Vec<G::Value>) -> Vec<Vec<G::Value>> {
+        let mut candidates = Vec::new();
+        if value.is_empty() {
+            return candidates;
+        }
+        candidates.push(Vec::new());
+        // Drop ever smaller chunks, then single elements
+        let mut chunk = value.len() / 2;
+        while chunk > 0 {
+            for start in (0..value.len()).step_by(chunk) {
+                let mut smaller = value[..start].to_vec();
+                smaller.extend_from_slice(&value[(start + chunk).min(value.len())..]);
+                if !smaller.is_empty() {
+                    candidates.push(smaller);
+                }
+            }
+            chunk /= 2;
+        }
+        for (i, element) in value.iter().enumerate() {
+            for simpler in self.element.shrink(element) {
+                let mut candidate = value.clone();
+                candidate[i] = simpler;
+                candidates.push(candidate);
+            }
+        }
+        candidates
+    }
+}
+
+/// Generates pairs of values
+#[derive(Debug, Clone, Copy)]
+pub struct Pairs<A, B> {
+    first: A,
+    second: B,
+}
+
+/// Make pairs, the first made by `first` and the second by `second`. Pairs shrink one side at a time.
+pub fn pairs<A: Generator, B: Generator>(first: A, second: B) -> Pairs<A, B> {
+    Pairs { first, second }
+}
+
+impl<A: Generator, B: Generator> Generator for Pairs<A, B> {
+    type Value = (A::Value, B::Value);
+
+    fn generate(&self, rng: &mut TapRng, size: usize) -> (A::Value, B::Value) {
+        let first = self.first.generate(rng, size);
+        (first, self.second.generate(rng, size))
+    }
+
+    fn shrink(&self, value: &(A::Value, B::Value)) -> Vec<(A::Value, B::Value)> {
+        let (first, second) = value;
+        let mut candidates = self
+            .first
+            .shrink(first)
+            .into_iter()
+            .map(|simpler| (simpler, second.clone()))
+            .collect::<Vec<_>>();
+        candidates.extend(
+            self.second
+                .shrink(second)
+                .into_iter()
+                .map(|simpler| (first.clone(), simpler)),
+        );
+        candidates
+    }
+}
+
+/// A property to check against generated values. A failure is shrunk to a minimal counterexample, and the test's diagnostics give it along with the seed, so the failure can be replayed from the TAP log alone.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_property::{ints, vecs, TapProperty};
+///
+/// let tap_test = TapProperty::new("Sorting keeps the length")
+///     .with_seed(42)
+///     .check(&vecs(ints(-100, 100), 20), |values| {
+///         let mut sorted = values.clone();
+///         sorted.sort();
+///         sorted.len() == values.len()
+///     });
+/// assert!(tap_test.passed);
+///
+/// let tap_test = TapProperty::new("No big numbers")
+///     .with_seed(42)
+///     .check(&ints(0, 1000), |n| *n < 500);
+/// assert!(!tap_test.passed);
+/// assert_eq!(tap_test.diagnostics[1], "Counterexample: 500");
+/// ```
+#[derive(Debug, Clone)]
+pub struct TapProperty {
+    name: String,
+    cases: usize,
+    seed: u64,
+    max_shrinks: usize,
+}
+
+impl TapProperty {
+    /// Make a property checked against 100 cases. The seed comes from the `TAP_PROPERTY_SEED` environment variable if set, and the clock otherwise.
+    pub fn new(name: &str) -> TapProperty {
+        TapProperty {
+            name: name.to_string(),
+            cases: 100,
+            seed: default_seed(),
+            max_shrinks: 1000,
+        }
+    }
+
+    /// Check this many generated values
+    pub fn with_cases(mut self, cases: usize) -> TapProperty {
+        self.cases = cases;
+        self
+    }
+
+    /// Generate values from this seed
+    pub fn with_seed(mut self, seed: u64) -> TapProperty {
+        self.seed = seed;
+        self
+    }
+
+    /// Give up shrinking a counterexample after this many simplifications
+    pub fn with_max_shrinks(mut self, max_shrinks: usize) -> TapProperty {
+        self.max_shrinks = max_shrinks;
+        self
+    }
+
+    /// The seed values are generated from
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Check `property` against values made by `generator`, producing a test located where this was called from.
+    #[track_caller]
+    pub fn check<G, P, R>(&self, generator: &G, property: P) -> TapTest
+    where
+        G: Generator,
+        P: Fn(&G::Value) -> R,
+        R: PropertyResult,
+    {
+        let mut builder = TapTestBuilder::new();
+        builder
+            .name(self.name.as_str())
+            .location(TapLocation::caller());
+
+        let mut rng = TapRng::new(self.seed);
+        for case in 0..self.cases {
+            let size = case * 100 / self.cases.max(1);
+            let value = generator.generate(&mut rng, size);
+            let Err(reason) = property(&value).into_result() else {
+                continue;
+            };
+
+            let (minimal, reason, shrinks) = self.shrink(generator, &property, value, reason);
+            let diagnostics = [
+                format!(
+                    "Falsified after {} of {} cases, then shrunk {} times",
+                    case + 1,
+                    self.cases,
+                    shrinks
+                ),
+                format!("Counterexample: {:?}", minimal),
+                reason,
+                format!("Reproduce with {}={:#x}", PROPERTY_SEED_VAR, self.seed),
+            ];
+            let diagnostics = diagnostics
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<&str>>();
+            return builder.passed(false).diagnostics(&diagnostics).finalize();
+        }
+
+        builder.passed(true).finalize()
+    }
+
+    /// Simplify a counterexample for as long as a simpler value still fails. Yields the simplest found, why it failed, and how many simplifications were made.
+    fn shrink<G, P, R>(
+        &self,
+        generator: &G,
+        property: &P,
+        mut value: G::Value,
+        mut reason: String,
+    ) -> (G::Value, String, usize)
+    where
+        G: Generator,
+        P: Fn(&G::Value) -> R,
+        R: PropertyResult,
+    {
+        let mut shrinks = 0;
+        'simplify: while shrinks < self.max_shrinks {
+            for candidate in generator.shrink(&value) {
+                if let Err(candidate_reason) = property(&candidate).into_result() {
+                    value = candidate;
+                    reason = candidate_reason;
+                    shrinks += 1;
+                    continue 'simplify;
+                }
+            }
+            break;
+        }
+        (value, reason, shrinks)
+    }
+}
+
+#[cfg(feature = "std")]
+fn default_seed() -> u64 {
+    if let Ok(seed) = env::var(PROPERTY_SEED_VAR) {
+        let seed = seed.trim();
+        let parsed = match seed.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => seed.parse::<u64>(),
+        };
+        if let Ok(seed) = parsed {
+            return seed;
+        }
+    }
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(not(feature = "std"))]
+fn default_seed() -> u64 {
+    // Without a clock or an environment, every run is the same run
+    0x5EED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chars, ints, pairs, strings, vecs, Generator, TapProperty, TapRng};
+
+    #[test]
+    fn test_rng() {
+        let mut rng = TapRng::new(7);
+        let first = (0..5).map(|_| rng.next_u64()).collect::<Vec<u64>>();
+        let mut rng = TapRng::new(7);
+        assert_eq!(first, (0..5).map(|_| rng.next_u64()).collect::<Vec<u64>>());
+
+        for _ in 0..1000 {
+            assert!((-3..=3).contains(&rng.between(-3, 3)));
+            assert!(rng.below(10) < 10);
+            assert!((0.0..1.0).contains(&rng.unit()));
+        }
+        rng.between(i64::MIN, i64::MAX);
+    }
+
+    #[test]
+    fn test_shrinking() {
+        assert_eq!(ints(-100, 100).shrink(&40), vec![0, 20, 30, 35, 38, 39]);
+        assert_eq!(ints(5, 100).shrink(&5), Vec::<i64>::new());
+
+        let shrunk = vecs(ints(0, 10), 10).shrink(&vec![3, 4]);
+        assert_eq!(shrunk[..3], [vec![], vec![4], vec![3]]);
+        assert!(shrunk.contains(&vec![0, 4]));
+
+        assert_eq!(chars().shrink(&'Q'), vec!['a', 'q']);
+    }
+
+    #[test]
+    fn test_check_finds_minimal_counterexample() {
+        let property = TapProperty::new("Short strings or small numbers").with_seed(1);
+
+        let tap_test = property.check(&pairs(strings(20), ints(0, 10)), |(s, n)| {
+            if s.chars().count() >= 3 && *n >= 5 {
+                Err(format!("{:?} is long and {} is big", s, n))
+            } else {
+                Ok(())
+            }
+        });
+
+        assert!(!tap_test.passed);
+        assert_eq!(tap_test.diagnostics[1], "Counterexample: (\"aaa\", 5)");
+        assert_eq!(tap_test.diagnostics[2], "\"aaa\" is long and 5 is big");
+        assert_eq!(
+            tap_test.diagnostics[3],
+            "Reproduce with TAP_PROPERTY_SEED=0x1"
+        );
+        assert_eq!(tap_test.location.unwrap().file, file!());
+
+        // The same seed finds the same failure
+        let again = property.check(&pairs(strings(20), ints(0, 10)), |(s, n)| {
+            s.chars().count() < 3 || *n < 5
+        });
+        assert_eq!(tap_test.diagnostics[..2], again.diagnostics[..2]);
+    }
+}
@@ -0,0 +1,470 @@
+//! `Regex` -- Just enough regular expression to check what a command printed
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+use core::iter::Peekable;
+use core::str::Chars;
+
+/// A compiled regular expression.
+///
+/// Supports literals, `.`, classes like `[a-z]` and `[^0-9]`, the escapes `\d`, `\w`, `\s` and their negations, `^` and `$` anchors, groups with `|` alternatives, and the greedy quantifiers `*`, `+`, `?` and `{m,n}`. Classes and escapes only know about ASCII. There are no captures, lookaround or backreferences.
+///
+/// Anchors work line by line, since the text is usually a command's whole output: `^` matches at the start of any line and `$` at the end of any, before its `\n` or `\r\n`.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_regex::Regex;
+///
+/// let regex = Regex::new(r"^test result: ok\. \d+ passed").unwrap();
+///
+/// assert!(regex.is_match("test result: ok. 62 passed; 0 failed"));
+/// assert!(!regex.is_match("test result: FAILED. 61 passed; 1 failed"));
+/// assert!(regex.is_match("running 62 tests\ntest result: ok. 62 passed; 0 failed\n"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Regex {
+    pattern: String,
+    program: Vec<Inst>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Start,
+    End,
+    Group(Vec<Vec<Node>>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+impl Regex {
+    /// Compile a pattern, or explain what's wrong with it
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let mut chars = pattern.chars().peekable();
+        let mut alternatives = parse_alternatives(&mut chars)?;
+        if chars.next().is_some() {
+            return Err(format!("Unmatched ')' in /{}/", pattern));
+        }
+        let nodes = if alternatives.len() == 1 {
+            alternatives.remove(0)
+        } else {
+            vec![Node::Group(alternatives)]
+        };
+        let mut program = Vec::new();
+        compile(&nodes, &mut program)?;
+        program.push(Inst::Match);
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            program,
+        })
+    }
+
+    /// The pattern the regex was compiled from
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Does the pattern match anywhere in `text`?
+    pub fn is_match(&self, text: &str) -> bool {
+        let text = text.chars().collect::<Vec<char>>();
+        Vm {
+            program: &self.program,
+            text: &text,
+            added_at: vec![usize::MAX; self.program.len()],
+            stack: Vec::new(),
+        }
+        .is_match()
+    }
+}
+
+/// Like "/^ok$/"
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "/{}/", self.pattern)
+    }
+}
+
+fn parse_alternatives(chars: &mut Peekable<Chars>) -> Result<Vec<Vec<Node>>, String> {
+    let mut alternatives = vec![parse_sequence(chars)?];
+    while chars.next_if_eq(&'|').is_some() {
+        alternatives.push(parse_sequence(chars)?);
+    }
+    Ok(alternatives)
+}
+
+fn parse_sequence(chars: &mut Peekable<Chars>) -> Result<Vec<Node>, String> {
+    let mut nodes = Vec::new();
+    while let Some(&c) = chars.peek() {
+        if c == '|' || c == ')' {
+            break;
+        }
+        chars.next();
+        let node = match c {
+            '(' => {
+                // Every group is non-capturing anyway
+                if chars.next_if_eq(&'?').is_some() && chars.next_if_eq(&':').is_none() {
+                    return Err("Only (?: ) groups are supported".to_string());
+                }
+                let alternatives = parse_alternatives(chars)?;
+                if chars.next() != Some(')') {
+                    return Err("Unclosed '('".to_string());
+                }
+                Node::Group(alternatives)
+            }
+            '[' => parse_class(chars)?,
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '\\' => parse_escape(chars)?,
+            '*' | '+' | '?' | '{' => {
+                let node = nodes
+                    .pop()
+                    .ok_or_else(|| format!("Nothing to repeat before '{}'", c))?;
+                let (min, max) = match c {
+                    '*' => (0, None),
+                    '+' => (1, None),
+                    '?' => (0, Some(1)),
+                    _ => parse_bounds(chars)?,
+                };
+                Node::Repeat {
+                    node: Box::new(node),
+                    min,
+                    max,
+                }
+            }
+            c => Node::Char(c),
+        };
+        nodes.push(node);
+    }
+    Ok(nodes)
+}
+
+/// The bounds of a `{m}`, `{m,}` or `{m,n}` quantifier, after the "{"
+fn parse_bounds(chars: &mut Peekable<Chars>) -> Result<(usize, Option<usize>), String> {
+    let mut bounds = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some(c) => bounds.push(c),
+            None => return Err("Unclosed '{'".to_string()),
+        }
+    }
+    let number = |n: &str| {
+        n.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("Bad repetition {{{}}}", bounds))
+    };
+    match bounds.split_once(',') {
+        None => number(&bounds).map(|n| (n, Some(n))),
+        Some((min, max)) if max.trim().is_empty() => number(min).map(|min| (min, None)),
+        Some((min, max)) => Ok((number(min)?, Some(number(max)?))),
+    }
+}
+
+fn parse_escape(chars: &mut Peekable<Chars>) -> Result<Node, String> {
+    let c = chars.next().ok_or("A pattern can't end with '\\'")?;
+    Ok(match escape_ranges(c) {
+        Some(ranges) => Node::Class {
+            ranges,
+            negated: c.is_ascii_uppercase(),
+        },
+        None => Node::Char(escaped_char(c)),
+    })
+}
+
+/// The ranges `\d`, `\w` and `\s` stand for, and their uppercase negations
+fn escape_ranges(c: char) -> Option<Vec<(char, char)>> {
+    match c.to_ascii_lowercase() {
+        'd' => Some(vec![('0', '9')]),
+        'w' => Some(vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')]),
+        's' => Some(vec![(' ', ' '), ('\t', '\r')]),
+        _ => None,
+    }
+}
+
+fn escaped_char(c: char) -> char {
+    match c {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        c => c,
+    }
+}
+
+fn parse_class(chars: &mut Peekable<Chars>) -> Result<Node, String> {
+    let negated = chars.next_if_eq(&'^').is_some();
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let c = chars.next().ok_or("Unclosed '['")?;
+        if c == ']' && !first {
+            break;
+        }
+        first = false;
+        let low = if c == '\\' {
+            let escaped = chars.next().ok_or("Unclosed '['")?;
+            match escape_ranges(escaped) {
+                Some(_) if escaped.is_ascii_uppercase() => {
+                    return Err(format!("\\{} isn't supported in a class", escaped))
+                }
+                Some(escape) => {
+                    ranges.extend(escape);
+                    continue;
+                }
+                None => escaped_char(escaped),
+            }
+        } else {
+            c
+        };
+        let mut lookahead = chars.clone();
+        if lookahead.next() == Some('-') && lookahead.peek().is_some_and(|c| *c != ']') {
+            chars.next();
+            let high = match chars.next() {
+                Some('\\') => escaped_char(chars.next().ok_or("Unclosed '['")?),
+                Some(high) => high,
+                None => return Err("Unclosed '['".to_string()),
+            };
+            if high < low {
+                return Err(format!("Backwards range {}-{}", low, high));
+            }
+            ranges.push((low, high));
+        } else {
+            ranges.push((low, low));
+        }
+    }
+    Ok(Node::Class { ranges, negated })
+}
+
+/// One step of a compiled pattern. Patterns are compiled into a program for a Pike VM, which runs every way the pattern could match side by side, a character at a time, so matching takes time proportional to the text and never recurses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Start,
+    End,
+    /// Carry on at both, preferring the first
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// The most instructions a pattern may compile to, so `a{1000}{1000}` is refused rather than eating all the memory
+const MAX_PROGRAM: usize = 100_000;
+
+fn compile(nodes: &[Node], program: &mut Vec<Inst>) -> Result<(), String> {
+    for node in nodes {
+        if program.len() > MAX_PROGRAM {
+            return Err("Too much repetition".to_string());
+        }
+        match node {
+            Node::Char(c) => program.push(Inst::Char(*c)),
+            Node::Any => program.push(Inst::Any),
+            Node::Class { ranges, negated } => program.push(Inst::Class {
+                ranges: ranges.clone(),
+                negated: *negated,
+            }),
+            Node::Start => program.push(Inst::Start),
+            Node::End => program.push(Inst::End),
+            Node::Group(alternatives) => {
+                // Parsing never makes a group without at least one alternative
+                let Some((last, others)) = alternatives.split_last() else {
+                    continue;
+                };
+                let mut jumps = Vec::new();
+                for alternative in others {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(alternative, program)?;
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                compile(last, program)?;
+                for jump in jumps {
+                    program[jump] = Inst::Jump(program.len());
+                }
+            }
+            Node::Repeat { node, min, max } => {
+                let node = core::slice::from_ref(node.as_ref());
+                for _ in 0..*min {
+                    compile(node, program)?;
+                }
+                let optional = match max {
+                    Some(max) => max.saturating_sub(*min),
+                    None => 1,
+                };
+                let mut splits = Vec::new();
+                for _ in 0..optional {
+                    splits.push(program.len());
+                    program.push(Inst::Split(program.len() + 1, 0));
+                    compile(node, program)?;
+                    if max.is_none() {
+                        program.push(Inst::Jump(splits[0]));
+                    }
+                }
+                for split in splits {
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs a compiled program over some text
+struct Vm<'a> {
+    program: &'a [Inst],
+    text: &'a [char],
+    // The position each instruction was last added to a thread list at, so each is only added once per position
+    added_at: Vec<usize>,
+    stack: Vec<usize>,
+}
+
+impl Vm<'_> {
+    fn is_match(&mut self) -> bool {
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+        for at in 0..=self.text.len() {
+            // A match may start anywhere
+            if self.add(&mut current, 0, at) {
+                return true;
+            }
+            let c = self.text.get(at).copied();
+            for &pc in &current {
+                let matched = match &self.program[pc] {
+                    Inst::Char(expected) => c == Some(*expected),
+                    Inst::Any => c.is_some_and(|c| c != '\n'),
+                    Inst::Class { ranges, negated } => c.is_some_and(|c| {
+                        ranges.iter().any(|(low, high)| (*low..=*high).contains(&c)) != *negated
+                    }),
+                    _ => false,
+                };
+                if matched && self.add(&mut next, pc + 1, at + 1) {
+                    return true;
+                }
+            }
+            core::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+        false
+    }
+
+    fn at_line_end(&self, at: usize) -> bool {
+        match self.text.get(at) {
+            None | Some('\n') => true,
+            Some('\r') => self.text.get(at + 1) == Some(&'\n'),
+            Some(_) => false,
+        }
+    }
+
+    /// Add the instructions reachable from `pc` without reading a character to `threads`, or say whether they include a match
+    fn add(&mut self, threads: &mut Vec<usize>, pc: usize, at: usize) -> bool {
+        self.stack.push(pc);
+        while let Some(pc) = self.stack.pop() {
+            if self.added_at[pc] == at {
+                continue;
+            }
+            self.added_at[pc] = at;
+            match self.program[pc] {
+                Inst::Match => {
+                    self.stack.clear();
+                    return true;
+                }
+                Inst::Jump(target) => self.stack.push(target),
+                Inst::Split(first, second) => {
+                    self.stack.push(second);
+                    self.stack.push(first);
+                }
+                Inst::Start if at == 0 || self.text[at - 1] == '\n' => self.stack.push(pc + 1),
+                Inst::End if self.at_line_end(at) => self.stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                _ => threads.push(pc),
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Regex;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn test_is_match() {
+        assert!(matches("bamboo", "pandas eat bamboo"));
+        assert!(!matches("^bamboo", "pandas eat bamboo"));
+        assert!(matches("^pandas? eat", "panda eat"));
+        assert!(matches(r"\d{2,3} pandas$", "There are 120 pandas"));
+        assert!(!matches(r"^\d{2,3} pandas$", "1200 pandas"));
+        assert!(matches("^(red|giant) panda$", "giant panda"));
+        assert!(!matches("^(red|giant) panda$", "grey panda"));
+        assert!(matches("^[a-c]+[^a-c]$", "abcabcd"));
+        assert!(!matches("^[a-c]+[^a-c]$", "abcabc"));
+        assert!(matches(r"^\w+\s\S+$", "panda bamboo!"));
+        assert!(matches("^a.*b$", "a and b"));
+        assert!(matches("^(a*)*b$", "aaab"));
+        assert!(matches(r"1\.5", "1.5"));
+        assert!(!matches(r"1\.5", "125"));
+        assert!(matches("^$", ""));
+        assert!(matches("^(|a)b{0}c{1,2}$", "acc"));
+    }
+
+    #[test]
+    fn test_anchors_on_lines() {
+        assert!(matches("^hi$", "hi\n"));
+        assert!(matches("^hi$", "hello\r\nhi\r\nbye"));
+        assert!(matches("^bye$", "hello\nbye"));
+        assert!(!matches("^hi$", "hi there\noh hi"));
+        assert!(!matches("^hi$", "hi\r"));
+    }
+
+    #[test]
+    fn test_long_text() {
+        let long = "a".repeat(200_000);
+        assert!(matches("^a.*b$", &(long.clone() + "b")));
+        assert!(!matches("^a.*b$", &long));
+        assert!(!matches("^(a*)*b$", &long[..1_000]));
+        assert!(!matches("(a|aa)+c", &long));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Regex::new("(panda").unwrap_err(), "Unclosed '('");
+        assert_eq!(
+            Regex::new("panda)").unwrap_err(),
+            "Unmatched ')' in /panda)/"
+        );
+        assert_eq!(Regex::new("*").unwrap_err(), "Nothing to repeat before '*'");
+        assert_eq!(Regex::new("[z-a]").unwrap_err(), "Backwards range z-a");
+        assert_eq!(Regex::new("a{x}").unwrap_err(), "Bad repetition {x}");
+        assert_eq!(
+            Regex::new("a{1000}{1000}").unwrap_err(),
+            "Too much repetition"
+        );
+        assert_eq!(Regex::new("panda").unwrap().to_string(), "/panda/");
+    }
+}
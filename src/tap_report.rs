@@ -0,0 +1,448 @@
+//! `markdown_report` and `html_report` -- Readable reports of a `TapSummary`, for pull request comments and archived build artifacts
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::time::Duration;
+
+use crate::tap_summary::{SummarizedTest, TapSummary, TestStatus};
+
+const STATUSES: [TestStatus; 4] = [
+    TestStatus::Pass,
+    TestStatus::Fail,
+    TestStatus::Skip,
+    TestStatus::Todo,
+];
+
+/// Produce a Markdown report: a heading, a tally, and a table with a row per test. Diagnostics are tucked into collapsible details. If any tests are tagged, a table of how each tag's tests went follows.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_parser::TapParser;
+/// use testanything::tap_report::markdown_report;
+/// use testanything::tap_summary::TapSummary;
+///
+/// let tap_suite = TapParser::new().parse_suite("Zoo", "1..2\nok 1 Panda\nnot ok 2 Tiger");
+///
+/// let report = markdown_report(&TapSummary::new(&tap_suite));
+///
+/// assert_eq!(report[0], "## \u{274C} Zoo");
+/// assert_eq!(report[2], "**FAIL**: 2 tests, 1 passed, 1 failed");
+/// assert_eq!(report[7], "| \u{274C} | 2 | Tiger |  |");
+/// ```
+pub fn markdown_report(summary: &TapSummary) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "## {} {}",
+            result_emoji(summary),
+            escape_markdown(&summary.name)
+        ),
+        String::new(),
+        format!("**{}**: {}", summary.result_string(), tally(summary)),
+        String::new(),
+    ];
+    for problem in summary.problems() {
+        lines.push(format!(
+            "> {} {}",
+            TestStatus::Fail.emoji(),
+            escape_markdown(&problem)
+        ));
+        lines.push(String::new());
+    }
+    lines.extend([
+        "| | # | Test | Duration |".to_string(),
+        "| --- | --: | --- | --: |".to_string(),
+    ]);
+
+    for test in &summary.tests {
+        let mut cell = escape_markdown(&test.name);
+        if let Some(note) = note(test) {
+            cell.push_str(&format!(" *({})*", escape_markdown(&note)));
+        }
+        if !test.diagnostics.is_empty() {
+            let diagnostics = test
+                .diagnostics
+                .iter()
+                .map(|diagnostic| escape_markdown(diagnostic))
+                .collect::<Vec<String>>();
+            cell.push_str(&format!(
+                "<details><summary>Diagnostics</summary><pre>{}</pre></details>",
+                diagnostics.join("<br>")
+            ));
+        }
+        lines.push(format!(
+            "| {} | {} | {} | {} |",
+            test.status.emoji(),
+            test.number,
+            cell,
+            format_duration(test.duration)
+        ));
+    }
+
+    let by_tag = summary.by_tag();
+    if !by_tag.is_empty() {
+        lines.extend([
+            String::new(),
+            "| Tag | Tests | Failed | Skipped |".to_string(),
+            "| --- | --: | --: | --: |".to_string(),
+        ]);
+        lines.extend(by_tag.iter().map(|tally| {
+            format!(
+                "| {} | {} | {} | {} |",
+                escape_markdown(&tally.tag),
+                tally.total,
+                tally.failed,
+                tally.skipped
+            )
+        }));
+    }
+
+    lines
+}
+
+/// Produce a standalone HTML page: a heading, a tally, buttons for showing only the tests of one status or tag, and a table with a row per test. Diagnostics are tucked into collapsible details.
+pub fn html_report(summary: &TapSummary) -> Vec<String> {
+    let title = format!(
+        "{} \u{2014} {}",
+        escape_html(&summary.name),
+        summary.result_string()
+    );
+    let mut lines = vec![
+        "<!DOCTYPE html>".to_string(),
+        "<html lang=\"en\">".to_string(),
+        "<head>".to_string(),
+        "<meta charset=\"utf-8\">".to_string(),
+        format!("<title>{}</title>", title),
+        "<style>".to_string(),
+    ];
+    lines.extend(STYLE.lines().map(|line| line.to_string()));
+    lines.extend([
+        "</style>".to_string(),
+        "</head>".to_string(),
+        "<body>".to_string(),
+        format!(
+            "<h1>{} {}</h1>",
+            result_emoji(summary),
+            escape_html(&summary.name)
+        ),
+        format!(
+            "<p class=\"tally\"><strong>{}</strong>: {}</p>",
+            summary.result_string(),
+            escape_html(&tally(summary))
+        ),
+    ]);
+    for problem in summary.problems() {
+        lines.push(format!(
+            "<p class=\"problem\">{} {}</p>",
+            TestStatus::Fail.emoji(),
+            escape_html(&problem)
+        ));
+    }
+    lines.extend([
+        "<nav>".to_string(),
+        format!(
+            "<button type=\"button\" data-filter=\"all\" class=\"active\">All ({})</button>",
+            summary.tests.len()
+        ),
+    ]);
+    for status in STATUSES {
+        lines.push(format!(
+            "<button type=\"button\" data-filter=\"{}\">{} {} ({})</button>",
+            status.label(),
+            status.emoji(),
+            heading(status),
+            count(summary, status)
+        ));
+    }
+    for tally in summary.by_tag() {
+        lines.push(format!(
+            "<button type=\"button\" data-filter=\"tag:{tag}\">{tag} ({})</button>",
+            tally.total,
+            tag = escape_html(&tally.tag)
+        ));
+    }
+    lines.extend([
+        "</nav>".to_string(),
+        "<table>".to_string(),
+        "<thead><tr><th></th><th>#</th><th>Test</th><th>Duration</th></tr></thead>".to_string(),
+        "<tbody>".to_string(),
+    ]);
+
+    for test in &summary.tests {
+        let mut cell = escape_html(&test.name);
+        if let Some(note) = note(test) {
+            cell.push_str(&format!(" <em>({})</em>", escape_html(&note)));
+        }
+        if !test.diagnostics.is_empty() {
+            let diagnostics = test
+                .diagnostics
+                .iter()
+                .map(|diagnostic| escape_html(diagnostic))
+                .collect::<Vec<String>>();
+            cell.push_str(&format!(
+                "<details><summary>Diagnostics</summary><pre>{}</pre></details>",
+                diagnostics.join("\n")
+            ));
+        }
+        let tags = if test.tags.is_empty() {
+            String::new()
+        } else {
+            format!(" data-tags=\"{}\"", escape_html(&test.tags.join(" ")))
+        };
+        lines.push(format!(
+            "<tr class=\"{status}\" data-status=\"{status}\"{tags}><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            test.status.emoji(),
+            test.number,
+            cell,
+            format_duration(test.duration),
+            status = test.status.label(),
+            tags = tags
+        ));
+    }
+
+    lines.extend([
+        "</tbody>".to_string(),
+        "</table>".to_string(),
+        "<script>".to_string(),
+    ]);
+    lines.extend(SCRIPT.lines().map(|line| line.to_string()));
+    lines.extend([
+        "</script>".to_string(),
+        "</body>".to_string(),
+        "</html>".to_string(),
+    ]);
+
+    lines
+}
+
+const STYLE: &str = "body { font-family: system-ui, sans-serif; margin: 2em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border-bottom: 1px solid #ddd; padding: 0.4em 0.6em; text-align: left; vertical-align: top; }
+td:nth-child(2), td:nth-child(4) { text-align: right; white-space: nowrap; }
+tr.fail td, p.problem { background: #fdecea; }
+pre { margin: 0.5em 0 0; white-space: pre-wrap; }
+nav { margin: 1em 0; }
+nav button { margin-right: 0.4em; padding: 0.3em 0.8em; border: 1px solid #999; border-radius: 1em; background: #fff; cursor: pointer; }
+nav button.active { background: #333; color: #fff; }";
+
+const SCRIPT: &str = "document.querySelectorAll('nav button').forEach(function (button) {
+  button.addEventListener('click', function () {
+    var filter = button.dataset.filter;
+    document.querySelectorAll('nav button').forEach(function (other) {
+      other.classList.toggle('active', other === button);
+    });
+    document.querySelectorAll('tbody tr').forEach(function (row) {
+      var shown = filter.startsWith('tag:')
+        ? (row.dataset.tags || '').split(' ').indexOf(filter.slice(4)) >= 0
+        : row.dataset.status === filter;
+      row.hidden = filter !== 'all' && !shown;
+    });
+  });
+});";
+
+fn result_emoji(summary: &TapSummary) -> &'static str {
+    match summary.result_string().as_str() {
+        "PASS" => TestStatus::Pass.emoji(),
+        "FAIL" => TestStatus::Fail.emoji(),
+        _ => TestStatus::Skip.emoji(),
+    }
+}
+
+fn heading(status: TestStatus) -> &'static str {
+    match status {
+        TestStatus::Pass => "Passed",
+        TestStatus::Fail => "Failed",
+        TestStatus::Skip => "Skipped",
+        TestStatus::Todo => "TODO",
+    }
+}
+
+fn count(summary: &TapSummary, status: TestStatus) -> usize {
+    summary
+        .tests
+        .iter()
+        .filter(|test| test.status == status)
+        .count()
+}
+
+/// Like "5 tests, 2 passed, 2 failed, 1 skipped, in 1.250s", leaving out the statuses no test had
+fn tally(summary: &TapSummary) -> String {
+    let mut parts = vec![format!("{} tests", summary.total)];
+    for (status, word) in [
+        (TestStatus::Pass, "passed"),
+        (TestStatus::Fail, "failed"),
+        (TestStatus::Skip, "skipped"),
+        (TestStatus::Todo, "TODO"),
+    ] {
+        let count = count(summary, status);
+        if count > 0 {
+            parts.push(format!("{} {}", count, word));
+        }
+    }
+    let mut tally = parts.join(", ");
+    if let Some(elapsed) = summary.elapsed {
+        tally.push_str(&format!(", in {:.3}s", elapsed.as_secs_f64()));
+    }
+    tally
+}
+
+/// The directive and its reason, for a skipped or TODO test
+fn note(test: &SummarizedTest) -> Option<String> {
+    let keyword = match test.status {
+        TestStatus::Skip => "SKIP",
+        TestStatus::Todo => "TODO",
+        _ => return None,
+    };
+    Some(
+        format!("{} {}", keyword, test.reason)
+            .trim_end()
+            .to_string(),
+    )
+}
+
+fn format_duration(duration: Option<Duration>) -> String {
+    duration
+        .map(|duration| format!("{:.3}s", duration.as_secs_f64()))
+        .unwrap_or_default()
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Text in a table cell mustn't break out of it, nor be read as formatting.
+fn escape_markdown(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len());
+    for c in escape_html(s).chars() {
+        match c {
+            '|' => buf.push_str("&#124;"),
+            '\\' | '`' | '*' | '_' | '[' | ']' | '#' | '~' => {
+                buf.push('\\');
+                buf.push(c);
+            }
+            c => buf.push(c),
+        }
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{html_report, markdown_report};
+    use crate::tap_parser::TapParser;
+    use crate::tap_summary::TapSummary;
+
+    use std::time::Duration;
+
+    fn summary() -> TapSummary {
+        let tap_suite = TapParser::new().parse_suite(
+            "Zoo",
+            "1..4
+ok 1 Panda
+not ok 2 Tiger | Lion
+# Escaped <again>
+# Hungry
+ok 3 Curry # SKIP too_spicy
+not ok 4 Noodle # TODO",
+        );
+        TapSummary::new(&tap_suite).with_elapsed(Duration::from_millis(1250))
+    }
+
+    #[test]
+    fn test_markdown_report() {
+        let expected = vec![
+            "## \u{274C} Zoo",
+            "",
+            "**FAIL**: 4 tests, 1 passed, 1 failed, 1 skipped, 1 TODO, in 1.250s",
+            "",
+            "| | # | Test | Duration |",
+            "| --- | --: | --- | --: |",
+            "| \u{2705} | 1 | Panda |  |",
+            "| \u{274C} | 2 | Tiger &#124; Lion<details><summary>Diagnostics</summary><pre>Escaped &lt;again&gt;<br>Hungry</pre></details> |  |",
+            "| \u{23ED}\u{FE0F} | 3 | Curry *(SKIP too\\_spicy)* |  |",
+            "| \u{1F6A7} | 4 | Noodle *(TODO)* |  |",
+        ];
+
+        assert_eq!(expected, markdown_report(&summary()));
+    }
+
+    #[test]
+    fn test_reports_of_bailed_out_run() {
+        let tap_suite = TapParser::new().parse_suite("Zoo", "1..2\nok 1 a\nBail out! db down");
+        let summary = TapSummary::new(&tap_suite);
+
+        assert_eq!(
+            markdown_report(&summary)[..6],
+            [
+                "## \u{274C} Zoo",
+                "",
+                "**FAIL**: 1 tests, 1 passed",
+                "",
+                "> \u{274C} Bailed out: db down",
+                "",
+            ]
+        );
+        assert!(html_report(&summary)
+            .contains(&"<p class=\"problem\">\u{274C} Planned 2 tests but ran 1</p>".to_string()));
+    }
+
+    #[test]
+    fn test_reports_by_tag() {
+        let tap_suite = TapParser::new().parse_suite(
+            "Zoo",
+            "1..2
+ok 1 Panda
+  ---
+  tags:
+    - network
+    - slow
+  ...
+not ok 2 Tiger
+  ---
+  tags:
+    - network
+  ...",
+        );
+        let summary = TapSummary::new(&tap_suite);
+
+        let markdown = markdown_report(&summary);
+        assert_eq!(
+            markdown[markdown.len() - 4..],
+            [
+                "| Tag | Tests | Failed | Skipped |",
+                "| --- | --: | --: | --: |",
+                "| network | 2 | 1 | 0 |",
+                "| slow | 1 | 0 | 0 |",
+            ]
+        );
+
+        let html = html_report(&summary).join("\n");
+        assert!(html
+            .contains("<button type=\"button\" data-filter=\"tag:network\">network (2)</button>"));
+        assert!(
+            html.contains("<tr class=\"pass\" data-status=\"pass\" data-tags=\"network slow\">")
+        );
+    }
+
+    #[test]
+    fn test_html_report() {
+        let html = html_report(&summary()).join("\n");
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Zoo \u{2014} FAIL</title>"));
+        assert!(html
+            .contains("<button type=\"button\" data-filter=\"fail\">\u{274C} Failed (1)</button>"));
+        assert!(html.contains(
+            "<tr class=\"fail\" data-status=\"fail\"><td>\u{274C}</td><td>2</td><td>Tiger | Lion<details><summary>Diagnostics</summary><pre>Escaped &lt;again&gt;\nHungry</pre></details></td><td></td></tr>"
+        ));
+        assert!(html.ends_with("</html>"));
+    }
+}
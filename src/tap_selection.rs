@@ -0,0 +1,307 @@
+//! `TapSelection` -- Pick out which tests of a large suite to run, by name, tag or shard
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::tap_directive::TapDirective;
+use crate::tap_regex::Regex;
+use crate::tap_suite::TapSuite;
+use crate::tap_test::TapTest;
+
+/// The SKIP reason given to tests which weren't selected
+pub const DESELECTED_REASON: &str = "filtered";
+
+/// Which tests to run. Tests must match one of the names given, if any, carry one of the tags given, if any, carry none of the excluded tags, and fall in the shard, if there is one. A selection with nothing given selects everything.
+///
+/// Tests which aren't selected are left out, and those after them renumbered, unless they're to be reported as `# SKIP filtered` instead.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_selection::TapSelection;
+///
+/// let selection = TapSelection::new().with_name("panda*").without_tag("slow");
+///
+/// assert!(selection.selects("panda eats", &[]));
+/// assert!(!selection.selects("panda sleeps", &["slow"]));
+/// assert!(!selection.selects("red panda", &[]));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TapSelection {
+    names: Vec<NamePattern>,
+    tags: Vec<String>,
+    excluded_tags: Vec<String>,
+    shard: Option<(usize, usize)>,
+    skip_deselected: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NamePattern {
+    Substring(String),
+    Glob(Regex),
+}
+
+impl TapSelection {
+    /// Select every test
+    pub fn new() -> TapSelection {
+        TapSelection::default()
+    }
+
+    /// Select tests whose names contain `pattern`. A pattern with `*`, `?` or `[` in it is a glob instead, which must match the whole name.
+    pub fn with_name(mut self, pattern: &str) -> TapSelection {
+        let pattern = glob_regex(pattern)
+            .map(NamePattern::Glob)
+            .unwrap_or_else(|| NamePattern::Substring(pattern.to_string()));
+        self.names.push(pattern);
+        self
+    }
+
+    /// Select tests tagged with `tag`
+    pub fn with_tag(mut self, tag: &str) -> TapSelection {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Leave out tests tagged with `tag`, whatever else selects them
+    pub fn without_tag(mut self, tag: &str) -> TapSelection {
+        self.excluded_tags.push(tag.to_string());
+        self
+    }
+
+    /// Select only the tests in shard `index` of `count`, counting from 1. Every test falls in exactly one shard, decided by a hash of its name, so the shards of a suite can run on separate machines.
+    ///
+    /// # Panics
+    ///
+    /// Unless `index` is between 1 and `count`.
+    pub fn with_shard(mut self, index: usize, count: usize) -> TapSelection {
+        assert!(
+            (1..=count).contains(&index),
+            "Shard {}/{} doesn't exist",
+            index,
+            count
+        );
+        self.shard = Some((index, count));
+        self
+    }
+
+    /// Report tests which aren't selected as `# SKIP filtered`, rather than leaving them out
+    pub fn with_skip_deselected(mut self) -> TapSelection {
+        self.skip_deselected = true;
+        self
+    }
+
+    /// Are tests which aren't selected reported as skipped?
+    pub fn skips_deselected(&self) -> bool {
+        self.skip_deselected
+    }
+
+    /// Is a test called `name`, with these tags, selected?
+    pub fn selects(&self, name: &str, tags: &[&str]) -> bool {
+        let has_tag = |wanted: &String| tags.contains(&wanted.as_str());
+        (self.names.is_empty() || self.names.iter().any(|pattern| pattern.matches(name)))
+            && (self.tags.is_empty() || self.tags.iter().any(has_tag))
+            && !self.excluded_tags.iter().any(has_tag)
+            && self
+                .shard
+                .map_or(true, |(index, count)| shard_of(name, count) == index)
+    }
+
+    /// Is this test selected?
+    pub fn selects_test(&self, test: &TapTest) -> bool {
+        let tags = test.tags.iter().map(String::as_str).collect::<Vec<&str>>();
+        self.selects(&test.name, &tags)
+    }
+
+    /// What's reported in place of a test called `name` which wasn't selected
+    pub fn deselected(&self, name: &str) -> TapTest {
+        TapTest {
+            name: name.to_string(),
+            passed: true,
+            directive: Some(TapDirective::Skip(DESELECTED_REASON.to_string())),
+            ..Default::default()
+        }
+    }
+
+    /// The suite with only the selected tests, or with the rest skipped. A suite which ran as planned is planned for just those; one which didn't keeps its plan, so it still doesn't.
+    pub fn apply(&self, suite: &TapSuite) -> TapSuite {
+        let tests = suite
+            .tests
+            .iter()
+            .filter_map(|test| {
+                if self.selects_test(test) {
+                    Some(test.clone())
+                } else if self.skip_deselected {
+                    Some(self.deselected(&test.name))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<TapTest>>();
+        let plan = match suite.plan {
+            Some(_) if !suite.missed_plan() => Some((1, tests.len() as i64)),
+            plan => plan,
+        };
+        TapSuite {
+            tests,
+            plan,
+            ..suite.clone()
+        }
+    }
+}
+
+impl NamePattern {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Substring(substring) => name.contains(substring.as_str()),
+            NamePattern::Glob(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// Read a shard like "2/4" into its index and count
+pub fn parse_shard(shard: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("A shard looks like 2/4, not {}", shard);
+    let (index, count) = shard.split_once('/').ok_or_else(invalid)?;
+    let index = index.trim().parse::<usize>().map_err(|_| invalid())?;
+    let count = count.trim().parse::<usize>().map_err(|_| invalid())?;
+    if (1..=count).contains(&index) {
+        Ok((index, count))
+    } else {
+        Err(format!("Shard {}/{} doesn't exist", index, count))
+    }
+}
+
+/// Which of `count` shards, counting from 1, the test called `name` falls in. Uses FNV-1a, so it's the same on every machine and every run.
+fn shard_of(name: &str, count: usize) -> usize {
+    let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    (hash % count as u64) as usize + 1
+}
+
+/// A glob as a regex, or nothing if it has no wildcards or won't compile
+fn glob_regex(glob: &str) -> Option<Regex> {
+    if !glob.contains(['*', '?', '[']) {
+        return None;
+    }
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '[' => {
+                pattern.push('[');
+                if chars.next_if_eq(&'!').is_some() {
+                    pattern.push('^');
+                }
+                for c in chars.by_ref() {
+                    pattern.push(c);
+                    if c == ']' {
+                        break;
+                    }
+                }
+            }
+            '.' | '+' | '(' | ')' | '{' | '}' | '|' | '^' | '$' | '\\' => {
+                pattern.push('\\');
+                pattern.push(c);
+            }
+            c => pattern.push(c),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_shard, shard_of, TapSelection};
+    use crate::tap_parser::TapParser;
+
+    #[test]
+    fn test_selects() {
+        let everything = TapSelection::new();
+        assert!(everything.selects("Panda", &[]));
+
+        let by_name = TapSelection::new()
+            .with_name("anda")
+            .with_name("B?m[a-c]oo*");
+        assert!(by_name.selects("Panda", &[]));
+        assert!(by_name.selects("Bamboo shoots", &[]));
+        assert!(!by_name.selects("A bamboo", &[]));
+        assert!(TapSelection::new().with_name("v1.2").selects("v1.2.3", &[]));
+        assert!(!TapSelection::new().with_name("v1.*").selects("v123", &[]));
+
+        let by_tag = TapSelection::new()
+            .with_tag("network")
+            .with_tag("owner:team-x")
+            .without_tag("slow");
+        assert!(by_tag.selects("Panda", &["owner:team-x"]));
+        assert!(!by_tag.selects("Panda", &["network", "slow"]));
+        assert!(!by_tag.selects("Panda", &[]));
+    }
+
+    #[test]
+    fn test_shards() {
+        let names = (0..100).map(|i| format!("Panda {}", i)).collect::<Vec<_>>();
+        let mut sizes = [0; 3];
+        for name in &names {
+            let in_shards = (1..=3)
+                .filter(|&index| TapSelection::new().with_shard(index, 3).selects(name, &[]))
+                .collect::<Vec<usize>>();
+            assert_eq!(in_shards, vec![shard_of(name, 3)]);
+            sizes[in_shards[0] - 1] += 1;
+        }
+        assert!(sizes.iter().all(|&size| size > 20), "{:?}", sizes);
+        assert_eq!(shard_of("Panda", 4), shard_of("Panda", 4));
+
+        assert_eq!(parse_shard("2/4"), Ok((2, 4)));
+        assert_eq!(
+            parse_shard("0/4"),
+            Err("Shard 0/4 doesn't exist".to_string())
+        );
+        assert_eq!(
+            parse_shard("half"),
+            Err("A shard looks like 2/4, not half".to_string())
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let tap_suite = TapParser::new().parse_suite(
+            "Zoo",
+            "1..3
+ok 1 Panda
+not ok 2 Bamboo
+  ---
+  tags:
+    - slow
+  ...
+ok 3 Red panda",
+        );
+
+        let omitted = TapSelection::new().without_tag("slow").apply(&tap_suite);
+        assert_eq!(
+            omitted.lines(),
+            vec!["1..2", "ok 1 Panda", "ok 2 Red panda"]
+        );
+
+        let skipped = TapSelection::new()
+            .with_name("*anda")
+            .with_skip_deselected()
+            .apply(&tap_suite);
+        assert_eq!(
+            skipped.lines(),
+            vec![
+                "1..3",
+                "ok 1 Panda",
+                "ok 2 Bamboo # SKIP filtered",
+                "ok 3 Red panda"
+            ]
+        );
+    }
+}
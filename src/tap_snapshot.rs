@@ -0,0 +1,222 @@
+//! `TapSnapshots` -- Snapshot testing: compare output against a stored copy, and report the comparison as TAP
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::env_flag;
+use crate::tap_comparison::TapComparison;
+use crate::tap_location::TapLocation;
+use crate::tap_test::TapTest;
+use crate::tap_test_builder::TapTestBuilder;
+use crate::tap_writer::TapWriter;
+
+/// Set this environment variable to anything but "" or "0" to record snapshots instead of checking them.
+pub const UPDATE_SNAPSHOTS_VAR: &str = "TAP_UPDATE_SNAPSHOTS";
+
+/// A directory of stored snapshots, one file per snapshot name.
+///
+/// A check passes when the value matches what's stored. When it doesn't, or nothing is stored yet, the check fails with a comparison of the two, which comes out with a line diff when they span several lines. In update mode, the value is stored instead and the check passes.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_snapshot::TapSnapshots;
+/// use testanything::tap_writer::TapWriter;
+///
+/// # let dir = std::env::temp_dir().join(format!("tap-snapshot-doc-{}", std::process::id()));
+/// let snapshots = TapSnapshots::new(&dir).with_update(true);
+/// let writer = TapWriter::new("Snapshots");
+///
+/// writer.plan(1, 1);
+/// let tap_test = snapshots.assert(&writer, 1, "menu", "Panda\nBamboo\n");
+///
+/// assert!(tap_test.passed);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TapSnapshots {
+    dir: PathBuf,
+    update: bool,
+}
+
+impl TapSnapshots {
+    /// Use the snapshots stored under `dir`, in update mode if the `TAP_UPDATE_SNAPSHOTS` environment variable says so.
+    pub fn new<P: AsRef<Path>>(dir: P) -> TapSnapshots {
+        TapSnapshots {
+            dir: dir.as_ref().to_path_buf(),
+            update: env_flag(UPDATE_SNAPSHOTS_VAR),
+        }
+    }
+
+    /// Turn update mode on or off, whatever the environment says
+    pub fn with_update(mut self, update: bool) -> TapSnapshots {
+        self.update = update;
+        self
+    }
+
+    /// Where the snapshot called `name` is stored. Each byte of the name but ASCII letters, digits, "-" and "_" is percent-escaped, like "zoo%2Fmenu" for "zoo/menu", so no two names share a file. Names differing only in case still do on a file system which ignores case.
+    pub fn path(&self, name: &str) -> PathBuf {
+        let mut file_name = String::with_capacity(name.len());
+        for byte in name.bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+                file_name.push(byte as char);
+            } else {
+                file_name.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        self.dir.join(format!("{}.snap", file_name))
+    }
+
+    /// Check `actual` against the snapshot called `name`, producing a test of the same name located where this was called from.
+    #[track_caller]
+    pub fn check(&self, name: &str, actual: &str) -> TapTest {
+        let mut builder = TapTestBuilder::new();
+        builder.name(name).location(TapLocation::caller());
+
+        let path = self.path(name);
+        let stored = match fs::read_to_string(&path) {
+            Ok(stored) => Some(stored),
+            Err(reason) if reason.kind() == io::ErrorKind::NotFound => None,
+            Err(reason) => {
+                let diagnostic = format!("Couldn't read {}: {}", path.display(), reason);
+                return builder.passed(false).diagnostics(&[&diagnostic]).finalize();
+            }
+        };
+
+        if stored.as_deref() == Some(actual) {
+            return builder.passed(true).finalize();
+        }
+
+        if self.update {
+            let diagnostic = match self.store(&path, actual) {
+                Ok(()) if stored.is_some() => format!("Updated {}", path.display()),
+                Ok(()) => format!("Recorded {}", path.display()),
+                Err(reason) => {
+                    let diagnostic = format!("Couldn't write {}: {}", path.display(), reason);
+                    return builder.passed(false).diagnostics(&[&diagnostic]).finalize();
+                }
+            };
+            return builder.passed(true).diagnostics(&[&diagnostic]).finalize();
+        }
+
+        let diagnostic = match &stored {
+            Some(_) => format!(
+                "Doesn't match {}; set {}=1 to update it",
+                path.display(),
+                UPDATE_SNAPSHOTS_VAR
+            ),
+            None => format!(
+                "No snapshot at {}; set {}=1 to record it",
+                path.display(),
+                UPDATE_SNAPSHOTS_VAR
+            ),
+        };
+        builder
+            .passed(false)
+            .diagnostics(&[&diagnostic])
+            .comparison(TapComparison::new(
+                actual,
+                stored.unwrap_or_default(),
+                "snapshot",
+            ))
+            .finalize()
+    }
+
+    /// Like `check`, but also emit the test through `writer` as test number `test_number`.
+    #[track_caller]
+    pub fn assert(
+        &self,
+        writer: &TapWriter,
+        test_number: i32,
+        name: &str,
+        actual: &str,
+    ) -> TapTest {
+        let tap_test = self.check(name, actual);
+        writer.test(test_number, &tap_test);
+        tap_test
+    }
+
+    fn store(&self, path: &Path, actual: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(path, actual)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TapSnapshots;
+    use crate::tap_comparison::TapComparison;
+
+    use crate::temp_path;
+
+    use std::fs;
+
+    #[test]
+    fn test_check_records_and_matches() {
+        let dir = temp_path("snapshot-records");
+        let snapshots = TapSnapshots::new(&dir).with_update(false);
+
+        let missing = snapshots.check("zoo/menu", "Panda\n");
+        assert!(!missing.passed);
+        assert!(missing.diagnostics[0].starts_with("No snapshot at"));
+
+        let recorded = snapshots
+            .clone()
+            .with_update(true)
+            .check("zoo/menu", "Panda\n");
+        assert!(recorded.passed);
+        assert_eq!(
+            fs::read_to_string(dir.join("zoo%2Fmenu.snap")).unwrap(),
+            "Panda\n"
+        );
+
+        let matching = snapshots.check("zoo/menu", "Panda\n");
+        assert!(matching.passed);
+        assert!(matching.diagnostics.is_empty());
+        assert_eq!(matching.location.unwrap().file, file!());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_paths_differ() {
+        let snapshots = TapSnapshots::new("snapshots");
+        let paths = ["zoo/menu", "zoo_menu", "zoo menu", "zoo%2Fmenu", "zöo"]
+            .map(|name| snapshots.path(name));
+
+        assert_eq!(paths[2], snapshots.dir.join("zoo%20menu.snap"));
+        assert_eq!(paths[4], snapshots.dir.join("z%C3%B6o.snap"));
+        for (i, path) in paths.iter().enumerate() {
+            assert!(!paths[i + 1..].contains(path));
+        }
+    }
+
+    #[test]
+    fn test_check_mismatch() {
+        let dir = temp_path("snapshot-mismatch");
+        let snapshots = TapSnapshots::new(&dir).with_update(true);
+        snapshots.check("menu", "Panda\nBamboo\n");
+
+        let mismatch = snapshots
+            .clone()
+            .with_update(false)
+            .check("menu", "Panda\nCurry\n");
+        assert!(!mismatch.passed);
+        assert_eq!(
+            mismatch.comparison,
+            Some(TapComparison::new(
+                "Panda\nCurry\n",
+                "Panda\nBamboo\n",
+                "snapshot"
+            ))
+        );
+        assert!(mismatch.tap(1).contains(&"#     - Bamboo".to_string()));
+
+        let updated = snapshots.check("menu", "Panda\nCurry\n");
+        assert!(updated.passed);
+        assert!(updated.diagnostics[0].starts_with("Updated"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,773 @@
+//! `SubunitPacket` -- For bridging TAP results to and from the SubUnit v2 binary protocol
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::collections::BTreeMap;
+
+use crate::tap_directive::TapDirective;
+use crate::tap_suite::TapSuite;
+use crate::tap_test::TapTest;
+use crate::tap_version::TapVersion;
+use crate::tap_yaml::{self, YamlValue};
+
+const SIGNATURE: u8 = 0xB3;
+const VERSION: u16 = 0x2000;
+const VERSION_MASK: u16 = 0xF000;
+const TEST_ID: u16 = 0x0800;
+const ROUTE_CODE: u16 = 0x0400;
+const TIMESTAMP: u16 = 0x0200;
+const RUNNABLE: u16 = 0x0100;
+const TAGS: u16 = 0x0080;
+const MIME_TYPE: u16 = 0x0040;
+const EOF: u16 = 0x0020;
+const FILE_CONTENT: u16 = 0x0010;
+const STATUS_MASK: u16 = 0x0007;
+
+/// The largest number a SubUnit variable-length integer can hold
+const MAX_NUMBER: u32 = 0x3FFF_FFFF;
+/// The largest packet the protocol allows
+const MAX_PACKET_LENGTH: usize = 4 * 1024 * 1024;
+/// File content is sent in chunks of at most this many bytes, keeping packets well under the limit
+const CHUNK_LENGTH: usize = 64 * 1024;
+/// The latest time a packet can be stamped with, since the seconds go in 32 bits
+const LATEST_TIMESTAMP: Duration = Duration::new(u32::MAX as u64, 999_999_999);
+
+/// Attachment names used when bridging from TAP, following the conventions of Python's subunit
+const TRACEBACK: &str = "traceback";
+const REASON: &str = "reason";
+const YAML: &str = "tap-yaml";
+const BAIL_OUT: &str = "bail-out";
+const TEXT: &str = "text/plain;charset=utf8";
+
+/// What a packet says about its test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SubunitStatus {
+    /// Nothing; the packet is carrying an attachment or tags
+    #[default]
+    Undefined,
+    /// The test exists, but hasn't been run
+    Exists,
+    /// The test has started
+    InProgress,
+    /// The test passed
+    Success,
+    /// The test was expected to fail, but passed
+    UnexpectedSuccess,
+    /// The test was skipped
+    Skip,
+    /// The test failed
+    Fail,
+    /// The test failed, as expected
+    ExpectedFailure,
+}
+
+impl SubunitStatus {
+    /// The status as it's packed into the low bits of a packet's flags
+    pub fn code(self) -> u16 {
+        match self {
+            SubunitStatus::Undefined => 0,
+            SubunitStatus::Exists => 1,
+            SubunitStatus::InProgress => 2,
+            SubunitStatus::Success => 3,
+            SubunitStatus::UnexpectedSuccess => 4,
+            SubunitStatus::Skip => 5,
+            SubunitStatus::Fail => 6,
+            SubunitStatus::ExpectedFailure => 7,
+        }
+    }
+
+    /// Read a status back out of a packet's flags, ignoring the other bits.
+    pub fn from_code(code: u16) -> SubunitStatus {
+        match code & STATUS_MASK {
+            1 => SubunitStatus::Exists,
+            2 => SubunitStatus::InProgress,
+            3 => SubunitStatus::Success,
+            4 => SubunitStatus::UnexpectedSuccess,
+            5 => SubunitStatus::Skip,
+            6 => SubunitStatus::Fail,
+            7 => SubunitStatus::ExpectedFailure,
+            _ => SubunitStatus::Undefined,
+        }
+    }
+
+    /// Is this a test's final word on how it went?
+    pub fn is_final(self) -> bool {
+        matches!(
+            self,
+            SubunitStatus::Success
+                | SubunitStatus::UnexpectedSuccess
+                | SubunitStatus::Skip
+                | SubunitStatus::Fail
+                | SubunitStatus::ExpectedFailure
+        )
+    }
+}
+
+/// A single SubUnit v2 packet. Every field but the status and flags is optional on the wire.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_subunit::{SubunitPacket, SubunitStatus};
+///
+/// let packet = SubunitPacket {
+///     test_id: Some("zoo.panda".to_string()),
+///     status: SubunitStatus::Success,
+///     ..Default::default()
+/// };
+///
+/// let bytes = packet.encode().unwrap();
+///
+/// assert_eq!(SubunitPacket::decode(&bytes), Ok((packet, bytes.len())));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SubunitPacket {
+    /// What the packet says about its test
+    pub status: SubunitStatus,
+    /// The test the packet is about, if any
+    pub test_id: Option<String>,
+    /// When the packet was sent, as time since the Unix epoch
+    pub timestamp: Option<Duration>,
+    /// Tags applied to the test
+    pub tags: Vec<String>,
+    /// The MIME type of the attached file
+    pub mime_type: Option<String>,
+    /// A chunk of an attached file: its name and these bytes of its content
+    pub file: Option<(String, Vec<u8>)>,
+    /// Which of several multiplexed streams the packet came from
+    pub route_code: Option<String>,
+    /// Whether the test is one that can be run, rather than a grouping of tests
+    pub runnable: bool,
+    /// Whether this is the last chunk of the attached file
+    pub eof: bool,
+}
+
+impl SubunitPacket {
+    /// Encode the packet, signature to CRC.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut flags = VERSION | self.status.code();
+        let mut body = Vec::new();
+
+        if let Some(timestamp) = self.timestamp {
+            flags |= TIMESTAMP;
+            let seconds = u32::try_from(timestamp.as_secs())
+                .map_err(|_| "Timestamp out of range".to_string())?;
+            body.extend_from_slice(&seconds.to_be_bytes());
+            write_number(&mut body, timestamp.subsec_nanos())?;
+        }
+        if let Some(test_id) = &self.test_id {
+            flags |= TEST_ID;
+            write_string(&mut body, test_id)?;
+        }
+        if !self.tags.is_empty() {
+            flags |= TAGS;
+            write_number(&mut body, checked_number(self.tags.len())?)?;
+            for tag in &self.tags {
+                write_string(&mut body, tag)?;
+            }
+        }
+        if let Some(mime_type) = &self.mime_type {
+            flags |= MIME_TYPE;
+            write_string(&mut body, mime_type)?;
+        }
+        if let Some((name, content)) = &self.file {
+            flags |= FILE_CONTENT;
+            write_string(&mut body, name)?;
+            write_number(&mut body, checked_number(content.len())?)?;
+            body.extend_from_slice(content);
+        }
+        if let Some(route_code) = &self.route_code {
+            flags |= ROUTE_CODE;
+            write_string(&mut body, route_code)?;
+        }
+        if self.runnable {
+            flags |= RUNNABLE;
+        }
+        if self.eof {
+            flags |= EOF;
+        }
+
+        // The length counts the whole packet, including the bytes it takes itself
+        let length = (1..=4)
+            .map(|size| 1 + 2 + size + body.len() + 4)
+            .find(|length| number_size(*length as u32) == Some(length - body.len() - 7))
+            .filter(|length| *length <= MAX_PACKET_LENGTH)
+            .ok_or_else(|| format!("Packet too long: {} bytes of fields", body.len()))?;
+
+        let mut packet = Vec::with_capacity(length);
+        packet.push(SIGNATURE);
+        packet.extend_from_slice(&flags.to_be_bytes());
+        write_number(&mut packet, length as u32)?;
+        packet.extend_from_slice(&body);
+        let crc = crc32(&packet);
+        packet.extend_from_slice(&crc.to_be_bytes());
+        Ok(packet)
+    }
+
+    /// Decode the packet at the start of `bytes`, yielding it and how many bytes it took up.
+    pub fn decode(bytes: &[u8]) -> Result<(SubunitPacket, usize), String> {
+        let mut header = Reader::new(bytes);
+        if header.byte()? != SIGNATURE {
+            return Err("Not a SubUnit v2 packet: bad signature".to_string());
+        }
+        let flags = u16::from_be_bytes([header.byte()?, header.byte()?]);
+        if flags & VERSION_MASK != VERSION {
+            return Err(format!(
+                "Unsupported SubUnit version {}",
+                (flags & VERSION_MASK) >> 12
+            ));
+        }
+        let length = header.number()? as usize;
+        if length < header.position + 4 || length > MAX_PACKET_LENGTH {
+            return Err(format!("Bad packet length {}", length));
+        }
+        if bytes.len() < length {
+            return Err(format!(
+                "Truncated packet: expected {} bytes, found {}",
+                length,
+                bytes.len()
+            ));
+        }
+
+        let expected_crc = u32::from_be_bytes([
+            bytes[length - 4],
+            bytes[length - 3],
+            bytes[length - 2],
+            bytes[length - 1],
+        ]);
+        if crc32(&bytes[..length - 4]) != expected_crc {
+            return Err("Packet failed its CRC check".to_string());
+        }
+
+        let mut fields = Reader::new(&bytes[..length - 4]);
+        fields.position = header.position;
+        let mut packet = SubunitPacket {
+            status: SubunitStatus::from_code(flags),
+            runnable: flags & RUNNABLE != 0,
+            eof: flags & EOF != 0,
+            ..Default::default()
+        };
+        if flags & TIMESTAMP != 0 {
+            let seconds = u32::from_be_bytes([
+                fields.byte()?,
+                fields.byte()?,
+                fields.byte()?,
+                fields.byte()?,
+            ]);
+            let nanos = fields.number()?;
+            packet.timestamp =
+                Some(Duration::new(seconds as u64, 0) + Duration::from_nanos(nanos as u64));
+        }
+        if flags & TEST_ID != 0 {
+            packet.test_id = Some(fields.string()?);
+        }
+        if flags & TAGS != 0 {
+            let count = fields.number()?;
+            for _ in 0..count {
+                packet.tags.push(fields.string()?);
+            }
+        }
+        if flags & MIME_TYPE != 0 {
+            packet.mime_type = Some(fields.string()?);
+        }
+        if flags & FILE_CONTENT != 0 {
+            let name = fields.string()?;
+            let size = fields.number()? as usize;
+            packet.file = Some((name, fields.bytes(size)?.to_vec()));
+        }
+        if flags & ROUTE_CODE != 0 {
+            packet.route_code = Some(fields.string()?);
+        }
+        if fields.position != length - 4 {
+            return Err("Packet has bytes left over after its fields".to_string());
+        }
+
+        Ok((packet, length))
+    }
+}
+
+/// Encode a suite as a SubUnit v2 stream. `started` is when the suite began, as time since the Unix epoch; each test gets an in-progress packet and a final one, and those of a timed test are stamped so that their difference is its duration. Stamps which would fall after early 2106, the latest SubUnit can send, are sent as that instead.
+///
+/// Subtests are sent as tests of their own, before their parent, with the parent's name and " / " in front of theirs. Diagnostics are attached as a "traceback" file, the reason for a SKIP or TODO as a "reason" file, and where the test lives, what it compared, its metadata and any other YAML as a "tap-yaml" file. Tags are sent as the tags of its final packet. A bail out is sent as a "bail-out" file belonging to no test.
+///
+/// # Errors
+///
+/// If a test's name or tags are too long to fit in a packet.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use testanything::tap_parser::TapParser;
+/// use testanything::tap_subunit::{from_subunit, to_subunit};
+///
+/// let tap_suite = TapParser::new().parse_suite("Zoo", "1..2\nok 1 Panda\nnot ok 2 Tiger\n# Escaped");
+///
+/// let bytes = to_subunit(&tap_suite, Duration::from_secs(1_700_000_000)).unwrap();
+/// let decoded = from_subunit("Zoo", &bytes).unwrap();
+///
+/// assert_eq!(decoded.tests[1].name, "Tiger");
+/// assert_eq!(decoded.tests[1].diagnostics, vec!["Escaped"]);
+/// ```
+pub fn to_subunit(suite: &TapSuite, started: Duration) -> Result<Vec<u8>, String> {
+    let mut packets = Vec::new();
+    let mut clock = started.min(LATEST_TIMESTAMP);
+    for test in &suite.tests {
+        test_packets(test, "", &mut clock, &mut packets);
+    }
+    if let Some(reason) = &suite.bail_out {
+        file_packets(None, BAIL_OUT, reason, None, &mut packets);
+    }
+
+    let mut bytes = Vec::new();
+    for packet in &packets {
+        let encoded = packet.encode().map_err(|reason| match &packet.test_id {
+            Some(test_id) => format!("Couldn't send {}: {}", abbreviated(test_id), reason),
+            None => reason,
+        })?;
+        bytes.extend(encoded);
+    }
+    Ok(bytes)
+}
+
+/// The start of a test id that may be too long to show
+fn abbreviated(test_id: &str) -> String {
+    match test_id.char_indices().nth(60) {
+        Some((end, _)) => format!("{}...", &test_id[..end]),
+        None => test_id.to_string(),
+    }
+}
+
+fn test_packets(
+    test: &TapTest,
+    path: &str,
+    clock: &mut Duration,
+    packets: &mut Vec<SubunitPacket>,
+) {
+    let id = if path.is_empty() {
+        test.name.clone()
+    } else {
+        format!("{} / {}", path, test.name)
+    };
+    for subtest in &test.subtests {
+        test_packets(subtest, &id, clock, packets);
+    }
+
+    packets.push(SubunitPacket {
+        status: SubunitStatus::InProgress,
+        test_id: Some(id.clone()),
+        timestamp: test.duration.map(|_| *clock),
+        runnable: true,
+        ..Default::default()
+    });
+    *clock = clock
+        .saturating_add(test.duration.unwrap_or_default())
+        .min(LATEST_TIMESTAMP);
+
+    let test_id = Some(id.as_str());
+    if !test.diagnostics.is_empty() {
+        file_packets(
+            test_id,
+            TRACEBACK,
+            &test.diagnostics.join("\n"),
+            Some(TEXT),
+            packets,
+        );
+    }
+    if let Some(directive) = &test.directive {
+        file_packets(test_id, REASON, directive.reason(), Some(TEXT), packets);
+    }
+    let mut fields = Vec::new();
+    if let Some(location) = &test.location {
+        fields.push(("at".to_string(), location.to_yaml()));
+    }
+    if let Some(comparison) = &test.comparison {
+        fields.extend(comparison.yaml_fields());
+    }
+    if !test.metadata.is_empty() {
+        let metadata = test
+            .metadata
+            .iter()
+            .map(|(key, value)| (key.clone(), YamlValue::scalar(value.as_str())));
+        fields.push(("metadata".to_string(), YamlValue::Map(metadata.collect())));
+    }
+    fields.extend(test.yaml.iter().cloned());
+    if !fields.is_empty() {
+        let yaml = tap_yaml::render_entries(&fields, 0).join("\n");
+        file_packets(test_id, YAML, &yaml, Some("application/x-yaml"), packets);
+    }
+
+    let status = match (&test.directive, test.passed) {
+        (Some(TapDirective::Skip(_)), _) => SubunitStatus::Skip,
+        (Some(TapDirective::Todo(_)), true) => SubunitStatus::UnexpectedSuccess,
+        (Some(TapDirective::Todo(_)), false) => SubunitStatus::ExpectedFailure,
+        (None, true) => SubunitStatus::Success,
+        (None, false) => SubunitStatus::Fail,
+    };
+    packets.push(SubunitPacket {
+        status,
+        test_id: Some(id),
+        timestamp: test.duration.map(|_| *clock),
+        tags: test.tags.iter().cloned().collect(),
+        runnable: true,
+        ..Default::default()
+    });
+}
+
+fn file_packets(
+    test_id: Option<&str>,
+    name: &str,
+    content: &str,
+    mime_type: Option<&str>,
+    packets: &mut Vec<SubunitPacket>,
+) {
+    let chunks = content
+        .as_bytes()
+        .chunks(CHUNK_LENGTH)
+        .collect::<Vec<&[u8]>>();
+    let count = chunks.len().max(1);
+    for i in 0..count {
+        packets.push(SubunitPacket {
+            test_id: test_id.map(|id| id.to_string()),
+            mime_type: mime_type.filter(|_| i == 0).map(|mime| mime.to_string()),
+            file: Some((
+                name.to_string(),
+                chunks.get(i).copied().unwrap_or_default().to_vec(),
+            )),
+            eof: i + 1 == count,
+            ..Default::default()
+        });
+    }
+}
+
+/// A test whose final status hasn't arrived yet
+#[derive(Default)]
+struct PendingTest {
+    started: Option<Duration>,
+    tags: Vec<String>,
+    files: Vec<(String, Vec<u8>)>,
+}
+
+/// Decode a SubUnit v2 stream into a suite called `name`, written as TAP 14. Tests appear in the order they finished, named by their test ids.
+///
+/// Attachments are read as `to_subunit` writes them; any other text attachment of a test, such as the "stdout" or "stderr" some runners send, joins its diagnostics. Packets which only record that a test exists are ignored.
+pub fn from_subunit(name: &str, bytes: &[u8]) -> Result<TapSuite, String> {
+    let mut suite = TapSuite {
+        name: name.to_string(),
+        version: TapVersion::Tap14,
+        ..Default::default()
+    };
+    let mut pending: BTreeMap<String, PendingTest> = BTreeMap::new();
+    let mut global_files: Vec<(String, Vec<u8>)> = Vec::new();
+
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let (packet, length) = SubunitPacket::decode(&bytes[offset..])
+            .map_err(|reason| format!("At byte {}: {}", offset, reason))?;
+        offset += length;
+
+        let Some(test_id) = packet.test_id else {
+            if let Some((file_name, content)) = packet.file {
+                append_file(&mut global_files, file_name, content);
+            }
+            continue;
+        };
+        let test = pending.entry(test_id.clone()).or_default();
+        if packet.status == SubunitStatus::InProgress {
+            test.started = test.started.or(packet.timestamp);
+        }
+        test.tags.extend(packet.tags);
+        if let Some((file_name, content)) = packet.file {
+            append_file(&mut test.files, file_name, content);
+        }
+        if packet.status.is_final() {
+            let test = pending.remove(&test_id).unwrap_or_default();
+            suite.tests.push(finished_test(
+                test_id,
+                packet.status,
+                packet.timestamp,
+                test,
+            ));
+        }
+    }
+
+    suite.bail_out = global_files
+        .into_iter()
+        .find(|(file_name, _)| file_name == BAIL_OUT)
+        .map(|(_, content)| String::from_utf8_lossy(&content).into_owned());
+
+    Ok(suite)
+}
+
+fn append_file(files: &mut Vec<(String, Vec<u8>)>, name: String, content: Vec<u8>) {
+    match files.iter_mut().find(|(file_name, _)| *file_name == name) {
+        Some((_, existing)) => existing.extend(content),
+        None => files.push((name, content)),
+    }
+}
+
+fn finished_test(
+    name: String,
+    status: SubunitStatus,
+    finished: Option<Duration>,
+    pending: PendingTest,
+) -> TapTest {
+    let mut test = TapTest {
+        name,
+        passed: matches!(
+            status,
+            SubunitStatus::Success | SubunitStatus::UnexpectedSuccess | SubunitStatus::Skip
+        ),
+        duration: pending
+            .started
+            .zip(finished)
+            .and_then(|(started, finished)| finished.checked_sub(started)),
+        tags: pending.tags.into_iter().collect(),
+        ..Default::default()
+    };
+
+    let mut reason = String::new();
+    for (file_name, content) in pending.files {
+        let text = String::from_utf8_lossy(&content);
+        match file_name.as_str() {
+            REASON => reason = text.into_owned(),
+            YAML => test.apply_yaml(&tap_yaml::parse_block(&text.lines().collect::<Vec<&str>>())),
+            _ => test
+                .diagnostics
+                .extend(text.lines().map(|line| line.to_string())),
+        }
+    }
+
+    test.directive = match status {
+        SubunitStatus::Skip => Some(TapDirective::Skip(reason)),
+        SubunitStatus::UnexpectedSuccess | SubunitStatus::ExpectedFailure => {
+            Some(TapDirective::Todo(reason))
+        }
+        _ => None,
+    };
+
+    test
+}
+
+/// The CRC-32 used by zlib, which is the one SubUnit uses.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn checked_number(n: usize) -> Result<u32, String> {
+    u32::try_from(n)
+        .ok()
+        .filter(|n| *n <= MAX_NUMBER)
+        .ok_or_else(|| format!("{} is too large for SubUnit", n))
+}
+
+/// How many bytes it takes to encode `n`, if it can be encoded at all
+fn number_size(n: u32) -> Option<usize> {
+    match n {
+        0..=0x3F => Some(1),
+        0x40..=0x3FFF => Some(2),
+        0x4000..=0x3F_FFFF => Some(3),
+        0x40_0000..=MAX_NUMBER => Some(4),
+        _ => None,
+    }
+}
+
+/// Write a variable-length integer: the top two bits of the first byte say how many more bytes follow, and the rest is the number, big-endian.
+fn write_number(buf: &mut Vec<u8>, n: u32) -> Result<(), String> {
+    let size = number_size(n).ok_or_else(|| format!("{} is too large for SubUnit", n))?;
+    let prefixed = n | ((size as u32 - 1) << (size * 8 - 2));
+    buf.extend_from_slice(&prefixed.to_be_bytes()[4 - size..]);
+    Ok(())
+}
+
+fn write_string(buf: &mut Vec<u8>, s: &str) -> Result<(), String> {
+    write_number(buf, checked_number(s.len())?)?;
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, position: 0 }
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.position + count;
+        let bytes = self
+            .bytes
+            .get(self.position..end)
+            .ok_or_else(|| "Packet ends in the middle of a field".to_string())?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn number(&mut self) -> Result<u32, String> {
+        let first = self.byte()?;
+        let mut n = (first & 0x3F) as u32;
+        for _ in 0..(first >> 6) {
+            n = (n << 8) | self.byte()? as u32;
+        }
+        Ok(n)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.number()? as usize;
+        String::from_utf8(self.bytes(length)?.to_vec())
+            .map_err(|_| "String field isn't valid UTF-8".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        crc32, from_subunit, to_subunit, write_number, Reader, SubunitPacket, SubunitStatus,
+    };
+    use crate::tap_comparison::TapComparison;
+    use crate::tap_location::TapLocation;
+    use crate::tap_parser::TapParser;
+
+    use std::time::Duration;
+
+    #[test]
+    fn test_numbers_and_crc() {
+        for (n, expected) in [
+            (0x3F, vec![0x3F]),
+            (0x40, vec![0x40, 0x40]),
+            (0x3FFF, vec![0x7F, 0xFF]),
+            (0x4000, vec![0x80, 0x40, 0x00]),
+            (0x3FFF_FFFF, vec![0xFF, 0xFF, 0xFF, 0xFF]),
+        ] {
+            let mut buf = Vec::new();
+            write_number(&mut buf, n).unwrap();
+            assert_eq!(buf, expected);
+            assert_eq!(Reader::new(&buf).number(), Ok(n));
+        }
+        assert!(write_number(&mut Vec::new(), 0x4000_0000).is_err());
+
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_packet_roundtrip() {
+        let packet = SubunitPacket {
+            status: SubunitStatus::Fail,
+            test_id: Some("zoo.tiger".to_string()),
+            timestamp: Some(Duration::new(1_700_000_000, 250)),
+            tags: vec!["slow".to_string(), "cats".to_string()],
+            mime_type: Some("text/plain".to_string()),
+            file: Some(("traceback".to_string(), b"Escaped".to_vec())),
+            route_code: Some("0".to_string()),
+            runnable: true,
+            eof: true,
+        };
+
+        let mut bytes = packet.encode().unwrap();
+        assert_eq!(bytes[0], 0xB3);
+        assert_eq!(SubunitPacket::decode(&bytes), Ok((packet, bytes.len())));
+
+        bytes[10] ^= 0xFF;
+        assert!(SubunitPacket::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_suite_roundtrip() {
+        let input = "TAP version 14
+1..4
+ok 1 Panda
+  ---
+  duration_ms: 1500.000
+  keeper: Sam
+  ...
+not ok 2 Tiger
+# Escaped
+# Twice
+    # Subtest: Aquarium
+    1..1
+    ok 1 Shark # SKIP drained
+ok 3 Aquarium
+not ok 4 Curry # TODO too spicy
+Bail out! Flooded";
+        let mut tap_suite = TapParser::new().parse_suite("Zoo", input);
+
+        let bytes = to_subunit(&tap_suite, Duration::from_secs(1_700_000_000)).unwrap();
+        let decoded = from_subunit("Zoo", &bytes).unwrap();
+
+        let expected = vec![
+            "TAP version 14",
+            "1..5",
+            "ok 1 Panda",
+            "  ---",
+            "  duration_ms: 1500.000",
+            "  keeper: Sam",
+            "  ...",
+            "not ok 2 Tiger",
+            "# Escaped",
+            "# Twice",
+            "ok 3 Aquarium / Shark # SKIP drained",
+            "ok 4 Aquarium",
+            "not ok 5 Curry # TODO too spicy",
+            "Bail out! Flooded",
+        ];
+
+        assert_eq!(expected, decoded.lines());
+        assert!(from_subunit("Zoo", &bytes[..bytes.len() - 1]).is_err());
+
+        let tiger = &mut tap_suite.tests[1];
+        tiger.comparison = Some(TapComparison::equal("1 tiger", "2 tigers"));
+        tiger.location = Some(TapLocation::new("tests/zoo.rs", 12, 5));
+        tiger.tags.extend(["big".to_string(), "cats".to_string()]);
+        tiger
+            .metadata
+            .insert("keeper".to_string(), "Sam".to_string());
+        let bytes = to_subunit(&tap_suite, Duration::from_secs(1_700_000_000)).unwrap();
+        let decoded = from_subunit("Zoo", &bytes).unwrap();
+        assert_eq!(decoded.tests[1], tap_suite.tests[1]);
+    }
+
+    #[test]
+    fn test_suite_with_huge_durations() {
+        let input = "1..2\nok 1 Panda\n  ---\n  duration_ms: 1e20\n  ...\nok 2 Tiger";
+        let mut tap_suite = TapParser::new().parse_suite("Zoo", input);
+        tap_suite.tests[1].duration = Some(Duration::MAX);
+
+        let bytes = to_subunit(&tap_suite, Duration::from_secs(4_000_000_000)).unwrap();
+        let decoded = from_subunit("Zoo", &bytes).unwrap();
+
+        assert_eq!(decoded.tests.len(), 2);
+        assert!(decoded.tests.iter().all(|test| test.passed));
+    }
+
+    #[test]
+    fn test_suite_with_huge_names() {
+        let mut tap_suite = TapParser::new().parse_suite("Zoo", "1..1\nok 1 Panda");
+        tap_suite.tests[0].name = "Panda".repeat(1_000_000);
+
+        let error = to_subunit(&tap_suite, Duration::from_secs(1_700_000_000)).unwrap_err();
+        assert!(error.starts_with("Couldn't send PandaPanda"), "{}", error);
+        assert!(error.contains("Packet too long"), "{}", error);
+    }
+}
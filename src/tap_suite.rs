@@ -1,34 +1,63 @@
 //! `TapSuite` -- A collection of `TapTest` objects renderable into a TAP text stream
 
 #[cfg(feature = "alloc")]
-use alloc::{format, string::String, vec, vec::Vec};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 #[cfg(feature = "std")]
 use std::io::Write;
 
 use crate::tap_test::TapTest;
+use crate::tap_version::TapVersion;
 
 /// Represents a collection of TAP tests (`TapTest`) which can be rendered into a (text) TAP stream. This orchestrates that rendering.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct TapSuite {
     /// The name of the suite. If this is a blank string, that's fine but it's considered a party foul.
     pub name: String,
     /// The collection of `TapTest` objects included in this test group, to be rendered into a TAP stream.
     pub tests: Vec<TapTest>,
+    /// The version of TAP to render. Defaults to classic, versionless TAP.
+    pub version: TapVersion,
+    /// If the run was cut short, why. Rendered as a "Bail out!" line after the tests.
+    pub bail_out: Option<String>,
+    /// The first and last test numbers of the plan of the stream the suite was read from, if it had one. Rendering always plans for the tests in the suite.
+    pub plan: Option<(i64, i64)>,
 }
 
 impl TapSuite {
-    /// Produce and arrange all text lines, in order, included in this TAP stream. This includes the leading plan line which is calculated based on the number of tests.
+    /// How many tests the suite's plan promised, if it has one
+    pub fn planned(&self) -> Option<usize> {
+        self.plan
+            .map(|(start, end)| (end - start + 1).max(0) as usize)
+    }
+
+    /// Were there fewer or more tests than the plan promised? A suite without a plan always ran as planned.
+    pub fn missed_plan(&self) -> bool {
+        self.planned()
+            .is_some_and(|planned| planned != self.tests.len())
+    }
+
+    /// Produce and arrange all text lines, in order, included in this TAP stream. This includes the leading plan line which is calculated based on the number of tests, preceded by the version line if the version calls for one.
     pub fn lines(&self) -> Vec<String> {
+        let mut all_lines = vec![];
+        all_lines.extend(self.version.version_line());
         // Make plan line
-        let first_line = format!("1..{}", self.tests.len());
-        let mut all_lines = vec![first_line];
+        all_lines.push(format!("1..{}", self.tests.len()));
 
         for (i, test) in self.tests.iter().enumerate() {
             let index = i as i64; // by default i is a usize.
-            let tap = test.tap(index + 1); // TAP tests can't start with zero
+            let tap = test.tap_with_version(index + 1, self.version); // TAP tests can't start with zero
             all_lines.extend(tap.iter().cloned());
         }
 
+        if let Some(reason) = &self.bail_out {
+            all_lines.push(format!("Bail out! {}", reason).trim_end().to_string());
+        }
+
         all_lines
     }
 }
@@ -49,6 +78,9 @@ impl TapSuite {
 mod tests {
     use super::TapSuite;
     use crate::tap_test_builder::TapTestBuilder;
+    use crate::tap_version::TapVersion;
+
+    use std::time::Duration;
 
     #[test]
     fn test_lines() {
@@ -60,12 +92,13 @@ mod tests {
         let failing_test = TapTestBuilder::new()
             .name("Curry Noodle")
             .passed(false)
-            .diagnostics(&vec!["Tree", "Flower"])
+            .diagnostics(&["Tree", "Flower"])
             .finalize();
 
         let tap_suite = TapSuite {
             name: "Example TAP Suite".to_string(),
             tests: vec![passing_test, failing_test],
+            ..Default::default()
         };
 
         let expected = vec![
@@ -79,4 +112,34 @@ mod tests {
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn test_lines_with_version_and_bail_out() {
+        let timed_test = TapTestBuilder::new()
+            .name("Panda Bamboo")
+            .passed(true)
+            .duration(Duration::from_millis(5))
+            .finalize();
+
+        let tap_suite = TapSuite {
+            name: "Example TAP Suite".to_string(),
+            tests: vec![timed_test],
+            version: TapVersion::Tap13,
+            bail_out: Some("Out of bamboo".to_string()),
+            plan: None,
+        };
+
+        let expected = vec![
+            "TAP version 13",
+            "1..1",
+            "ok 1 Panda Bamboo",
+            "  ---",
+            "  duration_ms: 5.000",
+            "  ...",
+            "Bail out! Out of bamboo",
+        ];
+        let actual = tap_suite.lines();
+
+        assert_eq!(expected, actual);
+    }
 }
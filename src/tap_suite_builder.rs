@@ -8,6 +8,7 @@ use core::option::Option;
 
 use crate::tap_suite::TapSuite;
 use crate::tap_test::TapTest;
+use crate::tap_version::TapVersion;
 
 /// Coordinator for constructing `TapSuite` objects using the builder pattern.
 ///
@@ -35,6 +36,10 @@ pub struct TapSuiteBuilder {
     pub name: Option<String>,
     /// Vector of type `Vec<TapTest>` which holds the actual tests
     pub tests: Option<Vec<TapTest>>,
+    /// Version of TAP to render
+    pub version: Option<TapVersion>,
+    /// Reason for bailing out, if the run was cut short
+    pub bail_out: Option<String>,
 }
 
 impl TapSuiteBuilder {
@@ -43,6 +48,8 @@ impl TapSuiteBuilder {
         TapSuiteBuilder {
             name: None,
             tests: None,
+            version: None,
+            bail_out: None,
         }
     }
     /// Set the name
@@ -55,11 +62,24 @@ impl TapSuiteBuilder {
         self.tests = Some(test_vec);
         self
     }
-    /// Produce the configured `TapSuite` object. Name defaults to a blank `String` and the tests default to an empty `Vec`.
+    /// Set the version of TAP to render
+    pub fn version(&mut self, version: TapVersion) -> &mut TapSuiteBuilder {
+        self.version = Some(version);
+        self
+    }
+    /// Mark the run as cut short, for the given reason
+    pub fn bail_out<S: Into<String>>(&mut self, reason: S) -> &mut TapSuiteBuilder {
+        self.bail_out = Some(reason.into());
+        self
+    }
+    /// Produce the configured `TapSuite` object. Name defaults to a blank `String`, the tests default to an empty `Vec`, the version defaults to classic TAP and nobody bails out.
     pub fn finalize(&mut self) -> TapSuite {
         TapSuite {
             name: self.name.take().unwrap_or_default(),
             tests: self.tests.take().unwrap_or_default(),
+            version: self.version.take().unwrap_or_default(),
+            bail_out: self.bail_out.take(),
+            plan: None,
         }
     }
 }
@@ -88,6 +108,7 @@ mod test {
                 .name("Example TAP test")
                 .passed(true)
                 .finalize()],
+            ..Default::default()
         };
 
         assert_eq!(tap_suite_from_builder, tap_suite_from_scratch);
@@ -0,0 +1,686 @@
+//! `TapSummary` -- A human-readable report of a `TapSuite`, in the spirit of the footer printed by `prove`
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+use crate::tap_directive::TapDirective;
+use crate::tap_suite::TapSuite;
+use crate::tap_test::TapTest;
+
+/// A failing test as it appears in a `TapSummary`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedTest {
+    /// The number the test was given in the TAP stream
+    pub number: i64,
+    /// The name of the test
+    pub name: String,
+}
+
+/// A test which took a measurable amount of time, as it appears in a `TapSummary`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedTest {
+    /// The number the test was given in the TAP stream
+    pub number: i64,
+    /// The name of the test
+    pub name: String,
+    /// How long it took
+    pub duration: Duration,
+}
+
+/// A test which passed only after being retried, as it appears in a `TapSummary`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlakyTest {
+    /// The number the test was given in the TAP stream
+    pub number: i64,
+    /// The name of the test
+    pub name: String,
+    /// How many attempts it took to pass
+    pub attempts: usize,
+}
+
+/// How the tests with one tag turned out, as it appears in a `TapSummary`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagTally {
+    /// The tag
+    pub tag: String,
+    /// How many tests have the tag
+    pub total: usize,
+    /// How many of those failed
+    pub failed: usize,
+    /// How many of those were skipped
+    pub skipped: usize,
+}
+
+/// How a test turned out, as far as a report is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestStatus {
+    /// The test passed
+    Pass,
+    /// The test failed, and counts against the suite
+    Fail,
+    /// The test was skipped
+    Skip,
+    /// The test is marked TODO, whether or not it passed
+    Todo,
+}
+
+impl TestStatus {
+    /// Work out the status of a test.
+    pub fn of(test: &TapTest) -> TestStatus {
+        match &test.directive {
+            Some(TapDirective::Skip(_)) => TestStatus::Skip,
+            Some(TapDirective::Todo(_)) => TestStatus::Todo,
+            None if test.passed => TestStatus::Pass,
+            None => TestStatus::Fail,
+        }
+    }
+
+    /// Yield "pass", "fail", "skip" or "todo"
+    pub fn label(self) -> &'static str {
+        match self {
+            TestStatus::Pass => "pass",
+            TestStatus::Fail => "fail",
+            TestStatus::Skip => "skip",
+            TestStatus::Todo => "todo",
+        }
+    }
+
+    /// An emoji standing for the status, for reports shown on the web
+    pub fn emoji(self) -> &'static str {
+        match self {
+            TestStatus::Pass => "\u{2705}",
+            TestStatus::Fail => "\u{274C}",
+            TestStatus::Skip => "\u{23ED}\u{FE0F}",
+            TestStatus::Todo => "\u{1F6A7}",
+        }
+    }
+}
+
+/// A test as it appears in the full listing of a `TapSummary`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SummarizedTest {
+    /// The number the test was given in the TAP stream
+    pub number: i64,
+    /// The name of the test
+    pub name: String,
+    /// How it turned out
+    pub status: TestStatus,
+    /// The reason given for a SKIP or TODO, which may be blank
+    pub reason: String,
+    /// Its diagnostics and what it compared, followed by the status lines, diagnostics and comparisons of any failing subtests
+    pub diagnostics: Vec<String>,
+    /// How long it took, if that was measured
+    pub duration: Option<Duration>,
+    /// Its tags, in order
+    pub tags: Vec<String>,
+}
+
+impl SummarizedTest {
+    fn new(number: i64, test: &TapTest) -> SummarizedTest {
+        let mut diagnostics = explanation(test);
+        if test.is_failure() {
+            for (i, subtest) in test.subtests.iter().enumerate() {
+                if subtest.is_failure() {
+                    diagnostics.push(subtest.status_line(i as i64 + 1));
+                    diagnostics.extend(
+                        explanation(subtest)
+                            .iter()
+                            .map(|diagnostic| format!("  {}", diagnostic)),
+                    );
+                }
+            }
+        }
+
+        SummarizedTest {
+            number,
+            name: test.name.clone(),
+            status: TestStatus::of(test),
+            reason: test
+                .directive
+                .as_ref()
+                .map(|directive| directive.reason().to_string())
+                .unwrap_or_default(),
+            diagnostics,
+            duration: test.duration,
+            tags: test.tags.iter().cloned().collect(),
+        }
+    }
+}
+
+/// A test's diagnostics, then what it compared
+fn explanation(test: &TapTest) -> Vec<String> {
+    let mut lines = test.diagnostics.clone();
+    if let Some(comparison) = &test.comparison {
+        lines.extend(comparison.classic_lines());
+    }
+    lines
+}
+
+/// The tallied results of a `TapSuite`, renderable into a summary block meant for humans rather than TAP consumers.
+///
+/// This is deliberately kept apart from the TAP stream itself: print it to STDERR or a log file so that the stream on STDOUT stays valid.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_suite_builder::TapSuiteBuilder;
+/// use testanything::tap_summary::TapSummary;
+/// use testanything::tap_test_builder::TapTestBuilder;
+///
+/// use std::io;
+/// use std::time::Duration;
+///
+/// let tap_suite = TapSuiteBuilder::new()
+///     .name("Example TAP suite")
+///     .tests(vec![TapTestBuilder::new().name("Panda").passed(false).finalize()])
+///     .finalize();
+///
+/// let summary = TapSummary::new(&tap_suite).with_elapsed(Duration::from_millis(1500));
+///
+/// match summary.print(io::stderr().lock()) {
+///     Ok(_) => {}
+///     Err(reason) => eprintln!("{}", reason),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TapSummary {
+    /// The name of the summarized suite
+    pub name: String,
+    /// How many tests were run
+    pub total: usize,
+    /// How many of those passed, counting skipped tests and failing TODO tests
+    pub passed: usize,
+    /// How many tests were skipped
+    pub skipped: usize,
+    /// Every failing test, in stream order
+    pub failures: Vec<FailedTest>,
+    /// Every test which passed only after being retried, in stream order
+    pub flaky: Vec<FlakyTest>,
+    /// Every test, in stream order
+    pub tests: Vec<SummarizedTest>,
+    /// Wall time taken by the run, if the caller measured it
+    pub elapsed: Option<Duration>,
+    /// Every test with a duration, slowest first
+    pub timings: Vec<TimedTest>,
+    /// How many of the slowest tests to list in the report. None are listed by default.
+    pub slowest: usize,
+    /// Why the run was cut short, if it was
+    pub bail_out: Option<String>,
+    /// How many tests the suite's plan promised, if it had one
+    pub planned: Option<usize>,
+}
+
+impl TapSummary {
+    /// Tally up the results of a suite. Tests are numbered the same way `TapSuite::lines` numbers them.
+    pub fn new(suite: &TapSuite) -> TapSummary {
+        let failures = suite
+            .tests
+            .iter()
+            .enumerate()
+            .filter(|(_, test)| test.is_failure())
+            .map(|(i, test)| FailedTest {
+                number: i as i64 + 1,
+                name: test.name.clone(),
+            })
+            .collect::<Vec<FailedTest>>();
+
+        let mut timings = suite
+            .tests
+            .iter()
+            .enumerate()
+            .filter_map(|(i, test)| {
+                test.duration.map(|duration| TimedTest {
+                    number: i as i64 + 1,
+                    name: test.name.clone(),
+                    duration,
+                })
+            })
+            .collect::<Vec<TimedTest>>();
+        // Stable, so equally slow tests stay in stream order
+        timings.sort_by_key(|timed| core::cmp::Reverse(timed.duration));
+
+        TapSummary {
+            name: suite.name.clone(),
+            total: suite.tests.len(),
+            passed: suite.tests.len() - failures.len(),
+            skipped: suite.tests.iter().filter(|test| test.is_skipped()).count(),
+            failures,
+            flaky: suite
+                .tests
+                .iter()
+                .enumerate()
+                .filter(|(_, test)| test.is_flaky())
+                .map(|(i, test)| FlakyTest {
+                    number: i as i64 + 1,
+                    name: test.name.clone(),
+                    attempts: test.attempts(),
+                })
+                .collect(),
+            tests: suite
+                .tests
+                .iter()
+                .enumerate()
+                .map(|(i, test)| SummarizedTest::new(i as i64 + 1, test))
+                .collect(),
+            elapsed: None,
+            timings,
+            slowest: 0,
+            bail_out: suite.bail_out.clone(),
+            planned: suite.planned(),
+        }
+    }
+
+    /// Attach the wall time of the run so it shows up in the report.
+    pub fn with_elapsed(mut self, elapsed: Duration) -> TapSummary {
+        self.elapsed = Some(elapsed);
+        self
+    }
+
+    /// List the `count` slowest tests in the report.
+    pub fn with_slowest(mut self, count: usize) -> TapSummary {
+        self.slowest = count;
+        self
+    }
+
+    /// How the tests went, tag by tag, in order of tag. Empty if no test has a tag.
+    pub fn by_tag(&self) -> Vec<TagTally> {
+        let mut tallies: Vec<TagTally> = Vec::new();
+        for test in &self.tests {
+            for tag in &test.tags {
+                let i = match tallies.binary_search_by(|tally| tally.tag.cmp(tag)) {
+                    Ok(i) => i,
+                    Err(i) => {
+                        tallies.insert(
+                            i,
+                            TagTally {
+                                tag: tag.clone(),
+                                total: 0,
+                                failed: 0,
+                                skipped: 0,
+                            },
+                        );
+                        i
+                    }
+                };
+                tallies[i].total += 1;
+                tallies[i].failed += usize::from(test.status == TestStatus::Fail);
+                tallies[i].skipped += usize::from(test.status == TestStatus::Skip);
+            }
+        }
+        tallies
+    }
+
+    /// How many tests failed
+    pub fn failed(&self) -> usize {
+        self.failures.len()
+    }
+
+    /// What went wrong with the run as a whole, rather than with any test: bailing out, and running more or fewer tests than planned
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(reason) = &self.bail_out {
+            problems.push(format!("Bailed out: {}", reason).trim_end().to_string());
+        }
+        match self.planned {
+            Some(planned) if planned != self.total => {
+                problems.push(format!("Planned {} tests but ran {}", planned, self.total))
+            }
+            _ => {}
+        }
+        problems
+    }
+
+    /// Yield "PASS", "FAIL" or, when nothing ran at all, "NOTESTS". Same vocabulary as `prove`. A run with problems fails, whatever its tests did.
+    pub fn result_string(&self) -> String {
+        if !self.problems().is_empty() {
+            "FAIL"
+        } else if self.total == 0 {
+            "NOTESTS"
+        } else if self.failures.is_empty() {
+            "PASS"
+        } else {
+            "FAIL"
+        }
+        .to_string()
+    }
+
+    /// Produce all lines of the summary block, in order.
+    pub fn lines(&self) -> Vec<String> {
+        let mut tally = format!("Tests: {} Failed: {}", self.total, self.failed());
+        if self.skipped > 0 {
+            tally.push_str(&format!(" Skipped: {}", self.skipped));
+        }
+        let mut lines = vec![
+            "Test Summary Report".to_string(),
+            "-------------------".to_string(),
+            format!("{} ({})", self.name, tally),
+        ];
+
+        let problems = self.problems();
+        if self.total == 0 && problems.is_empty() {
+            lines.push("No tests run.".to_string());
+        } else if self.failures.is_empty() && problems.is_empty() {
+            lines.push("All tests successful.".to_string());
+        } else if !self.failures.is_empty() {
+            let numbers = self
+                .failures
+                .iter()
+                .map(|failure| failure.number.to_string())
+                .collect::<Vec<String>>();
+            lines.push(format!("  Failed tests: {}", numbers.join(", ")));
+            lines.extend(
+                self.failures
+                    .iter()
+                    .map(|failure| format!("    {} - {}", failure.number, failure.name)),
+            );
+            lines.push(format!(
+                "Failed {}/{} tests, {:.2}% okay",
+                self.failed(),
+                self.total,
+                self.passed as f64 * 100.0 / self.total as f64
+            ));
+        }
+        lines.extend(problems);
+
+        if !self.flaky.is_empty() {
+            let numbers = self
+                .flaky
+                .iter()
+                .map(|flaky| flaky.number.to_string())
+                .collect::<Vec<String>>();
+            lines.push(format!("  Flaky tests: {}", numbers.join(", ")));
+            lines.extend(self.flaky.iter().map(|flaky| {
+                format!(
+                    "    {} - {} (passed on attempt {})",
+                    flaky.number, flaky.name, flaky.attempts
+                )
+            }));
+        }
+
+        let slowest = &self.timings[..self.slowest.min(self.timings.len())];
+        if !slowest.is_empty() {
+            lines.push(format!("Slowest {} tests:", slowest.len()));
+            lines.extend(slowest.iter().map(|timed| {
+                format!(
+                    "  {:>9.3}s  {} - {}",
+                    timed.duration.as_secs_f64(),
+                    timed.number,
+                    timed.name
+                )
+            }));
+        }
+
+        if let Some(elapsed) = self.elapsed {
+            lines.push(format!("Wallclock: {:.3}s", elapsed.as_secs_f64()));
+        }
+
+        lines.push(format!("Result: {}", self.result_string()));
+
+        lines
+    }
+}
+
+#[cfg(feature = "std")]
+impl TapSummary {
+    /// Emit the summary block to the provided sink, which must be `Write`. Ends with a newline, unlike a TAP stream.
+    pub fn print<T: Write>(&self, mut sink: T) -> Result<String, String> {
+        let output = self.lines().join("\n");
+        match writeln!(&mut sink, "{}", output) {
+            Ok(_) => Result::Ok(output),
+            Err(reason) => Result::Err(reason.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TapSummary, TestStatus};
+    use crate::tap_comparison::TapComparison;
+    use crate::tap_parser::TapParser;
+    use crate::tap_selection::TapSelection;
+    use crate::tap_suite::TapSuite;
+    use crate::tap_test_builder::TapTestBuilder;
+    use crate::tap_yaml::YamlValue;
+
+    use std::time::Duration;
+
+    fn example_suite() -> TapSuite {
+        TapSuite {
+            name: "Example TAP Suite".to_string(),
+            tests: vec![
+                TapTestBuilder::new().name("Panda").passed(true).finalize(),
+                TapTestBuilder::new()
+                    .name("Bamboo")
+                    .passed(false)
+                    .finalize(),
+                TapTestBuilder::new()
+                    .name("Curry")
+                    .passed(false)
+                    .todo("Not cooked yet")
+                    .finalize(),
+                TapTestBuilder::new()
+                    .name("Noodle")
+                    .passed(false)
+                    .finalize(),
+                TapTestBuilder::new()
+                    .name("Tree")
+                    .passed(true)
+                    .skip("Winter")
+                    .finalize(),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_summary_counts() {
+        let summary = TapSummary::new(&example_suite());
+
+        assert_eq!(summary.total, 5);
+        assert_eq!(summary.passed, 3);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.failed(), 2);
+        assert_eq!(summary.failures[1].number, 4);
+        assert_eq!(summary.failures[1].name, "Noodle");
+        assert_eq!(summary.result_string(), "FAIL");
+        assert_eq!(summary.tests.len(), 5);
+        assert_eq!(summary.tests[2].status, TestStatus::Todo);
+        assert_eq!(summary.tests[2].reason, "Not cooked yet");
+    }
+
+    #[test]
+    fn test_failing_summary_lines() {
+        let summary = TapSummary::new(&example_suite()).with_elapsed(Duration::from_millis(1250));
+
+        let expected = vec![
+            "Test Summary Report",
+            "-------------------",
+            "Example TAP Suite (Tests: 5 Failed: 2 Skipped: 1)",
+            "  Failed tests: 2, 4",
+            "    2 - Bamboo",
+            "    4 - Noodle",
+            "Failed 2/5 tests, 60.00% okay",
+            "Wallclock: 1.250s",
+            "Result: FAIL",
+        ];
+
+        assert_eq!(expected, summary.lines());
+    }
+
+    #[test]
+    fn test_passing_and_empty_summary_lines() {
+        let passing = TapSuite {
+            name: "Passing".to_string(),
+            tests: vec![TapTestBuilder::new().name("Panda").passed(true).finalize()],
+            ..Default::default()
+        };
+        let passing_lines = TapSummary::new(&passing).lines();
+
+        assert_eq!(passing_lines[3], "All tests successful.");
+        assert_eq!(passing_lines[4], "Result: PASS");
+
+        let empty = TapSuite {
+            name: "Empty".to_string(),
+            tests: vec![],
+            ..Default::default()
+        };
+        let empty_lines = TapSummary::new(&empty).lines();
+
+        assert_eq!(empty_lines[3], "No tests run.");
+        assert_eq!(empty_lines[4], "Result: NOTESTS");
+    }
+
+    #[test]
+    fn test_summarized_comparison() {
+        let tap_suite = TapSuite {
+            name: "Zoo".to_string(),
+            tests: vec![TapTestBuilder::new()
+                .name("Weigh panda")
+                .passed(false)
+                .diagnostics(&["Too light"])
+                .comparison(TapComparison::equal("99kg", "100kg"))
+                .finalize()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            TapSummary::new(&tap_suite).tests[0].diagnostics,
+            vec![
+                "Too light",
+                "       got: 99kg",
+                "  expected: 100kg",
+                "  operator: =="
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bailed_out_and_short_summary_lines() {
+        let bailed_out = TapParser::new().parse_suite("Zoo", "1..2\nok 1 a\nBail out! db down");
+        let bailed_out_lines = TapSummary::new(&bailed_out).lines();
+
+        assert_eq!(
+            bailed_out_lines[3..],
+            [
+                "Bailed out: db down",
+                "Planned 2 tests but ran 1",
+                "Result: FAIL"
+            ]
+        );
+
+        let short = TapParser::new().parse_suite("Zoo", "1..3\nok 1 a");
+        let short_summary = TapSummary::new(&short);
+
+        assert_eq!(short_summary.result_string(), "FAIL");
+        assert_eq!(short_summary.lines()[3], "Planned 3 tests but ran 1");
+
+        let selected = TapSelection::new().with_name("a").apply(&short);
+        assert_eq!(TapSummary::new(&selected).result_string(), "FAIL");
+    }
+
+    #[test]
+    fn test_slowest_lines() {
+        let tap_suite = TapSuite {
+            name: "Timed".to_string(),
+            tests: vec![
+                TapTestBuilder::new()
+                    .name("Panda")
+                    .passed(true)
+                    .duration(Duration::from_millis(20))
+                    .finalize(),
+                TapTestBuilder::new().name("Bamboo").passed(true).finalize(),
+                TapTestBuilder::new()
+                    .name("Curry")
+                    .passed(true)
+                    .duration(Duration::from_millis(1500))
+                    .finalize(),
+                TapTestBuilder::new()
+                    .name("Noodle")
+                    .passed(true)
+                    .duration(Duration::from_millis(3))
+                    .finalize(),
+            ],
+            ..Default::default()
+        };
+
+        let summary = TapSummary::new(&tap_suite).with_slowest(2);
+
+        assert_eq!(summary.timings.len(), 3);
+        let lines = summary.lines();
+        assert_eq!(lines[4], "Slowest 2 tests:");
+        assert_eq!(lines[5], "      1.500s  3 - Curry");
+        assert_eq!(lines[6], "      0.020s  1 - Panda");
+        assert_eq!(lines[7], "Result: PASS");
+    }
+
+    #[test]
+    fn test_by_tag() {
+        let tap_suite = TapSuite {
+            name: "Tagged".to_string(),
+            tests: vec![
+                TapTestBuilder::new()
+                    .name("Panda")
+                    .passed(true)
+                    .tags(&["slow", "network"])
+                    .finalize(),
+                TapTestBuilder::new()
+                    .name("Bamboo")
+                    .passed(false)
+                    .tag("network")
+                    .finalize(),
+                TapTestBuilder::new().name("Curry").passed(true).finalize(),
+            ],
+            ..Default::default()
+        };
+
+        let summary = TapSummary::new(&tap_suite);
+
+        assert_eq!(summary.tests[0].tags, vec!["network", "slow"]);
+        let by_tag = summary.by_tag();
+        assert_eq!(by_tag.len(), 2);
+        assert_eq!(
+            (by_tag[0].tag.as_str(), by_tag[0].total, by_tag[0].failed),
+            ("network", 2, 1)
+        );
+        assert_eq!((by_tag[1].tag.as_str(), by_tag[1].total), ("slow", 1));
+    }
+
+    #[test]
+    fn test_flaky_lines() {
+        let attempt =
+            |passed: &str| YamlValue::Map(vec![("passed".to_string(), YamlValue::scalar(passed))]);
+        let tap_suite = TapSuite {
+            name: "Flaky".to_string(),
+            tests: vec![
+                TapTestBuilder::new().name("Panda").passed(true).finalize(),
+                TapTestBuilder::new()
+                    .name("Bamboo")
+                    .passed(true)
+                    .yaml_field(
+                        "attempts",
+                        YamlValue::List(vec![attempt("false"), attempt("false"), attempt("true")]),
+                    )
+                    .finalize(),
+            ],
+            ..Default::default()
+        };
+
+        let summary = TapSummary::new(&tap_suite);
+
+        assert_eq!(summary.flaky.len(), 1);
+        assert_eq!(summary.flaky[0].attempts, 3);
+        let lines = summary.lines();
+        assert_eq!(lines[3], "All tests successful.");
+        assert_eq!(lines[4], "  Flaky tests: 2");
+        assert_eq!(lines[5], "    2 - Bamboo (passed on attempt 3)");
+        assert_eq!(lines[6], "Result: PASS");
+    }
+}
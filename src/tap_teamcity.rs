@@ -0,0 +1,348 @@
+//! `TapTeamcityReporter` -- Turns TAP results into TeamCity service messages, which TeamCity reads off a build's output as test results
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::tap_directive::TapDirective;
+use crate::tap_parser::TapEvent;
+use crate::tap_suite::TapSuite;
+use crate::tap_test::TapTest;
+
+/// Produce service messages for a finished suite. The suite is reported as a TeamCity test suite, as is every test with subtests; a test like that is reported as a test of its own as well only if it failed where none of its subtests did.
+///
+/// # Examples
+///
+/// ```
+/// use testanything::tap_parser::TapParser;
+/// use testanything::tap_teamcity::teamcity_messages;
+///
+/// let tap_suite = TapParser::new().parse_suite("Zoo", "1..1\nnot ok 1 Tiger\n# Escaped [again]");
+///
+/// assert_eq!(
+///     teamcity_messages(&tap_suite),
+///     vec![
+///         "##teamcity[testSuiteStarted name='Zoo']",
+///         "##teamcity[testStarted name='Tiger']",
+///         "##teamcity[testFailed name='Tiger' message='Escaped |[again|]']",
+///         "##teamcity[testFinished name='Tiger']",
+///         "##teamcity[testSuiteFinished name='Zoo']",
+///     ]
+/// );
+/// ```
+pub fn teamcity_messages(suite: &TapSuite) -> Vec<String> {
+    let mut messages = Vec::new();
+    suite_messages(&suite.name, &suite.tests, &mut messages);
+    if let Some(reason) = &suite.bail_out {
+        messages.push(build_problem(reason));
+    }
+    messages
+}
+
+fn suite_messages(name: &str, tests: &[TapTest], messages: &mut Vec<String>) {
+    if !name.is_empty() {
+        messages.push(message("testSuiteStarted", &[("name", name)]));
+    }
+    for test in tests {
+        if test.subtests.is_empty() {
+            messages.extend(test_messages(test));
+        } else {
+            suite_messages(&test.name, &test.subtests, messages);
+            if test.is_failure() && !has_failure(&test.subtests) {
+                messages.extend(test_messages(test));
+            }
+        }
+    }
+    if !name.is_empty() {
+        messages.push(message("testSuiteFinished", &[("name", name)]));
+    }
+}
+
+fn has_failure(tests: &[TapTest]) -> bool {
+    tests
+        .iter()
+        .any(|test| test.is_failure() || has_failure(&test.subtests))
+}
+
+/// Produces service messages from a live TAP stream, one `TapEvent` at a time. A test is reported once its diagnostics are all in, i.e. at the next line that isn't one of them; TAP only says how a test went after it has run, so it is started and finished in one go.
+#[derive(Debug, Default)]
+pub struct TapTeamcityReporter {
+    name: String,
+    started: bool,
+    pending: Option<TapTest>,
+    // Reads the subtest stream in progress, if there is one
+    child: Option<Box<TapTeamcityReporter>>,
+    // Whether any test reported so far, at any depth, failed
+    failed: bool,
+}
+
+impl TapTeamcityReporter {
+    /// Make a reporter positioned at the start of a stream, reporting it as a suite called `name`. Leave the name blank to report the tests on their own.
+    pub fn new(name: &str) -> TapTeamcityReporter {
+        TapTeamcityReporter {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Take in the next piece of a TAP stream, yielding any messages it completes.
+    pub fn event(&mut self, event: &TapEvent) -> Vec<String> {
+        let mut messages = self.start();
+        match event {
+            TapEvent::Yaml(fields) => {
+                if let Some(test) = self.pending.as_mut() {
+                    test.apply_yaml(fields);
+                }
+            }
+            TapEvent::Diagnostic(diagnostic) => {
+                if let Some(test) = self.pending.as_mut() {
+                    test.diagnostics.push(diagnostic.clone());
+                }
+            }
+            TapEvent::Subtest(inner) => {
+                messages.extend(self.flush());
+                let child = self.child.get_or_insert_with(|| {
+                    let name = match inner.as_ref() {
+                        TapEvent::Diagnostic(comment) => comment.strip_prefix("Subtest: "),
+                        _ => None,
+                    };
+                    Box::new(TapTeamcityReporter::new(name.unwrap_or("Subtest")))
+                });
+                messages.extend(child.event(inner));
+            }
+            TapEvent::Test { test, .. } => {
+                messages.extend(self.flush());
+                match self.child.take() {
+                    Some(mut child) => {
+                        messages.extend(child.finish());
+                        self.failed |= child.failed;
+                        if test.is_failure() && !child.failed {
+                            self.pending = Some(test.as_ref().clone());
+                        }
+                    }
+                    None => self.pending = Some(test.as_ref().clone()),
+                }
+            }
+            TapEvent::BailOut(reason) => {
+                messages.extend(self.finish());
+                messages.push(build_problem(reason));
+            }
+            _ => messages.extend(self.flush()),
+        }
+        messages
+    }
+
+    /// Yield the messages still held back, at the end of the stream.
+    pub fn finish(&mut self) -> Vec<String> {
+        let mut messages = self.start();
+        if let Some(mut child) = self.child.take() {
+            messages.extend(child.finish());
+            self.failed |= child.failed;
+        }
+        messages.extend(self.flush());
+        if !self.name.is_empty() {
+            messages.push(message("testSuiteFinished", &[("name", &self.name)]));
+            // Anything after this belongs outside the suite
+            self.name.clear();
+        }
+        messages
+    }
+
+    fn start(&mut self) -> Vec<String> {
+        if self.started || self.name.is_empty() {
+            return Vec::new();
+        }
+        self.started = true;
+        vec![message("testSuiteStarted", &[("name", &self.name)])]
+    }
+
+    fn flush(&mut self) -> Vec<String> {
+        match self.pending.take() {
+            Some(test) => {
+                self.failed |= test.is_failure();
+                test_messages(&test)
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+fn test_messages(test: &TapTest) -> Vec<String> {
+    let name = test.name.as_str();
+    let mut messages = vec![message("testStarted", &[("name", name)])];
+
+    match &test.directive {
+        Some(TapDirective::Skip(reason)) => {
+            messages.push(message(
+                "testIgnored",
+                &[("name", name), ("message", reason)],
+            ));
+        }
+        Some(TapDirective::Todo(reason)) if !test.passed => {
+            let reason = format!("TODO {}", reason);
+            messages.push(message(
+                "testIgnored",
+                &[("name", name), ("message", reason.trim_end())],
+            ));
+        }
+        _ if test.is_failure() => {
+            let (first, rest) = test
+                .diagnostics
+                .split_first()
+                .map(|(first, rest)| (first.as_str(), rest))
+                .unwrap_or(("Test failed", &[]));
+            let details = rest.join("\n");
+            let mut attributes = vec![("name", name), ("message", first)];
+            if !details.is_empty() {
+                attributes.push(("details", &details));
+            }
+            // Which TeamCity shows side by side, with a diff
+            if let Some(comparison) = &test.comparison {
+                attributes.extend([
+                    ("type", "comparisonFailure"),
+                    ("expected", &comparison.expected),
+                    ("actual", &comparison.got),
+                ]);
+            }
+            messages.push(message("testFailed", &attributes));
+        }
+        _ => {}
+    }
+
+    let duration = test
+        .duration
+        .map(|duration| duration.as_millis().to_string());
+    let mut attributes = vec![("name", name)];
+    if let Some(duration) = &duration {
+        attributes.push(("duration", duration));
+    }
+    messages.push(message("testFinished", &attributes));
+
+    messages
+}
+
+fn build_problem(reason: &str) -> String {
+    let description = format!("Bail out! {}", reason);
+    message("buildProblem", &[("description", description.trim_end())])
+}
+
+fn message(name: &str, attributes: &[(&str, &str)]) -> String {
+    let mut buf = format!("##teamcity[{}", name);
+    for (key, value) in attributes {
+        buf.push_str(&format!(" {}='{}'", key, escape(value)));
+    }
+    buf.push(']');
+    buf
+}
+
+/// TeamCity escapes with a vertical bar rather than a backslash.
+fn escape(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '|' => buf.push_str("||"),
+            '\'' => buf.push_str("|'"),
+            '\n' => buf.push_str("|n"),
+            '\r' => buf.push_str("|r"),
+            '[' => buf.push_str("|["),
+            ']' => buf.push_str("|]"),
+            '\u{0085}' => buf.push_str("|x"),
+            '\u{2028}' => buf.push_str("|l"),
+            '\u{2029}' => buf.push_str("|p"),
+            c => buf.push(c),
+        }
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, teamcity_messages, TapTeamcityReporter};
+    use crate::tap_comparison::TapComparison;
+    use crate::tap_parser::TapParser;
+
+    const STREAM: &str = "TAP version 14
+1..4
+ok 1 Panda
+  ---
+  duration_ms: 12.5
+  ...
+not ok 2 Tiger's cage
+# Escaped
+# Twice
+    # Subtest: Aquarium
+    1..2
+    not ok 1 Shark
+    ok 2 Octopus # SKIP drained
+not ok 3 Aquarium
+not ok 4 Curry # TODO too spicy
+Bail out! Flooded";
+
+    #[test]
+    fn test_teamcity_messages() {
+        let tap_suite = TapParser::new().parse_suite("Zoo", STREAM);
+
+        let expected = vec![
+            "##teamcity[testSuiteStarted name='Zoo']",
+            "##teamcity[testStarted name='Panda']",
+            "##teamcity[testFinished name='Panda' duration='12']",
+            "##teamcity[testStarted name='Tiger|'s cage']",
+            "##teamcity[testFailed name='Tiger|'s cage' message='Escaped' details='Twice']",
+            "##teamcity[testFinished name='Tiger|'s cage']",
+            "##teamcity[testSuiteStarted name='Aquarium']",
+            "##teamcity[testStarted name='Shark']",
+            "##teamcity[testFailed name='Shark' message='Test failed']",
+            "##teamcity[testFinished name='Shark']",
+            "##teamcity[testStarted name='Octopus']",
+            "##teamcity[testIgnored name='Octopus' message='drained']",
+            "##teamcity[testFinished name='Octopus']",
+            "##teamcity[testSuiteFinished name='Aquarium']",
+            "##teamcity[testStarted name='Curry']",
+            "##teamcity[testIgnored name='Curry' message='TODO too spicy']",
+            "##teamcity[testFinished name='Curry']",
+            "##teamcity[testSuiteFinished name='Zoo']",
+            "##teamcity[buildProblem description='Bail out! Flooded']",
+        ];
+
+        assert_eq!(expected, teamcity_messages(&tap_suite));
+    }
+
+    #[test]
+    fn test_teamcity_reporter_events() {
+        let mut parser = TapParser::new();
+        let mut reporter = TapTeamcityReporter::new("Zoo");
+
+        let mut messages = Vec::new();
+        for line in STREAM.lines() {
+            if let Some(event) = parser.parse_line(line) {
+                messages.extend(reporter.event(&event));
+            }
+        }
+        messages.extend(reporter.finish());
+
+        let tap_suite = TapParser::new().parse_suite("Zoo", STREAM);
+        assert_eq!(teamcity_messages(&tap_suite), messages);
+    }
+
+    #[test]
+    fn test_comparison_failure() {
+        let mut tap_suite = TapParser::new().parse_suite("Zoo", "1..1\nnot ok 1 Weigh panda");
+        tap_suite.tests[0].comparison = Some(TapComparison::equal("99kg", "100kg"));
+
+        assert_eq!(
+            teamcity_messages(&tap_suite)[2],
+            "##teamcity[testFailed name='Weigh panda' message='Test failed' type='comparisonFailure' expected='100kg' actual='99kg']"
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a|b'c\nd\re[f]"), "a||b|'c|nd|re|[f|]");
+        assert_eq!(escape("\u{2028}\u{2029}\u{0085}"), "|l|p|x");
+    }
+}
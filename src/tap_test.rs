@@ -2,17 +2,26 @@
 
 #[cfg(feature = "alloc")]
 use alloc::{
+    collections::{BTreeMap, BTreeSet},
     string::{String, ToString},
     vec,
     vec::Vec,
 };
 use core::fmt::Write;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 
+use crate::tap_comparison::TapComparison;
+use crate::tap_directive::TapDirective;
+use crate::tap_location::TapLocation;
+use crate::tap_version::TapVersion;
+use crate::tap_yaml::{self, YamlValue};
 use crate::{NOT_OK_SYMBOL, OK_SYMBOL};
 
 /// A test, a collection of which (a `TapSuite`) will be rendered into a TAP text stream. A `TapTest` knows how to render itself.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct TapTest {
     /// The name of the test, will be the primary text on a TAP test line
     pub name: String,
@@ -20,6 +29,22 @@ pub struct TapTest {
     pub passed: bool,
     /// If this test merits additional comments (diagnostics, in TAP parlance), they will be rendered in the TAP stream beginning with a # mark.
     pub diagnostics: Vec<String>,
+    /// How long the test took to run, if anybody measured. Rendered as `duration_ms`.
+    pub duration: Option<Duration>,
+    /// Where the test lives in the source, if known. Rendered as `at`.
+    pub location: Option<TapLocation>,
+    /// What a failing assertion got, what it expected, and how it compared them. Rendered as `got`, `expected` and `operator`.
+    pub comparison: Option<TapComparison>,
+    /// Labels for grouping and selecting tests, like `slow`, `network` or `owner:team-x`. Rendered as `tags`.
+    pub tags: BTreeSet<String>,
+    /// Whatever else is worth knowing about the test, by name. Rendered as `metadata`.
+    pub metadata: BTreeMap<String, String>,
+    /// Further structured diagnostics for the YAML block, beyond what `TapTest` models itself. Parsing keeps whatever it doesn't understand here.
+    pub yaml: Vec<(String, YamlValue)>,
+    /// A SKIP or TODO directive, if the result needs qualifying
+    pub directive: Option<TapDirective>,
+    /// Tests nested under this one. They are rendered as an indented TAP stream of their own, just before this test's line.
+    pub subtests: Vec<TapTest>,
 }
 
 impl TapTest {
@@ -33,20 +58,63 @@ impl TapTest {
         .to_string()
     }
 
-    /// Produce a properly-formatted TAP line. This excludes diagnostics.
+    /// Does this test count against the suite? A failing TODO test doesn't.
+    pub fn is_failure(&self) -> bool {
+        !self.passed && !matches!(self.directive, Some(TapDirective::Todo(_)))
+    }
+
+    /// Was this test skipped?
+    pub fn is_skipped(&self) -> bool {
+        matches!(self.directive, Some(TapDirective::Skip(_)))
+    }
+
+    /// Is this test tagged with `tag`?
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    /// How many times this test was run to get its result. A retried test records each attempt under `attempts`; any other test ran once.
+    pub fn attempts(&self) -> usize {
+        match self.yaml_value("attempts") {
+            Some(YamlValue::List(attempts)) if !attempts.is_empty() => attempts.len(),
+            _ => 1,
+        }
+    }
+
+    /// Did this test pass only after failing at least once?
+    pub fn is_flaky(&self) -> bool {
+        self.passed && self.attempts() > 1
+    }
+
+    /// Produce a properly-formatted TAP line, including any directive. This excludes diagnostics. A `#` or `\\` in the name is escaped with a backslash, so it can't be read as a directive.
     pub fn status_line(&self, test_number: i64) -> String {
         let ok_string = self.ok_string();
         let test_number_string = test_number.to_string();
+        let name = escape_name(&self.name);
         let mut buf =
-            String::with_capacity(ok_string.len() + test_number_string.len() + self.name.len());
-        write!(&mut buf, "{} {} {}", ok_string, test_number, self.name).unwrap();
+            String::with_capacity(ok_string.len() + test_number_string.len() + name.len());
+        write!(&mut buf, "{} {} {}", ok_string, test_number, name).unwrap();
+        if let Some(directive) = &self.directive {
+            write!(&mut buf, " {}", directive.directive_string()).unwrap();
+        }
         buf
     }
 
     /// Produce all lines (inclusive of diagnostics) representing this test. This is the money, right here.
     pub fn tap(&self, test_number: i64) -> Vec<String> {
+        self.tap_with_version(test_number, TapVersion::default())
+    }
+
+    /// Produce all lines representing this test for a particular version of TAP. Structured data goes in a YAML block where the version allows it, and into trailing diagnostics where it doesn't.
+    pub fn tap_with_version(&self, test_number: i64, version: TapVersion) -> Vec<String> {
+        let mut lines = self.subtest_lines(version);
         // Build the first line
-        let mut lines = vec![self.status_line(test_number)];
+        lines.push(self.status_line(test_number));
+        let yaml = self.yaml_fields();
+        // A YAML block must come directly after the line it belongs to
+        if version.supports_yaml() && !yaml.is_empty() {
+            lines.extend(tap_yaml::render_block(&yaml));
+        }
         // If there are diagnostics lines, format them.
         let formatted_diagnostics = self
             .diagnostics
@@ -56,6 +124,126 @@ impl TapTest {
 
         lines.extend(formatted_diagnostics.iter().cloned());
 
+        if !version.supports_yaml() {
+            let trailing = self
+                .classic_diagnostics()
+                .iter()
+                .map(|comment| self.format_diagnostics(comment))
+                .collect::<Vec<String>>();
+            lines.extend(trailing);
+        }
+
+        lines
+    }
+
+    /// Produce the indented stream of subtests, headed by a "# Subtest" comment and a plan. Empty if there are no subtests.
+    pub fn subtest_lines(&self, version: TapVersion) -> Vec<String> {
+        if self.subtests.is_empty() {
+            return Vec::new();
+        }
+
+        let mut buf = String::new();
+        write!(&mut buf, "# Subtest: {}", self.name).unwrap();
+        let mut plan = String::new();
+        write!(&mut plan, "1..{}", self.subtests.len()).unwrap();
+        let mut child_lines = vec![buf, plan];
+
+        for (i, subtest) in self.subtests.iter().enumerate() {
+            child_lines.extend(subtest.tap_with_version(i as i64 + 1, version));
+        }
+
+        child_lines
+            .iter()
+            .map(|line| {
+                let mut buf = String::with_capacity(line.len() + 4);
+                write!(&mut buf, "    {}", line).unwrap();
+                buf
+            })
+            .collect()
+    }
+
+    /// The structured data about this test, as it goes into a YAML block.
+    pub fn yaml_fields(&self) -> Vec<(String, YamlValue)> {
+        self.fields(true)
+    }
+
+    fn fields(&self, with_comparison: bool) -> Vec<(String, YamlValue)> {
+        let mut fields = Vec::new();
+        if let Some(duration) = self.duration {
+            fields.push((
+                "duration_ms".to_string(),
+                YamlValue::Scalar(format_duration_ms(duration)),
+            ));
+        }
+        if let Some(location) = &self.location {
+            fields.push(("at".to_string(), location.to_yaml()));
+        }
+        if let (true, Some(comparison)) = (with_comparison, &self.comparison) {
+            fields.extend(comparison.yaml_fields());
+        }
+        if !self.tags.is_empty() {
+            let tags = self.tags.iter().map(|tag| YamlValue::scalar(tag.as_str()));
+            fields.push(("tags".to_string(), YamlValue::List(tags.collect())));
+        }
+        if !self.metadata.is_empty() {
+            let metadata = self
+                .metadata
+                .iter()
+                .map(|(key, value)| (key.clone(), YamlValue::scalar(value.as_str())));
+            fields.push(("metadata".to_string(), YamlValue::Map(metadata.collect())));
+        }
+        fields.extend(self.yaml.iter().cloned());
+        fields
+    }
+
+    /// Pick up whatever this test understands out of a parsed YAML block. Anything else is kept in `yaml`.
+    pub fn apply_yaml(&mut self, fields: &[(String, YamlValue)]) {
+        for (key, value) in fields {
+            match key.as_str() {
+                "duration_ms" => {
+                    if let Some(duration) = value.as_str().and_then(parse_duration_ms) {
+                        self.duration = Some(duration);
+                    }
+                }
+                "got" | "expected" | "operator" if value.as_str().is_some() => {
+                    let comparison = self.comparison.get_or_insert_with(Default::default);
+                    let value = value.as_str().unwrap_or_default().to_string();
+                    match key.as_str() {
+                        "got" => comparison.got = value,
+                        "expected" => comparison.expected = value,
+                        _ => comparison.operator = value,
+                    }
+                }
+                "tags" if scalar_list(value).is_some() => {
+                    self.tags.extend(scalar_list(value).unwrap_or_default());
+                }
+                "metadata" if scalar_map(value).is_some() => {
+                    self.metadata.extend(scalar_map(value).unwrap_or_default());
+                }
+                // Worked out afresh from the values it was made from
+                "diff" if self.comparison.is_some() => {}
+                "at" if self.location.is_none() => match TapLocation::from_yaml(value) {
+                    Some(location) => self.location = Some(location),
+                    None => self.yaml.push((key.clone(), value.clone())),
+                },
+                _ => self.yaml.push((key.clone(), value.clone())),
+            }
+        }
+    }
+
+    /// Look up a structured diagnostic by key, among those that aren't otherwise modeled.
+    pub fn yaml_value(&self, key: &str) -> Option<&YamlValue> {
+        tap_yaml::lookup(&self.yaml, key)
+    }
+
+    /// The structured data about this test, flattened into diagnostics for consumers which predate YAML blocks.
+    fn classic_diagnostics(&self) -> Vec<String> {
+        let mut lines = self
+            .comparison
+            .as_ref()
+            .map(TapComparison::classic_lines)
+            .unwrap_or_default();
+        lines.extend(tap_yaml::render_entries(&self.fields(false), 0));
         lines
     }
 
@@ -67,6 +255,45 @@ impl TapTest {
     }
 }
 
+/// The items of a list made only of scalars
+fn scalar_list(value: &YamlValue) -> Option<Vec<String>> {
+    match value {
+        YamlValue::List(items) => items
+            .iter()
+            .map(|item| item.as_str().map(ToString::to_string))
+            .collect(),
+        _ => None,
+    }
+}
+
+/// The entries of a map made only of scalars
+fn scalar_map(value: &YamlValue) -> Option<Vec<(String, String)>> {
+    match value {
+        YamlValue::Map(entries) => entries
+            .iter()
+            .map(|(key, value)| value.as_str().map(|value| (key.clone(), value.to_string())))
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Milliseconds, to the microsecond
+pub(crate) fn format_duration_ms(duration: Duration) -> String {
+    let micros = duration.as_micros();
+    let mut buf = String::new();
+    write!(&mut buf, "{}.{:03}", micros / 1000, micros % 1000).unwrap();
+    buf
+}
+
+fn parse_duration_ms(s: &str) -> Option<Duration> {
+    let ms = s.trim().parse::<f64>().ok()?;
+    if ms.is_finite() && ms >= 0.0 {
+        Some(Duration::from_micros((ms * 1000.0 + 0.5) as u64))
+    } else {
+        None
+    }
+}
+
 impl From<TapTest> for String {
     fn from(tap_test: TapTest) -> Self {
         let mut buf = String::new();
@@ -99,9 +326,39 @@ impl fmt::Display for TapTest {
     }
 }
 
+/// A test name as it's written on a test line, with `#` and `\\` escaped
+pub(crate) fn escape_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c == '#' || c == '\\' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A test name as it was before `escape_name`. A backslash before anything else is kept.
+pub(crate) fn unescape_name(name: &str) -> String {
+    let mut unescaped = String::with_capacity(name.len());
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(&next @ ('#' | '\\')) if c == '\\' => {
+                unescaped.push(next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tap_parser::TapParser;
+    use crate::tap_test_builder::TapTestBuilder;
 
     #[test]
     fn test_tap_test_status_string() {
@@ -109,6 +366,7 @@ mod tests {
             name: "Panda".to_string(),
             passed: true,
             diagnostics: vec!["Doing fine".to_string()],
+            ..Default::default()
         };
 
         let expected_passing = OK_SYMBOL;
@@ -120,6 +378,7 @@ mod tests {
             name: "Panda".to_string(),
             passed: false,
             diagnostics: vec!["Doing fine".to_string()],
+            ..Default::default()
         };
 
         let expected_failing = NOT_OK_SYMBOL;
@@ -134,6 +393,7 @@ mod tests {
             name: "Panda".to_string(),
             passed: true,
             diagnostics: vec!["Doing fine".to_string()],
+            ..Default::default()
         };
 
         let expected_passing = "ok 42 Panda";
@@ -145,6 +405,7 @@ mod tests {
             name: "Panda".to_string(),
             passed: false,
             diagnostics: vec!["Doing fine".to_string()],
+            ..Default::default()
         };
 
         let expected_failing = "not ok 42 Panda";
@@ -159,6 +420,7 @@ mod tests {
             name: "Panda".to_string(),
             passed: true,
             diagnostics: vec!["Doing fine".to_string()],
+            ..Default::default()
         };
 
         let expected_passing = vec!["ok 42 Panda", "# Doing fine"];
@@ -173,6 +435,7 @@ mod tests {
             name: "Panda".to_string(),
             passed: true,
             diagnostics: vec!["Doing fine".to_string()],
+            ..Default::default()
         };
 
         let expected_passing = "# Doing fine";
@@ -180,4 +443,238 @@ mod tests {
 
         assert_eq!(expected_passing, actual_passing);
     }
+
+    #[test]
+    fn test_tap_lines_with_duration() {
+        let tap_test = TapTest {
+            name: "Panda".to_string(),
+            passed: true,
+            diagnostics: vec!["Doing fine".to_string()],
+            duration: Some(Duration::from_micros(12_345)),
+            ..Default::default()
+        };
+
+        let expected_classic = vec!["ok 1 Panda", "# Doing fine", "# duration_ms: 12.345"];
+        assert_eq!(expected_classic, tap_test.tap(1));
+
+        let expected_yaml = vec![
+            "ok 1 Panda",
+            "  ---",
+            "  duration_ms: 12.345",
+            "  ...",
+            "# Doing fine",
+        ];
+        assert_eq!(
+            expected_yaml,
+            tap_test.tap_with_version(1, TapVersion::Tap13)
+        );
+    }
+
+    #[test]
+    fn test_apply_yaml() {
+        let mut tap_test = TapTest::default();
+        let mut fields = tap_test_with_duration().yaml_fields();
+        fields.push(("severity".to_string(), YamlValue::scalar("fail")));
+        tap_test.apply_yaml(&fields);
+
+        assert_eq!(tap_test.duration, Some(Duration::from_micros(1_500)));
+        assert_eq!(
+            tap_test.yaml_value("severity"),
+            Some(&YamlValue::scalar("fail"))
+        );
+        assert_eq!(tap_test.yaml_fields(), fields);
+        assert_eq!(
+            tap_test.tap(1)[1..],
+            ["# duration_ms: 1.500", "# severity: fail"]
+        );
+    }
+
+    #[test]
+    fn test_tags_and_metadata() {
+        let tap_test = TapTestBuilder::new()
+            .name("Panda")
+            .passed(true)
+            .tags(&["slow", "owner:team-x"])
+            .tag("network")
+            .metadata("ticket", "ZOO-42")
+            .finalize();
+
+        let lines = tap_test.tap_with_version(1, TapVersion::Tap13);
+        assert_eq!(
+            lines,
+            vec![
+                "ok 1 Panda",
+                "  ---",
+                "  tags:",
+                "    - network",
+                "    - owner:team-x",
+                "    - slow",
+                "  metadata:",
+                "    ticket: ZOO-42",
+                "  ...",
+            ]
+        );
+
+        let mut parsed = TapTest {
+            name: "Panda".to_string(),
+            passed: true,
+            ..Default::default()
+        };
+        parsed.apply_yaml(&tap_yaml::parse_block(&lines[2..lines.len() - 1]));
+        assert_eq!(parsed, tap_test);
+        assert!(parsed.has_tag("owner:team-x"));
+    }
+
+    #[test]
+    fn test_tap_lines_with_location() {
+        let tap_test = TapTest {
+            name: "Panda".to_string(),
+            location: Some(TapLocation::new("src/zoo.rs", 12, 5).with_module_path("zoo")),
+            ..Default::default()
+        };
+
+        let expected = vec![
+            "not ok 1 Panda",
+            "  ---",
+            "  at:",
+            "    file: src/zoo.rs",
+            "    line: 12",
+            "    column: 5",
+            "    module: zoo",
+            "  ...",
+        ];
+        let lines = tap_test.tap_with_version(1, TapVersion::Tap13);
+        assert_eq!(expected, lines);
+
+        let mut parsed = TapTest::default();
+        parsed.apply_yaml(&tap_yaml::parse_block(&lines[2..7]));
+        assert_eq!(parsed.location, tap_test.location);
+        assert!(parsed.yaml.is_empty());
+    }
+
+    #[test]
+    fn test_tap_lines_with_comparison() {
+        let tap_test = TapTest {
+            name: "Panda".to_string(),
+            comparison: Some(TapComparison::equal("Panda\nTiger", "Panda\nLion")),
+            ..Default::default()
+        };
+
+        let expected_classic = vec![
+            "not ok 1 Panda",
+            "#        got: Panda",
+            "#             Tiger",
+            "#   expected: Panda",
+            "#             Lion",
+            "#   operator: ==",
+            "#       diff:",
+            "#       Panda",
+            "#     - Lion",
+            "#     + Tiger",
+        ];
+        assert_eq!(expected_classic, tap_test.tap(1));
+
+        let expected_yaml = vec![
+            "not ok 1 Panda",
+            "  ---",
+            "  got: |-",
+            "    Panda",
+            "    Tiger",
+            "  expected: |-",
+            "    Panda",
+            "    Lion",
+            "  operator: ==",
+            "  diff: |2-",
+            "      Panda",
+            "    - Lion",
+            "    + Tiger",
+            "  ...",
+        ];
+        let lines = tap_test.tap_with_version(1, TapVersion::Tap14);
+        assert_eq!(expected_yaml, lines);
+
+        let mut parsed = TapTest::default();
+        parsed.apply_yaml(&tap_yaml::parse_block(&lines[2..13]));
+        assert_eq!(parsed.comparison, tap_test.comparison);
+        assert!(parsed.yaml.is_empty());
+    }
+
+    fn tap_test_with_duration() -> TapTest {
+        TapTest {
+            duration: Some(Duration::from_micros(1_500)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tap_lines_with_subtests() {
+        let tap_test = TapTest {
+            name: "Zoo".to_string(),
+            passed: false,
+            subtests: vec![
+                TapTest {
+                    name: "Panda".to_string(),
+                    passed: true,
+                    ..Default::default()
+                },
+                TapTest {
+                    name: "Tiger".to_string(),
+                    passed: false,
+                    diagnostics: vec!["Escaped".to_string()],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let expected = vec![
+            "    # Subtest: Zoo",
+            "    1..2",
+            "    ok 1 Panda",
+            "    not ok 2 Tiger",
+            "    # Escaped",
+            "not ok 3 Zoo",
+        ];
+
+        assert_eq!(expected, tap_test.tap(3));
+    }
+
+    #[test]
+    fn test_status_line_with_directive() {
+        let skipped = TapTest {
+            name: "Panda".to_string(),
+            passed: true,
+            directive: Some(TapDirective::Skip("No bamboo".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(skipped.status_line(1), "ok 1 Panda # SKIP No bamboo");
+        assert!(skipped.is_skipped());
+
+        let todo = TapTest {
+            name: "Tiger".to_string(),
+            passed: false,
+            directive: Some(TapDirective::Todo(String::new())),
+            ..Default::default()
+        };
+        assert_eq!(todo.status_line(2), "not ok 2 Tiger # TODO");
+        assert!(!todo.is_failure());
+    }
+
+    #[test]
+    fn test_status_line_escapes_name() {
+        let tap_test = TapTest {
+            name: "Issue #12 in C:\\zoo".to_string(),
+            passed: true,
+            directive: Some(TapDirective::Skip("No bamboo".to_string())),
+            ..Default::default()
+        };
+        let line = tap_test.status_line(1);
+        assert_eq!(line, "ok 1 Issue \\#12 in C:\\\\zoo # SKIP No bamboo");
+
+        let parsed = TapParser::new().parse_suite("Zoo", &line);
+        assert_eq!(parsed.tests, vec![tap_test]);
+        let parsed = TapParser::new().parse_suite("Zoo", "ok 1 Panda \\# skip");
+        assert_eq!(parsed.tests[0].name, "Panda # skip");
+        assert_eq!(parsed.tests[0].directive, None);
+    }
 }
@@ -2,12 +2,21 @@
 
 #[cfg(feature = "alloc")]
 use alloc::{
+    collections::{BTreeMap, BTreeSet},
     string::{String, ToString},
     vec::Vec,
 };
-use core::{default::Default, option::Option};
+use core::{default::Default, option::Option, time::Duration};
+#[cfg(feature = "std")]
+use std::collections::{BTreeMap, BTreeSet};
+#[cfg(feature = "std")]
+use std::time::Instant;
 
+use crate::tap_comparison::TapComparison;
+use crate::tap_directive::TapDirective;
+use crate::tap_location::TapLocation;
 use crate::tap_test::TapTest;
+use crate::tap_yaml::YamlValue;
 
 /// Coordinator for construction of `TapTest` objects using the builder pattern.
 ///
@@ -27,6 +36,16 @@ pub struct TapTestBuilder {
     name: Option<String>,
     passed: Option<bool>,
     diagnostics: Option<Vec<String>>,
+    duration: Option<Duration>,
+    location: Option<TapLocation>,
+    comparison: Option<TapComparison>,
+    subtests: Option<Vec<TapTest>>,
+    directive: Option<TapDirective>,
+    tags: BTreeSet<String>,
+    metadata: BTreeMap<String, String>,
+    yaml: Vec<(String, YamlValue)>,
+    #[cfg(feature = "std")]
+    started: Option<Instant>,
 }
 
 impl TapTestBuilder {
@@ -36,6 +55,16 @@ impl TapTestBuilder {
             name: None,
             passed: None,
             diagnostics: None,
+            duration: None,
+            location: None,
+            comparison: None,
+            subtests: None,
+            directive: None,
+            tags: BTreeSet::new(),
+            metadata: BTreeMap::new(),
+            yaml: Vec::new(),
+            #[cfg(feature = "std")]
+            started: None,
         }
     }
     /// Set test name
@@ -53,8 +82,80 @@ impl TapTestBuilder {
         self.diagnostics = Some(comments.iter().map(|s| String::from(*s)).collect());
         self
     }
+    /// Set how long the test took to run
+    pub fn duration(&mut self, duration: Duration) -> &mut TapTestBuilder {
+        self.duration = Some(duration);
+        self
+    }
+    /// Set where the test lives in the source
+    pub fn location(&mut self, location: TapLocation) -> &mut TapTestBuilder {
+        self.location = Some(location);
+        self
+    }
+    /// Set the test's location to wherever this is called from. Called from a `#[track_caller]` function, that function's caller is used instead.
+    #[track_caller]
+    pub fn here(&mut self) -> &mut TapTestBuilder {
+        self.location(TapLocation::caller())
+    }
+    /// Set what a failing assertion got and expected
+    pub fn comparison(&mut self, comparison: TapComparison) -> &mut TapTestBuilder {
+        self.comparison = Some(comparison);
+        self
+    }
+    /// Set the tests nested under this one
+    pub fn subtests(&mut self, tests: Vec<TapTest>) -> &mut TapTestBuilder {
+        self.subtests = Some(tests);
+        self
+    }
+    /// Set a SKIP or TODO directive
+    pub fn directive(&mut self, directive: TapDirective) -> &mut TapTestBuilder {
+        self.directive = Some(directive);
+        self
+    }
+    /// Mark the test as skipped, for the given reason
+    pub fn skip<S: Into<String>>(&mut self, reason: S) -> &mut TapTestBuilder {
+        self.directive(TapDirective::Skip(reason.into()))
+    }
+    /// Mark the test as not done yet, for the given reason
+    pub fn todo<S: Into<String>>(&mut self, reason: S) -> &mut TapTestBuilder {
+        self.directive(TapDirective::Todo(reason.into()))
+    }
+    /// Tag the test. Can be called any number of times.
+    pub fn tag<S: Into<String>>(&mut self, tag: S) -> &mut TapTestBuilder {
+        self.tags.insert(tag.into());
+        self
+    }
+    /// Tag the test with each of these
+    pub fn tags(&mut self, tags: &[&str]) -> &mut TapTestBuilder {
+        self.tags.extend(tags.iter().map(|tag| String::from(*tag)));
+        self
+    }
+    /// Attach a piece of metadata, replacing any already under `key`. Can be called any number of times.
+    pub fn metadata<K: Into<String>, V: Into<String>>(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> &mut TapTestBuilder {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+    /// Add a structured diagnostic for the YAML block. Can be called any number of times.
+    pub fn yaml_field<S: Into<String>>(&mut self, key: S, value: YamlValue) -> &mut TapTestBuilder {
+        self.yaml.push((key.into(), value));
+        self
+    }
+    /// Start the clock. Unless a duration is set outright, the time from here until `finalize` becomes the test's duration.
+    #[cfg(feature = "std")]
+    pub fn start_timer(&mut self) -> &mut TapTestBuilder {
+        self.started = Some(Instant::now());
+        self
+    }
     /// Produce the configured `TapTest` object. Panics if you don't pass a passed status.
     pub fn finalize(&mut self) -> TapTest {
+        #[cfg(feature = "std")]
+        if self.duration.is_none() {
+            self.duration = self.started.take().map(|started| started.elapsed());
+        }
         TapTest {
             name: self
                 .name
@@ -65,6 +166,14 @@ impl TapTestBuilder {
                 .take()
                 .expect("You build a test but didn't say whether or not it passed"),
             diagnostics: self.diagnostics.take().unwrap_or_default(),
+            duration: self.duration.take(),
+            location: self.location.take(),
+            comparison: self.comparison.take(),
+            subtests: self.subtests.take().unwrap_or_default(),
+            directive: self.directive.take(),
+            tags: core::mem::take(&mut self.tags),
+            metadata: core::mem::take(&mut self.metadata),
+            yaml: core::mem::take(&mut self.yaml),
         }
     }
 }
@@ -74,18 +183,21 @@ mod tests {
     use super::TapTestBuilder;
     use crate::tap_test::TapTest;
 
+    use std::time::Duration;
+
     #[test]
     fn test_tap_test_builder() {
         let tap_test_from_builder = TapTestBuilder::new()
             .name("Panda")
             .passed(true)
-            .diagnostics(&vec!["Doing fine"])
+            .diagnostics(&["Doing fine"])
             .finalize();
 
         let tap_test_from_scratch = TapTest {
             name: "Panda".to_string(),
             passed: true,
             diagnostics: vec!["Doing fine".to_string()],
+            ..Default::default()
         };
 
         assert_eq!(tap_test_from_builder, tap_test_from_scratch);
@@ -99,6 +211,22 @@ mod tests {
         assert_eq!(bad_tap_test.name, expected);
     }
 
+    #[test]
+    fn test_tap_test_builder_timer() {
+        let explicit = TapTestBuilder::new()
+            .passed(true)
+            .start_timer()
+            .duration(Duration::from_secs(3))
+            .finalize();
+        assert_eq!(explicit.duration, Some(Duration::from_secs(3)));
+
+        let timed = TapTestBuilder::new().passed(true).start_timer().finalize();
+        assert!(timed.duration.is_some());
+
+        let untimed = TapTestBuilder::new().passed(true).finalize();
+        assert_eq!(untimed.duration, None);
+    }
+
     #[test]
     #[should_panic]
     fn test_tap_test_builder_with_no_passed_status() {
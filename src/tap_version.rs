@@ -0,0 +1,66 @@
+//! `TapVersion` -- Which revision of the TAP specification a stream is written against
+
+#[cfg(feature = "alloc")]
+use alloc::{format, string::String};
+
+/// The flavor of TAP to produce. Anything from TAP 13 on announces itself with a version line and may carry YAML blocks of structured diagnostics; plain, versionless TAP has to make do with # comments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum TapVersion {
+    /// Classic TAP, as understood by every consumer. No version line is written.
+    #[default]
+    Tap12,
+    /// TAP 13, which introduced YAML diagnostic blocks
+    Tap13,
+    /// TAP 14, which formalized subtests
+    Tap14,
+}
+
+impl TapVersion {
+    /// Map a version number to a `TapVersion`. Anything before 13 is classic TAP; anything after 14 is read as 14.
+    pub fn from_number(number: u32) -> TapVersion {
+        match number {
+            0..=12 => TapVersion::Tap12,
+            13 => TapVersion::Tap13,
+            _ => TapVersion::Tap14,
+        }
+    }
+
+    /// The version number, as it would appear in the version line
+    pub fn number(&self) -> u32 {
+        match self {
+            TapVersion::Tap12 => 12,
+            TapVersion::Tap13 => 13,
+            TapVersion::Tap14 => 14,
+        }
+    }
+
+    /// Produce the "TAP version N" line which must lead the stream, if this version has one.
+    pub fn version_line(&self) -> Option<String> {
+        match self {
+            TapVersion::Tap12 => None,
+            _ => Some(format!("TAP version {}", self.number())),
+        }
+    }
+
+    /// Can structured diagnostics be written as YAML blocks?
+    pub fn supports_yaml(&self) -> bool {
+        *self >= TapVersion::Tap13
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TapVersion;
+
+    #[test]
+    fn test_version_line() {
+        assert_eq!(TapVersion::Tap12.version_line(), None);
+        assert_eq!(
+            TapVersion::Tap13.version_line(),
+            Some("TAP version 13".to_string())
+        );
+        assert_eq!(TapVersion::from_number(14), TapVersion::Tap14);
+        assert!(!TapVersion::default().supports_yaml());
+        assert!(TapVersion::Tap14.supports_yaml());
+    }
+}
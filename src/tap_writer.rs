@@ -1,6 +1,12 @@
 //! `TapWriter` -- For writing TAP streams incrementally
 
+use std::time::Instant;
+
 use super::{NOT_OK_SYMBOL, OK_SYMBOL};
+use crate::tap_panic;
+use crate::tap_test::TapTest;
+use crate::tap_test_builder::TapTestBuilder;
+use crate::tap_version::TapVersion;
 
 /// A named TAP stream writer. This will print directly to STDOUT as you call methods. No waiting.
 /// See examples/stream.rs for usage.
@@ -8,13 +14,28 @@ use super::{NOT_OK_SYMBOL, OK_SYMBOL};
 pub struct TapWriter {
     /// TAP stream name
     pub name: String,
+    /// The version of TAP being written, which decides how structured data about tests comes out
+    pub version: TapVersion,
 }
 
 impl TapWriter {
     /// Make me a new one from a name. Don't leave the name blank as it improves clarity.
     pub fn new(name: &str) -> TapWriter {
+        TapWriter::with_version(name, TapVersion::default())
+    }
+
+    /// Make me a new one which writes a particular version of TAP.
+    pub fn with_version(name: &str, version: TapVersion) -> TapWriter {
         TapWriter {
             name: name.to_string(),
+            version,
+        }
+    }
+
+    /// Print the "TAP version N" line, if the version has one. This must be the very first thing in the stream.
+    pub fn version(&self) {
+        if let Some(line) = self.version.version_line() {
+            println!("{}", line);
         }
     }
 
@@ -40,6 +61,47 @@ impl TapWriter {
         println!("{} {} {}", NOT_OK_SYMBOL, test_number, message);
     }
 
+    /// Emit a complete `TapTest`: its test line along with its diagnostics and any structured data.
+    pub fn test(&self, test_number: i32, test: &TapTest) {
+        for line in test.tap_with_version(test_number as i64, self.version) {
+            println!("{}", line);
+        }
+    }
+
+    /// Run `check`, timing it, and emit the result as a test, which is also returned. The check says whether it passed.
+    pub fn timed<F: FnOnce() -> bool>(&self, test_number: i32, message: &str, check: F) -> TapTest {
+        let started = Instant::now();
+        let passed = check();
+        let tap_test = TapTestBuilder::new()
+            .name(message)
+            .passed(passed)
+            .duration(started.elapsed())
+            .finalize();
+        self.test(test_number, &tap_test);
+        tap_test
+    }
+
+    /// Like `timed`, but a panic in `check` is caught and emitted as a failing test, with the panic's message and location as diagnostics, so the stream carries on with the next test.
+    pub fn guarded<F: FnOnce() -> bool>(
+        &self,
+        test_number: i32,
+        message: &str,
+        check: F,
+    ) -> TapTest {
+        let tap_test = tap_panic::test_caught(message, check);
+        self.test(test_number, &tap_test);
+        tap_test
+    }
+
+    /// Emit a `TapTest` whose subtests are out already, having been printed as they ran: just its test line and what comes after it.
+    pub fn test_after_subtests(&self, test_number: i32, test: &TapTest) {
+        let lines = test.tap_with_version(test_number as i64, self.version);
+        let emitted = test.subtest_lines(self.version).len();
+        for line in &lines[emitted..] {
+            println!("{}", line);
+        }
+    }
+
     /// Emit a diagnostic message. Prefaced with a #.
     pub fn diagnostic(&self, message: &str) {
         println!("# {}", message);
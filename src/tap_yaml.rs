@@ -0,0 +1,484 @@
+//! `YamlValue` -- Just enough YAML to read and write the structured diagnostic blocks of TAP 13 and up
+
+#[cfg(feature = "alloc")]
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt::Write;
+
+/// A value in a YAML diagnostic block. Everything is kept as text; YAML's typing of scalars is left to whoever reads the block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum YamlValue {
+    /// A string, number or similar. Multi-line strings are written as literal blocks.
+    Scalar(String),
+    /// A sequence of values
+    List(Vec<YamlValue>),
+    /// A mapping, in insertion order
+    Map(Vec<(String, YamlValue)>),
+}
+
+impl YamlValue {
+    /// Make a scalar from anything that can be turned into a `String`
+    pub fn scalar<S: Into<String>>(s: S) -> YamlValue {
+        YamlValue::Scalar(s.into())
+    }
+
+    /// Borrow the text of a scalar
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            YamlValue::Scalar(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Look up a key, if this is a mapping
+    pub fn get(&self, key: &str) -> Option<&YamlValue> {
+        match self {
+            YamlValue::Map(entries) => lookup(entries, key),
+            _ => None,
+        }
+    }
+}
+
+/// Find the value for `key` among the entries of a block or mapping.
+pub fn lookup<'a>(entries: &'a [(String, YamlValue)], key: &str) -> Option<&'a YamlValue> {
+    entries
+        .iter()
+        .find(|(entry_key, _)| entry_key == key)
+        .map(|(_, value)| value)
+}
+
+/// Produce the lines of a YAML block, from the "---" through the "...", indented by two spaces so that it sits under a test line.
+pub fn render_block(entries: &[(String, YamlValue)]) -> Vec<String> {
+    let mut lines = vec!["  ---".to_string()];
+    render_into(&mut lines, entries, 2);
+    lines.push("  ...".to_string());
+    lines
+}
+
+/// Read the lines between a block's "---" and "..." markers back into entries. Only the subset of YAML written by `render_block` (plus inline list mappings) is understood; anything else is skipped.
+pub fn parse_block<S: AsRef<str>>(lines: &[S]) -> Vec<(String, YamlValue)> {
+    let lines = lines
+        .iter()
+        .map(|line| line.as_ref())
+        .collect::<Vec<&str>>();
+    let mut parser = Parser { lines, position: 0 };
+    let indent = parser.next_indent().unwrap_or(0);
+    parser.parse_map(indent)
+}
+
+/// Produce the lines of YAML for `entries` alone, without block markers, indented by `indent` spaces.
+pub fn render_entries(entries: &[(String, YamlValue)], indent: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    render_into(&mut lines, entries, indent);
+    lines
+}
+
+fn render_into(lines: &mut Vec<String>, entries: &[(String, YamlValue)], indent: usize) {
+    for (key, value) in entries {
+        let prefix = format!("{:indent$}{}:", "", quote(key), indent = indent);
+        render_value(lines, prefix, value, indent);
+    }
+}
+
+fn render_value(lines: &mut Vec<String>, prefix: String, value: &YamlValue, indent: usize) {
+    match value {
+        YamlValue::Scalar(s) if s.contains('\n') => {
+            let body = s.strip_suffix('\n').unwrap_or(s);
+            let chomp = if body.len() == s.len() {
+                "-"
+            } else if body.ends_with('\n') || body.is_empty() {
+                "+"
+            } else {
+                ""
+            };
+            // Otherwise the indentation would be read off the first line, spaces and all
+            let first = body.split('\n').find(|line| !line.is_empty());
+            let indentation = if first.is_some_and(|line| line.starts_with(char::is_whitespace)) {
+                "2"
+            } else {
+                ""
+            };
+            lines.push(format!("{} |{}{}", prefix, indentation, chomp));
+            for line in body.split('\n') {
+                if line.is_empty() {
+                    lines.push(String::new());
+                } else {
+                    lines.push(format!("{:indent$}{}", "", line, indent = indent + 2));
+                }
+            }
+        }
+        YamlValue::Scalar(s) => lines.push(format!("{} {}", prefix, quote(s))),
+        YamlValue::List(items) if items.is_empty() => lines.push(format!("{} []", prefix)),
+        YamlValue::List(items) => {
+            lines.push(prefix);
+            for item in items {
+                let item_prefix = format!("{:indent$}-", "", indent = indent + 2);
+                render_value(lines, item_prefix, item, indent + 2);
+            }
+        }
+        YamlValue::Map(entries) if entries.is_empty() => lines.push(format!("{} {{}}", prefix)),
+        YamlValue::Map(entries) => {
+            lines.push(prefix);
+            render_into(lines, entries, indent + 2);
+        }
+    }
+}
+
+/// Write a scalar plainly when that's unambiguous, double-quoted otherwise.
+fn quote(s: &str) -> String {
+    let plain = !s.is_empty()
+        && s.trim() == s
+        && !s.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        && !s.ends_with(':')
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.contains(|c: char| c.is_control());
+
+    if plain {
+        return s.to_string();
+    }
+
+    let mut buf = String::with_capacity(s.len() + 2);
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if c.is_control() => write!(&mut buf, "\\u{:04x}", c as u32).unwrap(),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+    buf
+}
+
+fn unquote(s: &str) -> String {
+    let s = s.trim();
+    if s.len() >= 2 && s.starts_with('\'') && s.ends_with('\'') {
+        return s[1..s.len() - 1].replace("''", "'");
+    }
+    if s.len() < 2 || !s.starts_with('"') || !s.ends_with('"') {
+        return s.to_string();
+    }
+
+    let mut buf = String::with_capacity(s.len());
+    let mut chars = s[1..s.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            buf.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => buf.push('\n'),
+            Some('r') => buf.push('\r'),
+            Some('t') => buf.push('\t'),
+            Some('u') => {
+                let hex = chars.by_ref().take(4).collect::<String>();
+                if let Some(c) = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    buf.push(c);
+                }
+            }
+            Some(c) => buf.push(c),
+            None => buf.push('\\'),
+        }
+    }
+    buf
+}
+
+/// Split "key: value" into its parts. A key alone on its line has an empty value.
+fn split_entry(content: &str) -> Option<(String, &str)> {
+    let (key, rest) = if let Some(quoted) = content.strip_prefix('"') {
+        let end = quoted.find("\":").map(|end| end + 2)?;
+        (&content[..end], &content[end + 1..])
+    } else if let Some(key) = content.strip_suffix(':') {
+        (key, "")
+    } else {
+        content.split_once(": ")?
+    };
+    Some((unquote(key), rest.trim()))
+}
+
+pub(crate) fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+struct Parser<'a> {
+    lines: Vec<&'a str>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    /// Skip blank lines and report the indentation of the next one with content.
+    fn next_indent(&mut self) -> Option<usize> {
+        while self.position < self.lines.len() && self.lines[self.position].trim().is_empty() {
+            self.position += 1;
+        }
+        self.lines.get(self.position).map(|line| indent_of(line))
+    }
+
+    fn parse_map(&mut self, indent: usize) -> Vec<(String, YamlValue)> {
+        let mut entries = Vec::new();
+        while let Some(line_indent) = self.next_indent() {
+            let content = self.lines[self.position].trim();
+            if line_indent < indent || (line_indent == indent && content.starts_with('-')) {
+                break;
+            }
+            self.position += 1;
+            if line_indent > indent {
+                continue;
+            }
+            if let Some((key, rest)) = split_entry(content) {
+                let value = self.parse_value(rest, indent);
+                entries.push((key, value));
+            }
+        }
+        entries
+    }
+
+    fn parse_list(&mut self, indent: usize) -> Vec<YamlValue> {
+        let mut items = Vec::new();
+        while let Some(line_indent) = self.next_indent() {
+            let content = self.lines[self.position].trim();
+            if line_indent != indent || !(content == "-" || content.starts_with("- ")) {
+                break;
+            }
+            self.position += 1;
+            let rest = content[1..].trim();
+            let is_inline_map =
+                !rest.starts_with('"') && (rest.contains(": ") || rest.ends_with(':'));
+            if is_inline_map {
+                // "- key: value" starts a mapping whose other keys line up with the first
+                let mut entries = Vec::new();
+                if let Some((key, rest)) = split_entry(rest) {
+                    let value = self.parse_value(rest, indent + 2);
+                    entries.push((key, value));
+                }
+                entries.extend(self.parse_map(indent + 2));
+                items.push(YamlValue::Map(entries));
+            } else {
+                items.push(self.parse_value(rest, indent));
+            }
+        }
+        items
+    }
+
+    fn parse_value(&mut self, rest: &str, indent: usize) -> YamlValue {
+        match rest {
+            _ if is_literal_header(rest) => YamlValue::Scalar(self.parse_literal(rest, indent)),
+            "[]" => YamlValue::List(Vec::new()),
+            "{}" => YamlValue::Map(Vec::new()),
+            "" => {
+                let start = self.position;
+                match self.next_indent() {
+                    Some(child) if child > indent || (child == indent && self.is_list_item()) => {
+                        if self.is_list_item() {
+                            YamlValue::List(self.parse_list(child))
+                        } else {
+                            YamlValue::Map(self.parse_map(child))
+                        }
+                    }
+                    _ => {
+                        self.position = start;
+                        YamlValue::Scalar(String::new())
+                    }
+                }
+            }
+            _ => YamlValue::Scalar(unquote(rest)),
+        }
+    }
+
+    fn is_list_item(&self) -> bool {
+        let content = self.lines[self.position].trim();
+        content == "-" || content.starts_with("- ")
+    }
+
+    /// Read a literal block scalar, given its header (like "|", "|-" or "|2+") and the indentation of the line it's on
+    fn parse_literal(&mut self, header: &str, indent: usize) -> String {
+        let indicators = &header[1..];
+        let keep = indicators.contains('+');
+        let strip = indicators.contains('-');
+        let mut block_indent = indicators
+            .chars()
+            .find_map(|c| c.to_digit(10))
+            .map(|n| indent + n as usize);
+
+        let mut body: Vec<&str> = Vec::new();
+        while self.position < self.lines.len() {
+            let line = self.lines[self.position];
+            if line.trim().is_empty() {
+                // Spaces beyond the block's indentation belong to the text
+                body.push(line);
+                self.position += 1;
+                continue;
+            }
+            let line_indent = indent_of(line);
+            if line_indent <= indent || block_indent.is_some_and(|block| line_indent < block) {
+                break;
+            }
+            block_indent.get_or_insert(line_indent);
+            body.push(line);
+            self.position += 1;
+        }
+        let block_indent = block_indent.unwrap_or(indent + 2);
+        let mut body = body
+            .into_iter()
+            .map(|line| line.get(block_indent..).unwrap_or(""))
+            .collect::<Vec<&str>>();
+
+        if !keep {
+            // Trailing blank lines were most likely the gap before the next key
+            let kept = body.len() - body.iter().rev().take_while(|line| line.is_empty()).count();
+            self.position -= body.len() - kept;
+            body.truncate(kept);
+        }
+
+        let mut text = body.join("\n");
+        if !strip {
+            text.push('\n');
+        }
+        text
+    }
+}
+
+/// Is this the header of a literal block scalar, like "|", "|-" or "|2+"?
+fn is_literal_header(rest: &str) -> bool {
+    rest.strip_prefix('|').is_some_and(|indicators| {
+        indicators.len() <= 2
+            && indicators
+                .chars()
+                .all(|c| c == '-' || c == '+' || ('1'..='9').contains(&c))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_block, render_block, YamlValue};
+
+    fn example_entries() -> Vec<(String, YamlValue)> {
+        vec![
+            ("duration_ms".to_string(), YamlValue::scalar("12.345")),
+            (
+                "message".to_string(),
+                YamlValue::scalar("Panda: \"hungry\""),
+            ),
+            (
+                "output".to_string(),
+                YamlValue::scalar("first line\n\nthird line"),
+            ),
+            (
+                "at".to_string(),
+                YamlValue::Map(vec![
+                    ("file".to_string(), YamlValue::scalar("src/lib.rs")),
+                    ("line".to_string(), YamlValue::scalar("42")),
+                ]),
+            ),
+            (
+                "tags".to_string(),
+                YamlValue::List(vec![YamlValue::scalar("slow"), YamlValue::scalar("#1")]),
+            ),
+            ("empty".to_string(), YamlValue::List(vec![])),
+        ]
+    }
+
+    #[test]
+    fn test_render_block() {
+        let expected = vec![
+            "  ---",
+            "  duration_ms: 12.345",
+            "  message: \"Panda: \\\"hungry\\\"\"",
+            "  output: |-",
+            "    first line",
+            "",
+            "    third line",
+            "  at:",
+            "    file: src/lib.rs",
+            "    line: 42",
+            "  tags:",
+            "    - slow",
+            "    - \"#1\"",
+            "  empty: []",
+            "  ...",
+        ];
+
+        assert_eq!(expected, render_block(&example_entries()));
+    }
+
+    #[test]
+    fn test_parse_block_round_trip() {
+        let lines = render_block(&example_entries());
+        let parsed = parse_block(&lines[1..lines.len() - 1]);
+
+        assert_eq!(example_entries(), parsed);
+    }
+
+    #[test]
+    fn test_literal_round_trip() {
+        let texts = [
+            "  indented\nbar",
+            "\n  first line blank",
+            "\n\nbar\n",
+            "foo\n   \nbar  ",
+            "trailing blank lines\n\n\n",
+            "\n",
+            "  ...\n---",
+        ];
+        for text in texts {
+            let entries = vec![
+                ("output".to_string(), YamlValue::scalar(text)),
+                (
+                    "list".to_string(),
+                    YamlValue::List(vec![YamlValue::scalar(text)]),
+                ),
+                ("after".to_string(), YamlValue::scalar("end")),
+            ];
+            let lines = render_block(&entries);
+
+            assert_eq!(
+                entries,
+                parse_block(&lines[1..lines.len() - 1]),
+                "{:?}",
+                lines
+            );
+        }
+
+        let lines = render_block(&[("output".to_string(), YamlValue::scalar("  a\nb"))]);
+        assert_eq!(lines[1..3], ["  output: |2-", "      a"]);
+    }
+
+    #[test]
+    fn test_parse_inline_list_mappings() {
+        let lines = [
+            "  failures:",
+            "  - message: boom",
+            "    line: 7",
+            "  - message: 'it''s broken'",
+            "  done: true",
+        ];
+
+        let expected = vec![
+            (
+                "failures".to_string(),
+                YamlValue::List(vec![
+                    YamlValue::Map(vec![
+                        ("message".to_string(), YamlValue::scalar("boom")),
+                        ("line".to_string(), YamlValue::scalar("7")),
+                    ]),
+                    YamlValue::Map(vec![(
+                        "message".to_string(),
+                        YamlValue::scalar("it's broken"),
+                    )]),
+                ]),
+            ),
+            ("done".to_string(), YamlValue::scalar("true")),
+        ];
+
+        assert_eq!(expected, parse_block(&lines));
+    }
+}